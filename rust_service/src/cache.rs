@@ -1,25 +1,283 @@
 use crate::errors::AppError;
-use serde::Deserialize;
-use sled::Db;
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
+use crate::providers::TokenUsage;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use sled::transaction::{ConflictableTransactionResult, Transactional};
+use sled::{Db, IVec, Tree};
+use std::collections::BTreeSet;
 use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
 use std::time::{SystemTime, UNIX_EPOCH};
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
+use unicode_normalization::UnicodeNormalization;
 
 /// Cache configuration options
 #[derive(Debug, Clone, Deserialize)]
 pub struct CacheConfig {
     pub enabled: bool,
     pub ttl_days: u64,
+    /// Budget for the entries (keys and values); the least recently used
+    /// entries are evicted beyond it. 0 means no limit.
     pub max_size_mb: u64,
+    /// Remove entries produced with other backend endpoints or `llm_params`
+    /// than the current config on startup
+    #[serde(default)]
+    pub invalidate_changed_params: bool,
+    /// How the request text is normalised for the cache key, so that small
+    /// differences in the selection still hit
+    #[serde(default = "default_normalize")]
+    pub normalize: Vec<KeyNormalization>,
+}
+
+pub(crate) fn default_normalize() -> Vec<KeyNormalization> {
+    vec![KeyNormalization::Trim, KeyNormalization::Nfc]
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            enabled: true,
+            ttl_days: 30,
+            max_size_mb: 100,
+            invalidate_changed_params: false,
+            normalize: default_normalize(),
+        }
+    }
+}
+
+/// One normalisation of the request text before it is used as the cache key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyNormalization {
+    /// Ignore leading and trailing whitespace; the cached response gets the
+    /// outer whitespace of the request
+    Trim,
+    /// Treat a run of spaces as one space, and a run of blank lines as one
+    /// blank line
+    CollapseWhitespace,
+    /// Compose characters (Unicode NFC), so that "é" typed as "e" plus an
+    /// accent matches "é"
+    Nfc,
+    /// Treat curly quotes and apostrophes as straight ones
+    FoldQuotes,
+}
+
+impl CacheConfig {
+    /// The request text as it is keyed, after the `normalize` steps in order
+    pub fn normalize_key(&self, text: &str) -> String {
+        let mut key = text.to_string();
+        for step in &self.normalize {
+            key = match step {
+                KeyNormalization::Trim => key.trim().to_string(),
+                KeyNormalization::CollapseWhitespace => collapse_whitespace(&key),
+                KeyNormalization::Nfc => key.nfc().collect(),
+                KeyNormalization::FoldQuotes => key.chars().map(fold_quote).collect(),
+            };
+        }
+        key
+    }
+}
+
+/// Runs of whitespace as a single space, line break or blank line, depending
+/// on how many line breaks they hold
+fn collapse_whitespace(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if !c.is_whitespace() {
+            collapsed.push(c);
+            continue;
+        }
+        let mut breaks = usize::from(c == '\n');
+        while let Some(next) = chars.next_if(|next| next.is_whitespace()) {
+            breaks += usize::from(next == '\n');
+        }
+        collapsed.push_str(match breaks {
+            0 => " ",
+            1 => "\n",
+            _ => "\n\n",
+        });
+    }
+    collapsed
+}
+
+fn fold_quote(c: char) -> char {
+    match c {
+        '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}' => '\'',
+        '\u{201C}' | '\u{201D}' | '\u{201E}' | '\u{201F}' => '"',
+        other => other,
+    }
+}
+
+/// Leading and trailing whitespace of `text`
+fn outer_whitespace(text: &str) -> (&str, &str) {
+    let trimmed = text.trim_start();
+    let leading = &text[..text.len() - trimmed.len()];
+    let trailing = &trimmed[trimmed.trim_end().len()..];
+    (leading, trailing)
+}
+
+/// Name of the sled tree holding the last access time and size of each entry
+const ACCESS_TREE: &str = "access";
+
+/// Name of the tree holding the total size of the entries, under `TOTAL_KEY`
+const SIZE_TREE: &str = "size";
+const TOTAL_KEY: &[u8] = b"total";
+
+fn decode_total(bytes: Option<&[u8]>) -> u64 {
+    bytes.and_then(|bytes| bytes.try_into().ok()).map_or(0, u64::from_be_bytes)
+}
+
+/// Last access time and size of a cache entry, stored next to it
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct AccessRecord {
+    /// Microseconds since the epoch, strictly increasing within a process
+    last_access: u64,
+    /// Bytes taken by the key and the value
+    size: u64,
+}
+
+impl AccessRecord {
+    fn to_bytes(self) -> [u8; 16] {
+        let mut bytes = [0; 16];
+        bytes[..8].copy_from_slice(&self.last_access.to_be_bytes());
+        bytes[8..].copy_from_slice(&self.size.to_be_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes: [u8; 16] = bytes.try_into().ok()?;
+        Some(Self {
+            last_access: u64::from_be_bytes(bytes[..8].try_into().ok()?),
+            size: u64::from_be_bytes(bytes[8..].try_into().ok()?),
+        })
+    }
+}
+
+/// Version of the cache entry encoding, stored as the first byte of each entry
+///
+/// Version 1 was the unversioned `response|created_at|expires_at` text,
+/// versions 1 and 2 were keyed with a 64-bit `DefaultHasher` hash, and
+/// version 3 with `llm_params` and the endpoint as written in the config.
+const ENTRY_VERSION: u8 = 4;
+
+/// SHA-256 over a sequence of named fields
+///
+/// Each field is length-prefixed, so no two different sequences share an
+/// encoding, and the result does not depend on the Rust version.
+pub struct Fingerprint(Sha256);
+
+impl Fingerprint {
+    pub fn new() -> Self {
+        Self(Sha256::new())
+    }
+
+    pub fn field(&mut self, name: &str, value: impl AsRef<[u8]>) -> &mut Self {
+        for part in [name.as_bytes(), value.as_ref()] {
+            self.0.update((part.len() as u64).to_be_bytes());
+            self.0.update(part);
+        }
+        self
+    }
+
+    fn digest(self) -> Vec<u8> {
+        self.0.finalize().to_vec()
+    }
+
+    /// The digest in hex
+    pub fn finish(self) -> String {
+        format!("{:x}", self.0.finalize())
+    }
+}
+
+impl Default for Fingerprint {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `value` as JSON with object keys sorted and whole numbers written without a
+/// fraction, so that equivalent parameters always encode the same way
+///
+/// `temperature = 1` and `temperature = 1.0` both become `{"temperature":1}`.
+pub fn canonical_json(value: &Value) -> String {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by_key(|(key, _)| key.as_str());
+            let fields: Vec<String> = entries
+                .into_iter()
+                .map(|(key, value)| format!("{}:{}", Value::from(key.as_str()), canonical_json(value)))
+                .collect();
+            format!("{{{}}}", fields.join(","))
+        }
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(canonical_json).collect();
+            format!("[{}]", items.join(","))
+        }
+        Value::Number(number) => match number.as_f64() {
+            // Exactly representable, so the conversion is lossless
+            Some(float) if number.is_f64() && float.fract() == 0.0 && float.abs() < 9_007_199_254_740_992.0 => {
+                (float as i64).to_string()
+            }
+            _ => number.to_string(),
+        },
+        other => other.to_string(),
+    }
+}
+
+/// `url` with the scheme and host lowercased, the default port and a trailing
+/// slash dropped, so that two spellings of one endpoint match
+pub fn canonical_url(url: &str) -> String {
+    let url = url.trim();
+    match reqwest::Url::parse(url) {
+        Ok(parsed) => parsed.as_str().trim_end_matches('/').to_string(),
+        Err(_) => url.trim_end_matches('/').to_string(),
+    }
+}
+
+/// Hex SHA-256 of a request text, stored with its entry to detect key collisions
+fn text_hash(text: &str) -> String {
+    format!("{:x}", Sha256::digest(text.as_bytes()))
+}
+
+/// Where a cached response came from
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EntryMetadata {
+    /// Model that produced the response; empty for migrated entries
+    pub model: String,
+    /// Rewrite mode of the request, if any
+    pub mode: Option<String>,
+    /// Fingerprint of the backend and prompt settings the response was produced with
+    pub fingerprint: String,
+    /// Fingerprint of the backend's provider, endpoint and `llm_params` alone,
+    /// to tell entries made with settings that have since changed
+    pub backend: String,
+    /// Tokens the request used, if the backend reported them
+    pub usage: Option<TokenUsage>,
+    /// Time the backend took to answer
+    pub latency_ms: Option<u64>,
 }
 
 /// The data stored in the cache
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CacheEntry {
     pub response: String,
     pub created_at: u64,
     pub expires_at: u64,
+    /// Hash of the request text the response is for, after normalisation
+    #[serde(default)]
+    pub text_hash: String,
+    /// Leading and trailing whitespace of the request text as it was sent,
+    /// which normalisation may have removed from the key
+    #[serde(default)]
+    pub input_leading: String,
+    #[serde(default)]
+    pub input_trailing: String,
+    #[serde(default)]
+    pub metadata: EntryMetadata,
 }
 
 impl CacheEntry {
@@ -35,40 +293,48 @@ impl CacheEntry {
             response,
             created_at: now,
             expires_at,
+            text_hash: String::new(),
+            input_leading: String::new(),
+            input_trailing: String::new(),
+            metadata: EntryMetadata::default(),
         }
     }
     
+    /// Encode as the version byte followed by the entry as JSON
     pub fn to_bytes(&self) -> Vec<u8> {
-        // Simple serialization: combine fields with delimiters
-        let data = format!(
-            "{}|{}|{}", 
-            self.response, 
-            self.created_at, 
-            self.expires_at
-        );
-        data.into_bytes()
+        let mut bytes = vec![ENTRY_VERSION];
+        // Strings and numbers only, so serialization cannot fail
+        bytes.extend(serde_json::to_vec(self).unwrap_or_default());
+        bytes
     }
     
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, AppError> {
-        let data = String::from_utf8(bytes.to_vec())
-            .map_err(|e| AppError::CacheError(format!("Failed to deserialize cache entry: {}", e)))?;
-        
-        let parts: Vec<&str> = data.splitn(3, '|').collect();
-        if parts.len() != 3 {
-            return Err(AppError::CacheError("Invalid cache entry format".to_string()));
+        match bytes.split_first() {
+            Some((&ENTRY_VERSION, json)) => serde_json::from_slice(json)
+                .map_err(|e| AppError::CacheError(format!("Failed to deserialize cache entry: {}", e))),
+            Some((version, _)) if *version < b' ' => {
+                Err(AppError::CacheError(format!("Unsupported cache entry version {}", version)))
+            }
+            _ => Err(AppError::CacheError("Cache entry has the unversioned legacy format".to_string())),
+        }
+    }
+    
+    /// `response`, this entry's or one of its candidates, for a request `text`
+    /// that may differ from the stored one in its outer whitespace
+    ///
+    /// Whitespace the response took over from the stored request text is
+    /// replaced with that of `text`; other responses are returned as they are.
+    pub fn response_for(&self, response: &str, text: &str) -> String {
+        let inner = response
+            .strip_prefix(self.input_leading.as_str())
+            .and_then(|rest| rest.strip_suffix(self.input_trailing.as_str()));
+        match inner {
+            Some(inner) => {
+                let (leading, trailing) = outer_whitespace(text);
+                format!("{}{}{}", leading, inner, trailing)
+            }
+            None => response.to_string(),
         }
-        
-        let response = parts[0].to_string();
-        let created_at = parts[1].parse::<u64>()
-            .map_err(|e| AppError::CacheError(format!("Invalid created_at timestamp: {}", e)))?;
-        let expires_at = parts[2].parse::<u64>()
-            .map_err(|e| AppError::CacheError(format!("Invalid expires_at timestamp: {}", e)))?;
-        
-        Ok(Self {
-            response,
-            created_at,
-            expires_at,
-        })
     }
     
     pub fn is_expired(&self) -> bool {
@@ -84,7 +350,16 @@ impl CacheEntry {
 /// Manager for the sled-based response cache
 pub struct CacheManager {
     db: Db,
+    /// Access record of every entry, for least-recently-used eviction
+    access: Tree,
     config: CacheConfig,
+    /// Total size of the entries in bytes, changed in the same transaction
+    /// as the entries themselves
+    sizes: Tree,
+    /// Last access time handed out, so that no two accesses share one
+    clock: AtomicU64,
+    /// Held while evicting, so that concurrent stores do not evict twice
+    eviction: Mutex<()>,
 }
 
 impl CacheManager {
@@ -92,51 +367,240 @@ impl CacheManager {
     pub fn new<P: AsRef<Path>>(path: P, config: CacheConfig) -> Result<Self, AppError> {
         let db = sled::open(path)
             .map_err(|e| AppError::CacheError(format!("Failed to open cache database: {}", e)))?;
+        let access = db.open_tree(ACCESS_TREE)
+            .map_err(|e| AppError::CacheError(format!("Failed to open cache database: {}", e)))?;
+        let sizes = db.open_tree(SIZE_TREE)
+            .map_err(|e| AppError::CacheError(format!("Failed to open cache database: {}", e)))?;
         
-        let manager = Self { db, config: config.clone() };
+        let manager = Self {
+            db,
+            access,
+            config: config.clone(),
+            sizes,
+            clock: AtomicU64::new(0),
+            eviction: Mutex::new(()),
+        };
         
         // Run cleanup on startup if cache is enabled
         if config.enabled {
+            manager.migrate_entries()?;
+            manager.load_access_records()?;
             let count = manager.cleanup_expired()?;
             if count > 0 {
                 info!("Removed {} expired cache entries during startup", count);
             }
+            let evicted = manager.evict_to_budget()?;
+            if evicted > 0 {
+                info!("Evicted {} cache entries during startup to stay within {} MB", evicted, config.max_size_mb);
+            }
         }
         
         Ok(manager)
     }
+
+    /// Total size of the cached entries (keys and values) in bytes
+    ///
+    /// This is what `max_size_mb` bounds; the database files on disk are
+    /// somewhat larger.
+    pub fn size_bytes(&self) -> u64 {
+        decode_total(self.sizes.get(TOTAL_KEY).ok().flatten().as_deref())
+    }
+
+    fn set_size_bytes(&self, total: u64) -> Result<(), AppError> {
+        self.sizes.insert(TOTAL_KEY, &total.to_be_bytes())
+            .map_err(|e| AppError::CacheError(format!("Failed to store cache size: {}", e)))?;
+        Ok(())
+    }
+
+    /// Drop entries written in an older format or under older keys, which
+    /// lookups would never find again
+    fn migrate_entries(&self) -> Result<(), AppError> {
+        let mut discarded = 0;
+        for item in self.db.iter() {
+            let (key, value) = item.map_err(|e| AppError::CacheError(format!("Failed to read cache: {}", e)))?;
+            if CacheEntry::from_bytes(&value).is_err() {
+                self.remove_entry(&key)?;
+                discarded += 1;
+            }
+        }
+        if discarded > 0 {
+            info!("Discarded {} cache entries from an older version of the service", discarded);
+        }
+        Ok(())
+    }
+
+    /// Remove the entries whose backend fingerprint is not in `current`
+    ///
+    /// Returns the number of entries removed.
+    pub fn invalidate_other_backends(&self, current: &BTreeSet<String>) -> Result<usize, AppError> {
+        if !self.config.enabled {
+            return Ok(0);
+        }
+        let mut removed = 0;
+        for item in self.db.iter() {
+            let (key, value) = item.map_err(|e| AppError::CacheError(format!("Failed to read cache: {}", e)))?;
+            let stale = CacheEntry::from_bytes(&value).map_or(true, |entry| !current.contains(&entry.metadata.backend));
+            if stale && self.remove_entry(&key)? {
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Sum up the entry sizes, adding access records for entries that lack one
+    ///
+    /// Entries without a record, e.g. from before records were kept, count
+    /// as the least recently used.
+    fn load_access_records(&self) -> Result<(), AppError> {
+        let mut total = 0;
+        for item in self.db.iter() {
+            let (key, value) = item.map_err(|e| AppError::CacheError(format!("Failed to read cache: {}", e)))?;
+            let size = (key.len() + value.len()) as u64;
+            let stored = match self.access.get(&key) {
+                Ok(Some(bytes)) => AccessRecord::from_bytes(&bytes),
+                _ => None,
+            };
+            let record = AccessRecord { last_access: stored.map_or(0, |record| record.last_access), size };
+            if stored != Some(record) {
+                self.access.insert(&key, &record.to_bytes())
+                    .map_err(|e| AppError::CacheError(format!("Failed to store access record: {}", e)))?;
+            }
+            total += record.size;
+            self.clock.fetch_max(record.last_access, Ordering::SeqCst);
+        }
+        self.set_size_bytes(total)?;
+        debug!("Cache holds {} bytes", total);
+        Ok(())
+    }
+
+    /// A new access time, later than every one handed out before
+    fn tick(&self) -> u64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+        let previous = self.clock.fetch_max(now, Ordering::SeqCst);
+        if previous < now {
+            now
+        } else {
+            self.clock.fetch_add(1, Ordering::SeqCst) + 1
+        }
+    }
+
+    /// Mark an entry as just used, unless it was removed in the meantime
+    fn touch(&self, key: &[u8], size: u64) {
+        let record = AccessRecord { last_access: self.tick(), size }.to_bytes();
+        if let Err(e) = self.access.update_and_fetch(key, |stored| stored.map(|_| &record[..])) {
+            warn!("Failed to update cache access time: {}", e);
+        }
+    }
+
+    /// Write (`Some`) or remove (`None`) an entry with its access record
+    ///
+    /// The total size is updated in the same transaction, so concurrent
+    /// writes of one key cannot count it twice or not at all. Returns the
+    /// previous value.
+    fn write_entry(&self, key: &[u8], entry: Option<(&[u8], AccessRecord)>) -> Result<Option<IVec>, AppError> {
+        (&*self.db, &self.access, &self.sizes)
+            .transaction(|(db, access, sizes)| -> ConflictableTransactionResult<Option<IVec>> {
+                let previous = match entry {
+                    Some((value, record)) => {
+                        access.insert(key, &record.to_bytes()[..])?;
+                        db.insert(key, value)?
+                    }
+                    None => {
+                        access.remove(key)?;
+                        db.remove(key)?
+                    }
+                };
+                let added = entry.map_or(0, |(value, _)| (key.len() + value.len()) as u64);
+                let removed = previous.as_ref().map_or(0, |previous| (key.len() + previous.len()) as u64);
+                let total = decode_total(sizes.get(TOTAL_KEY)?.as_deref());
+                sizes.insert(TOTAL_KEY, &(total + added).saturating_sub(removed).to_be_bytes()[..])?;
+                Ok(previous)
+            })
+            .map_err(|e| AppError::CacheError(format!("Failed to write cache entry: {:?}", e)))
+    }
+
+    /// Remove an entry and its access record, keeping the total size current
+    fn remove_entry(&self, key: &[u8]) -> Result<bool, AppError> {
+        Ok(self.write_entry(key, None)?.is_some())
+    }
+
+    /// Evict the least recently used entries until the cache fits `max_size_mb`
+    ///
+    /// Returns the number of entries evicted.
+    fn evict_to_budget(&self) -> Result<usize, AppError> {
+        let budget = self.config.max_size_mb.saturating_mul(1024 * 1024);
+        if budget == 0 || self.size_bytes() <= budget {
+            return Ok(0);
+        }
+        let _guard = self.eviction.lock().unwrap_or_else(|e| e.into_inner());
+
+        let mut records: Vec<(sled::IVec, AccessRecord)> = self
+            .access
+            .iter()
+            .filter_map(Result::ok)
+            .filter_map(|(key, bytes)| AccessRecord::from_bytes(&bytes).map(|record| (key, record)))
+            .collect();
+        records.sort_by_key(|(_, record)| record.last_access);
+
+        let mut evicted = 0;
+        for (key, _) in records {
+            if self.size_bytes() <= budget {
+                break;
+            }
+            if self.remove_entry(&key)? {
+                evicted += 1;
+            }
+        }
+        debug!("Evicted {} cache entries, {} bytes left", evicted, self.size_bytes());
+        Ok(evicted)
+    }
     
-    /// Generate a cache key from the input text, model, and prompt template hash
-    pub fn generate_key(text: &str, model: &str, prompt_template_hash: u64) -> Vec<u8> {
-        let combined = format!("{}|{}|{}", model, prompt_template_hash, text);
-        
-        let mut hasher = DefaultHasher::new();
-        combined.hash(&mut hasher);
-        let hash = hasher.finish();
-        
-        hash.to_be_bytes().to_vec()
+    /// Generate a cache key from the input text, model, and the fingerprint of
+    /// everything else that affects the response
+    pub fn generate_key(text: &str, model: &str, fingerprint: &str) -> Vec<u8> {
+        let mut key = Fingerprint::new();
+        key.field("text", text).field("model", model).field("fingerprint", fingerprint);
+        key.digest()
     }
     
     /// Lookup a cached response for the given input
-    pub fn lookup(&self, text: &str, model: &str, prompt_template_hash: u64) -> Result<Option<String>, AppError> {
+    ///
+    /// The response gets the outer whitespace of `text`, see `CacheEntry::response_for`.
+    pub fn lookup(&self, text: &str, model: &str, fingerprint: &str) -> Result<Option<String>, AppError> {
+        Ok(self.lookup_entry(text, model, fingerprint)?.map(|entry| entry.response_for(&entry.response, text)))
+    }
+    
+    /// Lookup the cache entry for the given input, with the response as stored
+    pub fn lookup_entry(&self, text: &str, model: &str, fingerprint: &str) -> Result<Option<CacheEntry>, AppError> {
         if !self.config.enabled {
             return Ok(None);
         }
         
-        let key = Self::generate_key(text, model, prompt_template_hash);
+        let normalized = self.config.normalize_key(text);
+        let key = Self::generate_key(&normalized, model, fingerprint);
         
         match self.db.get(&key) {
             Ok(Some(ivec)) => {
                 let entry = CacheEntry::from_bytes(&ivec)?;
                 
-                if entry.is_expired() {
+                if entry.text_hash != text_hash(&normalized) {
+                    warn!("Cache key collision: the entry is for a different text, ignoring it");
+                    Ok(None)
+                } else if entry.is_expired() {
                     // Remove expired entry
-                    let _ = self.db.remove(&key);
+                    let _ = self.remove_entry(&key);
                     debug!("Removed expired cache entry");
                     Ok(None)
                 } else {
-                    debug!("Cache hit for text input");
-                    Ok(Some(entry.response))
+                    debug!(
+                        "Cache hit for text input (saved {} tokens)",
+                        entry.metadata.usage.map_or(0, |usage| usage.input_tokens + usage.output_tokens)
+                    );
+                    self.touch(&key, (key.len() + ivec.len()) as u64);
+                    Ok(Some(entry))
                 }
             },
             Ok(None) => {
@@ -148,18 +612,43 @@ impl CacheManager {
     }
     
     /// Store a response in the cache
-    pub fn store(&self, text: &str, response: &str, model: &str, prompt_template_hash: u64) -> Result<(), AppError> {
+    #[allow(dead_code)]
+    pub fn store(&self, text: &str, response: &str, model: &str, fingerprint: &str) -> Result<(), AppError> {
+        let metadata = EntryMetadata {
+            model: model.to_string(),
+            fingerprint: fingerprint.to_string(),
+            ..EntryMetadata::default()
+        };
+        self.store_with_metadata(text, response, metadata)
+    }
+    
+    /// Store a response in the cache along with where it came from
+    ///
+    /// The key is made from the normalised text, `metadata.model` and `metadata.fingerprint`.
+    pub fn store_with_metadata(&self, text: &str, response: &str, metadata: EntryMetadata) -> Result<(), AppError> {
         if !self.config.enabled {
             return Ok(());
         }
         
-        let key = Self::generate_key(text, model, prompt_template_hash);
-        let entry = CacheEntry::new(response.to_string(), self.config.ttl_days);
-        
-        self.db.insert(key, entry.to_bytes())
-            .map_err(|e| AppError::CacheError(format!("Failed to store in cache: {}", e)))?;
+        let normalized = self.config.normalize_key(text);
+        let key = Self::generate_key(&normalized, &metadata.model, &metadata.fingerprint);
+        let (leading, trailing) = outer_whitespace(text);
+        let entry = CacheEntry {
+            text_hash: text_hash(&normalized),
+            input_leading: leading.to_string(),
+            input_trailing: trailing.to_string(),
+            metadata,
+            ..CacheEntry::new(response.to_string(), self.config.ttl_days)
+        };
+        let bytes = entry.to_bytes();
+        let record = AccessRecord { last_access: self.tick(), size: (key.len() + bytes.len()) as u64 };
+        self.write_entry(&key, Some((&bytes, record)))?;
         
         debug!("Stored response in cache");
+        let evicted = self.evict_to_budget()?;
+        if evicted > 0 {
+            info!("Evicted {} least recently used cache entries", evicted);
+        }
         Ok(())
     }
     
@@ -181,7 +670,7 @@ impl CacheManager {
                 Ok((key, value)) => {
                     match CacheEntry::from_bytes(&value) {
                         Ok(entry) if entry.expires_at < now => {
-                            if let Ok(_) = self.db.remove(key) {
+                            if let Ok(true) = self.remove_entry(&key) {
                                 removed_count += 1;
                             }
                         },
@@ -200,6 +689,9 @@ impl CacheManager {
     pub fn clear(&self) -> Result<(), AppError> {
         self.db.clear()
             .map_err(|e| AppError::CacheError(format!("Failed to clear cache: {}", e)))?;
+        self.access.clear()
+            .map_err(|e| AppError::CacheError(format!("Failed to clear cache: {}", e)))?;
+        self.set_size_bytes(0)?;
         
         info!("Cache cleared");
         Ok(())
@@ -220,6 +712,195 @@ mod tests {
         assert_eq!(entry.response, deserialized.response);
         assert_eq!(entry.created_at, deserialized.created_at);
         assert_eq!(entry.expires_at, deserialized.expires_at);
+        
+        // Pipes and metadata survive the round trip
+        let entry = CacheEntry {
+            metadata: EntryMetadata {
+                model: "test-model".to_string(),
+                mode: Some("formal".to_string()),
+                fingerprint: "abc123".to_string(),
+                backend: "def456".to_string(),
+                usage: Some(TokenUsage { input_tokens: 12, output_tokens: 7 }),
+                latency_ms: Some(850),
+            },
+            ..CacheEntry::new("| a | b |\n|---|---|\n| 1 | 2 |".to_string(), 30)
+        };
+        let bytes = entry.to_bytes();
+        assert_eq!(bytes[0], ENTRY_VERSION);
+        assert_eq!(CacheEntry::from_bytes(&bytes).unwrap(), entry);
+    }
+    
+    #[test]
+    fn test_older_entries_are_discarded() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache_path = temp_dir.path().join("test_cache.sled");
+        let config = CacheConfig {
+            enabled: true,
+            ttl_days: 30,
+            max_size_mb: 100,
+            invalidate_changed_params: false,
+            normalize: Vec::new(),
+        };
+        
+        let cache_manager = CacheManager::new(&cache_path, config).unwrap();
+        cache_manager.store("current", "Current response", "test-model", "abc123").unwrap();
+        
+        // Entries as written before they were versioned, and by version 2
+        let legacy = format!("grep 'a|b' log.txt | wc -l|1700000000|{}", u64::MAX / 2);
+        cache_manager.db.insert(7u64.to_be_bytes(), legacy.as_bytes()).unwrap();
+        cache_manager.db.insert(8u64.to_be_bytes(), &b"\x02{}"[..]).unwrap();
+        
+        // As on startup
+        cache_manager.migrate_entries().unwrap();
+        assert_eq!(cache_manager.db.len(), 1);
+        assert_eq!(
+            cache_manager.lookup("current", "test-model", "abc123").unwrap(),
+            Some("Current response".to_string())
+        );
+    }
+    
+    #[test]
+    fn test_key_collision_is_a_miss() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = CacheConfig {
+            enabled: true,
+            ttl_days: 30,
+            max_size_mb: 100,
+            invalidate_changed_params: false,
+            normalize: Vec::new(),
+        };
+        let cache_manager = CacheManager::new(temp_dir.path().join("test_cache.sled"), config).unwrap();
+        cache_manager.store("first text", "First response", "test-model", "abc123").unwrap();
+        
+        // Pretend "second text" hashed to the key of "first text"
+        let entry = cache_manager.db.get(CacheManager::generate_key("first text", "test-model", "abc123")).unwrap().unwrap();
+        cache_manager.db.insert(CacheManager::generate_key("second text", "test-model", "abc123"), entry).unwrap();
+        
+        assert_eq!(cache_manager.lookup("second text", "test-model", "abc123").unwrap(), None);
+        assert!(cache_manager.lookup("first text", "test-model", "abc123").unwrap().is_some());
+    }
+    
+    #[test]
+    fn test_invalidate_other_backends() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = CacheConfig {
+            enabled: true,
+            ttl_days: 30,
+            max_size_mb: 100,
+            invalidate_changed_params: true,
+            normalize: Vec::new(),
+        };
+        let cache_manager = CacheManager::new(temp_dir.path().join("test_cache.sled"), config).unwrap();
+        for (text, backend) in [("kept", "current"), ("stale", "previous")] {
+            let metadata = EntryMetadata {
+                model: "test-model".to_string(),
+                fingerprint: backend.to_string(),
+                backend: backend.to_string(),
+                ..EntryMetadata::default()
+            };
+            cache_manager.store_with_metadata(text, "Response", metadata).unwrap();
+        }
+        
+        let current = BTreeSet::from(["current".to_string()]);
+        assert_eq!(cache_manager.invalidate_other_backends(&current).unwrap(), 1);
+        assert!(cache_manager.lookup("kept", "test-model", "current").unwrap().is_some());
+        assert!(cache_manager.lookup("stale", "test-model", "previous").unwrap().is_none());
+        assert_eq!(cache_manager.size_bytes(), cache_manager.db.iter().map(|item| {
+            let (key, value) = item.unwrap();
+            (key.len() + value.len()) as u64
+        }).sum::<u64>());
+    }
+    
+    #[test]
+    fn test_normalize_key() {
+        let config = |normalize: Vec<KeyNormalization>| CacheConfig {
+            enabled: true,
+            ttl_days: 30,
+            max_size_mb: 100,
+            invalidate_changed_params: false,
+            normalize,
+        };
+        
+        let default = config(default_normalize());
+        assert_eq!(default.normalize_key("  I has a apple\n"), "I has a apple");
+        assert_eq!(default.normalize_key("Cafe\u{301} au lait"), "Caf\u{e9} au lait");
+        assert_eq!(default.normalize_key("a  b"), "a  b");
+        
+        let all = config(vec![
+            KeyNormalization::Trim,
+            KeyNormalization::CollapseWhitespace,
+            KeyNormalization::Nfc,
+            KeyNormalization::FoldQuotes,
+        ]);
+        assert_eq!(all.normalize_key("\tWe  don\u{2019}t\tsay \u{201C}hi\u{201D}. \n"), "We don't say \"hi\".");
+        assert_eq!(all.normalize_key("Dear team,  \n  thanks.\n\n\n\nBye"), "Dear team,\nthanks.\n\nBye");
+        
+        assert_eq!(config(Vec::new()).normalize_key(" as is "), " as is ");
+    }
+    
+    #[test]
+    fn test_outer_whitespace_is_reapplied() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = CacheConfig {
+            enabled: true,
+            ttl_days: 30,
+            max_size_mb: 100,
+            invalidate_changed_params: false,
+            normalize: default_normalize(),
+        };
+        let cache_manager = CacheManager::new(temp_dir.path().join("test_cache.sled"), config).unwrap();
+        cache_manager.store("  i has a apple\n", "  I have an apple.\n", "test-model", "abc123").unwrap();
+        
+        assert_eq!(
+            cache_manager.lookup("  i has a apple\n", "test-model", "abc123").unwrap().as_deref(),
+            Some("  I have an apple.\n")
+        );
+        assert_eq!(
+            cache_manager.lookup("i has a apple", "test-model", "abc123").unwrap().as_deref(),
+            Some("I have an apple.")
+        );
+        assert_eq!(
+            cache_manager.lookup("\ti has a apple\n\n", "test-model", "abc123").unwrap().as_deref(),
+            Some("\tI have an apple.\n\n")
+        );
+        
+        // A response that did not take over the request's whitespace is left alone
+        cache_manager.store("  hello  ", "Hello.", "test-model", "abc123").unwrap();
+        assert_eq!(cache_manager.lookup("hello\n", "test-model", "abc123").unwrap().as_deref(), Some("Hello."));
+    }
+    
+    #[test]
+    fn test_canonical_params() {
+        assert_eq!(
+            canonical_json(&serde_json::json!({ "top_p": 0.9, "temperature": 1.0, "stop": ["\n", 2] })),
+            r#"{"stop":["\n",2],"temperature":1,"top_p":0.9}"#
+        );
+        assert_eq!(
+            canonical_json(&serde_json::json!({ "temperature": 1 })),
+            canonical_json(&serde_json::json!({ "temperature": 1.0 }))
+        );
+        assert_ne!(
+            canonical_json(&serde_json::json!({ "temperature": 0.7 })),
+            canonical_json(&serde_json::json!({ "temperature": 0.75 }))
+        );
+        
+        assert_eq!(canonical_url("HTTP://LocalHost:80/api/chat/"), "http://localhost/api/chat");
+        assert_eq!(canonical_url(" http://gpu-box:11434/api/chat"), "http://gpu-box:11434/api/chat");
+        assert_ne!(canonical_url("http://gpu-box:11434/api/chat"), canonical_url("http://laptop:11434/api/chat"));
+    }
+    
+    #[test]
+    fn test_fingerprint_is_stable() {
+        let mut fingerprint = Fingerprint::new();
+        fingerprint.field("model", "gpt-4o").field("text", "Hello");
+        assert_eq!(fingerprint.finish(), "d0158d795fd79f67092e7772934dc048f18c79121b8ca595e498dbcb566ad929");
+        
+        // Moving a boundary between fields changes the fingerprint
+        let mut a = Fingerprint::new();
+        a.field("a", "bc");
+        let mut b = Fingerprint::new();
+        b.field("ab", "c");
+        assert_ne!(a.finish(), b.finish());
     }
     
     #[test]
@@ -252,6 +933,8 @@ mod tests {
             enabled: true,
             ttl_days: 30,
             max_size_mb: 100,
+            invalidate_changed_params: false,
+            normalize: Vec::new(),
         };
         
         // Create cache manager
@@ -260,38 +943,38 @@ mod tests {
         // Test storing and retrieving data
         let text = "Test input text";
         let model = "test-model";
-        let prompt_hash = 12345u64;
+        let fingerprint = "abc123";
         let response = "Test response";
         
         // Store the response
-        cache_manager.store(text, response, model, prompt_hash).unwrap();
+        cache_manager.store(text, response, model, fingerprint).unwrap();
         
         // Lookup the response
-        let cached_response = cache_manager.lookup(text, model, prompt_hash).unwrap();
+        let cached_response = cache_manager.lookup(text, model, fingerprint).unwrap();
         
         assert_eq!(cached_response, Some(response.to_string()));
         
         // Test with different text
         let different_text = "Different text";
-        let cached_response = cache_manager.lookup(different_text, model, prompt_hash).unwrap();
+        let cached_response = cache_manager.lookup(different_text, model, fingerprint).unwrap();
         
         assert_eq!(cached_response, None);
         
         // Test with different model
         let different_model = "different-model";
-        let cached_response = cache_manager.lookup(text, different_model, prompt_hash).unwrap();
+        let cached_response = cache_manager.lookup(text, different_model, fingerprint).unwrap();
         
         assert_eq!(cached_response, None);
         
-        // Test with different prompt hash
-        let different_hash = 54321u64;
-        let cached_response = cache_manager.lookup(text, model, different_hash).unwrap();
+        // Test with different fingerprint
+        let different_fingerprint = "def456";
+        let cached_response = cache_manager.lookup(text, model, different_fingerprint).unwrap();
         
         assert_eq!(cached_response, None);
         
         // Test cache clear
         cache_manager.clear().unwrap();
-        let cached_response = cache_manager.lookup(text, model, prompt_hash).unwrap();
+        let cached_response = cache_manager.lookup(text, model, fingerprint).unwrap();
         
         assert_eq!(cached_response, None);
     }
@@ -307,6 +990,8 @@ mod tests {
             enabled: false,
             ttl_days: 30,
             max_size_mb: 100,
+            invalidate_changed_params: false,
+            normalize: Vec::new(),
         };
         
         // Create cache manager
@@ -315,15 +1000,93 @@ mod tests {
         // Test storing and retrieving data with disabled cache
         let text = "Test input text";
         let model = "test-model";
-        let prompt_hash = 12345u64;
+        let fingerprint = "abc123";
         let response = "Test response";
         
         // Store the response (should be a no-op)
-        cache_manager.store(text, response, model, prompt_hash).unwrap();
+        cache_manager.store(text, response, model, fingerprint).unwrap();
         
         // Lookup the response (should return None)
-        let cached_response = cache_manager.lookup(text, model, prompt_hash).unwrap();
+        let cached_response = cache_manager.lookup(text, model, fingerprint).unwrap();
         
         assert_eq!(cached_response, None);
     }
+    
+    #[test]
+    fn test_lru_eviction() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache_path = temp_dir.path().join("test_cache.sled");
+        let config = CacheConfig {
+            enabled: true,
+            ttl_days: 30,
+            max_size_mb: 1,
+            invalidate_changed_params: false,
+            normalize: Vec::new(),
+        };
+        let cache_manager = CacheManager::new(&cache_path, config.clone()).unwrap();
+        
+        // Three entries of ~300 KB fit in 1 MB, a fourth does not
+        let response = "x".repeat(300 * 1024);
+        for text in ["a", "b", "c"] {
+            cache_manager.store(text, &response, "test-model", "").unwrap();
+        }
+        assert!(cache_manager.size_bytes() > 900 * 1024);
+        
+        // Reading "a" makes "b" the least recently used entry
+        assert!(cache_manager.lookup("a", "test-model", "").unwrap().is_some());
+        cache_manager.store("d", &response, "test-model", "").unwrap();
+        
+        assert!(cache_manager.size_bytes() <= 1024 * 1024);
+        assert!(cache_manager.lookup("b", "test-model", "").unwrap().is_none());
+        for text in ["a", "c", "d"] {
+            assert!(cache_manager.lookup(text, "test-model", "").unwrap().is_some(), "{}", text);
+        }
+        
+        // Replacing an entry does not count it twice
+        let size = cache_manager.size_bytes();
+        cache_manager.store("d", &response, "test-model", "").unwrap();
+        assert_eq!(cache_manager.size_bytes(), size);
+        
+        // The size is recomputed from the database on startup
+        cache_manager.set_size_bytes(0).unwrap();
+        cache_manager.load_access_records().unwrap();
+        assert_eq!(cache_manager.size_bytes(), size);
+        
+        cache_manager.clear().unwrap();
+        assert_eq!(cache_manager.size_bytes(), 0);
+    }
+
+    #[test]
+    fn test_size_stays_exact_with_concurrent_writes() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = CacheConfig { max_size_mb: 0, ..CacheConfig::default() };
+        let cache_manager = CacheManager::new(temp_dir.path().join("test_cache.sled"), config).unwrap();
+
+        // Threads store, replace and remove the same few entries
+        std::thread::scope(|scope| {
+            for thread in 0..8 {
+                let cache_manager = &cache_manager;
+                scope.spawn(move || {
+                    for round in 0..50 {
+                        let text = ["a", "b", "c"][(thread + round) % 3];
+                        if round % 4 == 3 {
+                            let key = CacheManager::generate_key(text, "test-model", "");
+                            cache_manager.remove_entry(&key).unwrap();
+                        } else {
+                            let response = "x".repeat(thread * 100 + round);
+                            cache_manager.store(text, &response, "test-model", "").unwrap();
+                            assert!(cache_manager.lookup(text, "test-model", "").is_ok());
+                        }
+                    }
+                });
+            }
+        });
+
+        let actual: u64 = cache_manager
+            .db
+            .iter()
+            .map(|item| item.map(|(key, value)| (key.len() + value.len()) as u64).unwrap())
+            .sum();
+        assert_eq!(cache_manager.size_bytes(), actual);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,302 @@
+use serde::Deserialize;
+use std::ops::Range;
+
+use crate::masking::protected_ranges;
+
+/// Options for splitting long inputs into separately processed chunks
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ChunkingConfig {
+    pub enabled: bool,
+    /// Largest chunk sent to the model, in estimated tokens
+    pub max_chunk_tokens: usize,
+    /// How many chunks may be in flight at once; 1 processes them in order
+    pub max_concurrency: usize,
+}
+
+impl Default for ChunkingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_chunk_tokens: 400,
+            max_concurrency: 1,
+        }
+    }
+}
+
+/// Rough token count, assuming about four characters per token
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Whether a text ends with sentence-ending punctuation, possibly followed by
+/// closing quotes or brackets
+pub(crate) fn ends_sentence(text: &str) -> bool {
+    text.trim_end_matches(['"', '\'', ')', ']', '»', '”', '’'])
+        .ends_with(['.', '!', '?', '…', '。', '！', '？'])
+}
+
+/// A text split into chunks, plus the original whitespace around each of them
+#[derive(Debug, PartialEq)]
+pub struct ChunkedText {
+    pub chunks: Vec<String>,
+    /// `separators[i]` comes before `chunks[i]`; the last one follows the final chunk
+    separators: Vec<String>,
+}
+
+impl ChunkedText {
+    /// Join processed chunks back together with the original whitespace
+    pub fn reassemble<S: AsRef<str>>(&self, outputs: &[S]) -> String {
+        debug_assert_eq!(outputs.len(), self.chunks.len());
+
+        let mut text = String::new();
+        for (separator, output) in self.separators.iter().zip(outputs) {
+            text.push_str(separator);
+            text.push_str(output.as_ref());
+        }
+        if let Some(trailing) = self.separators.last() {
+            text.push_str(trailing);
+        }
+        text
+    }
+}
+
+/// Boundaries a chunk may be split at, from coarsest to finest
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Level {
+    /// Blank lines between paragraphs
+    Paragraph,
+    /// Line breaks, e.g. between list items
+    Line,
+    /// Whitespace after a sentence-ending punctuation mark
+    Sentence,
+    /// Any whitespace; the last resort for very long sentences
+    Word,
+}
+
+impl Level {
+    fn finer(self) -> Option<Level> {
+        match self {
+            Level::Paragraph => Some(Level::Line),
+            Level::Line => Some(Level::Sentence),
+            Level::Sentence => Some(Level::Word),
+            Level::Word => None,
+        }
+    }
+
+    /// Whether the whitespace run `text[gap]` separates two pieces at this level
+    fn is_boundary(self, text: &str, gap: Range<usize>) -> bool {
+        let newlines = text[gap.clone()].matches('\n').count();
+        match self {
+            Level::Paragraph => newlines >= 2,
+            Level::Line => newlines >= 1,
+            Level::Sentence => {
+                // "e.g. foo" is not a sentence end; the next sentence starts with a capital
+                let next_is_lowercase = text[gap.end..].chars().next().is_some_and(char::is_lowercase);
+                ends_sentence(&text[..gap.start]) && !next_is_lowercase
+            }
+            Level::Word => true,
+        }
+    }
+}
+
+/// Split `text[range]` (which has no outer whitespace) into pieces at the given
+/// level, never inside one of the `unbreakable` spans
+fn pieces(text: &str, range: Range<usize>, level: Level, unbreakable: &[Range<usize>]) -> Vec<Range<usize>> {
+    let mut pieces = Vec::new();
+    let mut piece_start = range.start;
+    let mut gap_start = None;
+
+    for (offset, c) in text[range.clone()].char_indices() {
+        let index = range.start + offset;
+        if c.is_whitespace() {
+            gap_start.get_or_insert(index);
+        } else if let Some(start) = gap_start.take() {
+            let inside = unbreakable.iter().any(|span| span.start < start && index < span.end);
+            if level.is_boundary(text, start..index) && !inside {
+                pieces.push(piece_start..start);
+                piece_start = index;
+            }
+        }
+    }
+    pieces.push(piece_start..range.end);
+    pieces
+}
+
+/// Split an oversized piece at the given level or finer ones, then merge
+/// neighbouring parts again as long as they fit the budget
+fn split_oversized(
+    text: &str,
+    range: Range<usize>,
+    level: Level,
+    max_tokens: usize,
+    unbreakable: &[Range<usize>],
+    out: &mut Vec<Range<usize>>,
+) {
+    if estimate_tokens(&text[range.clone()]) <= max_tokens {
+        out.push(range);
+        return;
+    }
+
+    let mut level = level;
+    let parts = loop {
+        let parts = pieces(text, range.clone(), level, unbreakable);
+        if parts.len() > 1 {
+            break parts;
+        }
+        match level.finer() {
+            Some(finer) => level = finer,
+            // A single unbreakable run of text; send it as it is
+            None => {
+                out.push(range);
+                return;
+            }
+        }
+    };
+
+    let mut split = Vec::new();
+    for part in parts {
+        match level.finer() {
+            Some(finer) => split_oversized(text, part, finer, max_tokens, unbreakable, &mut split),
+            None => split.push(part),
+        }
+    }
+
+    let mut merged: Vec<Range<usize>> = Vec::new();
+    for part in split {
+        match merged.last_mut() {
+            Some(last) if estimate_tokens(&text[last.start..part.end]) <= max_tokens => last.end = part.end,
+            _ => merged.push(part),
+        }
+    }
+    out.extend(merged);
+}
+
+/// Split a text into chunks of at most `max_tokens` estimated tokens
+///
+/// Every paragraph starts a new chunk, so blank lines between paragraphs are
+/// never sent to the model and survive reassembly unchanged. Paragraphs over
+/// the budget are split at line breaks, then sentences, then words. Protected
+/// spans such as fenced code blocks are never split, even at a blank line,
+/// so they can still be masked within their chunk.
+pub fn split(text: &str, max_tokens: usize) -> ChunkedText {
+    let trimmed = text.trim();
+    if trimmed.is_empty() {
+        return ChunkedText {
+            chunks: Vec::new(),
+            separators: vec![text.to_string()],
+        };
+    }
+
+    let start = text.len() - text.trim_start().len();
+    let content = start..start + trimmed.len();
+
+    let unbreakable = protected_ranges(text);
+    let mut ranges = Vec::new();
+    for paragraph in pieces(text, content, Level::Paragraph, &unbreakable) {
+        split_oversized(text, paragraph, Level::Line, max_tokens.max(1), &unbreakable, &mut ranges);
+    }
+
+    let mut separators = Vec::with_capacity(ranges.len() + 1);
+    let mut previous_end = 0;
+    for range in &ranges {
+        separators.push(text[previous_end..range.start].to_string());
+        previous_end = range.end;
+    }
+    separators.push(text[previous_end..].to_string());
+
+    ChunkedText {
+        chunks: ranges.into_iter().map(|range| text[range].to_string()).collect(),
+        separators,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abcd"), 1);
+        assert_eq!(estimate_tokens("abcde"), 2);
+        // Characters, not bytes
+        assert_eq!(estimate_tokens("привет"), 2);
+    }
+
+    #[test]
+    fn test_split_keeps_paragraphs_and_blank_lines() {
+        let text = "\n  First paragraph.\n\n\nSecond one.\n \nThird.  \n";
+        let chunked = split(text, 100);
+
+        assert_eq!(chunked.chunks, vec!["First paragraph.", "Second one.", "Third."]);
+        assert_eq!(chunked.separators, vec!["\n  ", "\n\n\n", "\n \n", "  \n"]);
+        assert_eq!(chunked.reassemble(&chunked.chunks), text);
+
+        let upper: Vec<String> = chunked.chunks.iter().map(|c| c.to_uppercase()).collect();
+        assert_eq!(chunked.reassemble(&upper), "\n  FIRST PARAGRAPH.\n\n\nSECOND ONE.\n \nTHIRD.  \n");
+    }
+
+    #[test]
+    fn test_split_long_list_at_line_breaks() {
+        let text = "Todo:\n- buy milk and eggs\n  - the organic ones\n- call the plumber today\n- water plants";
+        let chunked = split(text, 8);
+
+        // Each chunk holds whole list items; indentation stays in the separators
+        assert!(chunked.chunks.iter().all(|c| estimate_tokens(c) <= 8));
+        assert!(chunked.chunks.iter().all(|c| !c.starts_with(char::is_whitespace)));
+        assert!(chunked.chunks.contains(&"- the organic ones".to_string()));
+        assert!(chunked.separators.contains(&"\n  ".to_string()));
+        assert_eq!(chunked.reassemble(&chunked.chunks), text);
+    }
+
+    #[test]
+    fn test_split_long_paragraph_at_sentences() {
+        let text = "This is the first sentence. Here is another one, e.g. with an abbreviation. And a third!";
+        let chunked = split(text, 12);
+
+        assert_eq!(
+            chunked.chunks,
+            vec![
+                "This is the first sentence.",
+                "Here is another one, e.g. with an abbreviation.",
+                "And a third!"
+            ]
+        );
+        assert_eq!(chunked.reassemble(&chunked.chunks), text);
+    }
+
+    #[test]
+    fn test_split_merges_small_sentences() {
+        let text = "One. Two. Three. Four. Five. Six.";
+        let chunked = split(text, 5);
+
+        assert_eq!(chunked.chunks, vec!["One. Two. Three.", "Four. Five. Six."]);
+        assert_eq!(chunked.reassemble(&chunked.chunks), text);
+    }
+
+    #[test]
+    fn test_split_keeps_code_blocks_whole() {
+        let text = "Intro.\n\n```\nlet first = 1;\n\nlet second = 2;\n```\n\nOutro.";
+        let chunked = split(text, 4);
+
+        assert_eq!(chunked.chunks, vec!["Intro.", "```\nlet first = 1;\n\nlet second = 2;\n```", "Outro."]);
+        assert_eq!(chunked.reassemble(&chunked.chunks), text);
+    }
+
+    #[test]
+    fn test_split_unbreakable_text() {
+        let text = "https://example.com/a/very/long/url/without/any/spaces/in/it";
+        let chunked = split(text, 4);
+
+        assert_eq!(chunked.chunks, vec![text]);
+    }
+
+    #[test]
+    fn test_split_whitespace_only() {
+        let chunked = split(" \n ", 10);
+
+        assert!(chunked.chunks.is_empty());
+        assert_eq!(chunked.reassemble::<String>(&[]), " \n ");
+    }
+}
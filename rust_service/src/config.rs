@@ -1,6 +1,7 @@
 use config::{Config as ConfigLoader, Environment, File as ConfigFile};
 use serde::Deserialize;
 use serde_json::Value;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 use tracing::{debug, info, warn};
 
@@ -10,31 +11,392 @@ use crate::errors::AppError;
 #[derive(Debug, Deserialize, Clone)]
 pub struct AppConfig {
     pub port: u16,
+    #[serde(default)]
+    pub provider: crate::providers::ProviderKind,
     pub llm_url: String,
     pub model_name: String,
     #[serde(default)]
     pub llm_params: Option<Value>,
     #[serde(default)]
     pub prompt_template: Option<String>,
+    /// Overrides the built-in system prompt; an empty string sends none at all
+    #[serde(default)]
+    pub system_prompt: Option<String>,
     #[serde(default)]
     pub openai_api_key: Option<String>,
     #[serde(default)]
     pub openai_org_id: Option<String>,
     #[serde(default)]
     pub openai_project_id: Option<String>,
-    #[serde(default = "default_cache_config")]
+    #[serde(default)]
+    pub anthropic_api_key: Option<String>,
+    #[serde(default)]
+    pub anthropic_version: Option<String>,
+    /// Optional bearer token for OpenAI-compatible servers (llama.cpp, vLLM, LM Studio)
+    #[serde(default)]
+    pub llm_api_key: Option<String>,
+    /// Named rewrite modes selectable per request, from `[modes.<name>]` tables
+    #[serde(default)]
+    pub modes: BTreeMap<String, ModeConfig>,
+    /// Backends tried in order when the primary one is unavailable
+    #[serde(default)]
+    pub fallbacks: Vec<FallbackConfig>,
+    /// Retry policy for failed LLM requests
+    #[serde(default)]
+    pub retry: crate::retry::RetryConfig,
+    /// How long inputs are split into chunks
+    #[serde(default)]
+    pub chunking: crate::chunking::ChunkingConfig,
+    /// Length limit for LLM responses and what to do with longer ones
+    #[serde(default)]
+    pub response_limit: crate::response_limit::ResponseLimitConfig,
+    /// Protection of code, URLs and placeholders from being rewritten
+    #[serde(default)]
+    pub masking: crate::masking::MaskingConfig,
+    /// Team glossary, injected into the prompt and checked in the response
+    #[serde(default)]
+    pub glossary: crate::glossary::GlossaryConfig,
+    /// Removal of sensitive values before text is sent to a non-local backend
+    #[serde(default)]
+    pub redaction: crate::redaction::RedactionConfig,
+    /// Ordered clean-up of the model output
+    #[serde(default)]
+    pub post_process: crate::postprocess::PostProcessConfig,
+    /// Detection of the request text's language, so the response keeps it
+    #[serde(default)]
+    pub language: crate::language::LanguageConfig,
+    #[serde(default)]
     pub cache: crate::cache::CacheConfig,
 }
 
-// Default cache configuration
-fn default_cache_config() -> crate::cache::CacheConfig {
-    crate::cache::CacheConfig {
-        enabled: true,
-        ttl_days: 30,
-        max_size_mb: 100,
+/// The built-in defaults, as used by `load_config` when nothing overrides them
+impl Default for AppConfig {
+    fn default() -> Self {
+        AppConfig {
+            port: 8989,
+            provider: Default::default(),
+            llm_url: "https://api.openai.com/v1/responses".to_string(),
+            model_name: "gpt-4o".to_string(),
+            llm_params: None,
+            prompt_template: None,
+            system_prompt: None,
+            openai_api_key: None,
+            openai_org_id: None,
+            openai_project_id: None,
+            anthropic_api_key: None,
+            anthropic_version: None,
+            llm_api_key: None,
+            modes: BTreeMap::new(),
+            fallbacks: Vec::new(),
+            retry: Default::default(),
+            chunking: Default::default(),
+            response_limit: Default::default(),
+            masking: Default::default(),
+            glossary: Default::default(),
+            redaction: Default::default(),
+            post_process: Default::default(),
+            language: Default::default(),
+            cache: Default::default(),
+        }
+    }
+}
+
+/// A named rewrite mode; each field set here overrides the top-level one
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ModeConfig {
+    /// Short description shown by `GET /modes`
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub prompt_template: Option<String>,
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    #[serde(default)]
+    pub model_name: Option<String>,
+    /// Merged over the top-level `llm_params`, key by key
+    #[serde(default)]
+    pub llm_params: Option<Value>,
+    /// Replaces `[language] enabled`; set it to false for modes that translate
+    #[serde(default)]
+    pub preserve_language: Option<bool>,
+}
+
+/// An additional LLM backend, used when every backend before it is unavailable
+///
+/// Credentials are shared with the primary backend; `llm_params` and
+/// `llm_api_key` are inherited unless set here.
+#[derive(Debug, Deserialize, Clone)]
+pub struct FallbackConfig {
+    #[serde(default)]
+    pub provider: crate::providers::ProviderKind,
+    pub llm_url: String,
+    pub model_name: String,
+    #[serde(default)]
+    pub llm_params: Option<Value>,
+    #[serde(default)]
+    pub llm_api_key: Option<String>,
+}
+
+impl AppConfig {
+    /// Check the prompt templates of the top level and of every mode
+    pub fn validate_templates(&self) -> Result<(), AppError> {
+        if let Some(template) = &self.prompt_template {
+            crate::template::validate(template)?;
+        }
+        for (name, mode) in &self.modes {
+            if let Some(template) = &mode.prompt_template {
+                crate::template::validate(template).map_err(|e| {
+                    AppError::Config(config::ConfigError::Message(format!("Mode '{}': {}", name, e)))
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The config to use for a request in the given mode, or unchanged without one
+    pub fn with_mode(&self, mode: Option<&str>) -> Result<AppConfig, AppError> {
+        let Some(name) = mode else {
+            return Ok(self.clone());
+        };
+        let mode = self.modes.get(name).ok_or_else(|| {
+            AppError::InvalidRequest(format!(
+                "Unknown mode '{}'. Available modes: {}",
+                name,
+                self.modes.keys().cloned().collect::<Vec<_>>().join(", ")
+            ))
+        })?;
+
+        let mut config = self.clone();
+        if mode.prompt_template.is_some() {
+            config.prompt_template = mode.prompt_template.clone();
+        }
+        if mode.system_prompt.is_some() {
+            config.system_prompt = mode.system_prompt.clone();
+        }
+        if let Some(model_name) = &mode.model_name {
+            config.model_name = model_name.clone();
+        }
+        if let Some(Value::Object(mode_params)) = &mode.llm_params {
+            let mut params = match config.llm_params.take() {
+                Some(Value::Object(params)) => params,
+                _ => serde_json::Map::new(),
+            };
+            params.extend(mode_params.clone());
+            config.llm_params = Some(Value::Object(params));
+        }
+        if let Some(preserve_language) = mode.preserve_language {
+            config.language.enabled = preserve_language;
+        }
+        Ok(config)
+    }
+
+    /// Set one entry of `llm_params`, creating the table if needed
+    pub fn set_llm_param(&mut self, key: &str, value: Value) {
+        let mut params = match self.llm_params.take() {
+            Some(Value::Object(params)) => params,
+            _ => serde_json::Map::new(),
+        };
+        params.insert(key.to_string(), value);
+        self.llm_params = Some(Value::Object(params));
+    }
+
+    /// The primary backend followed by each fallback, as complete configs
+    pub fn backends(&self) -> Vec<AppConfig> {
+        let mut backends = vec![self.clone()];
+        for fallback in &self.fallbacks {
+            let mut backend = self.clone();
+            backend.provider = fallback.provider;
+            backend.llm_url = fallback.llm_url.clone();
+            backend.model_name = fallback.model_name.clone();
+            if fallback.llm_params.is_some() {
+                backend.llm_params = fallback.llm_params.clone();
+            }
+            if fallback.llm_api_key.is_some() {
+                backend.llm_api_key = fallback.llm_api_key.clone();
+            }
+            backend.fallbacks.clear();
+            backends.push(backend);
+        }
+        backends
+    }
+}
+
+/// Prompt template written to a newly created config file
+const DEFAULT_PROMPT_TEMPLATE: &str = r#"Improve the provided text input for clarity, grammar, and overall communication, ensuring it's fluently expressed in English.
+
+# Steps
+
+1. **Identify Errors**: Examine the input text for grammatical, spelling, and punctuation errors.
+2. **Improve Clarity**: Rephrase sentences to improve clarity and flow while maintaining the original meaning.
+3. **Ensure Fluency**: Adjust the text to sound natural and fluent in English.
+4. **Check Consistency**: Ensure the tone remains consistent throughout the text.
+5. **Produce Improved Text**: Deliver the revised version focusing on correctness and readability.
+
+# Output Format
+
+- Provide a single improved version of the input text as a plain sentence or paragraph. 
+- Do not include the original text in the response.
+
+# Examples
+
+**Example 1:**
+
+- **Input**: "My English is no such god. Howe ar you?"
+- **Output**: "My English isn't very good. How are you?"
+
+**Example 2:**
+
+- **Input**: "Weather here change alot. I not used it."
+- **Output**: "The weather here changes a lot. I'm not used to it."
+
+# Notes
+
+- Maintain the main idea or intent of the original input.
+- Focus on improving readability and grammatical correctness.
+- Consider cultural nuances if necessary to preserve meaning.
+
+{{input}}
+"#;
+
+/// Mask most of a secret so it can be logged safely
+pub fn mask_key(key: &str) -> String {
+    if key.len() > 8 {
+        format!("{}...{}", &key[..4], &key[key.len() - 4..])
+    } else {
+        "[too short]".to_string()
     }
 }
 
+/// Contents of the config file created on first start
+fn default_config_toml(config: &AppConfig) -> String {
+    format!(
+        r#"# Default LLM Service Configuration
+# Created because the file was missing. Review and adjust as needed.
+
+port = {}
+provider = "openai" # LLM backend: "openai", "ollama", "anthropic" or "openai_compatible"
+llm_url = "{}" # OpenAI API endpoint
+model_name = "{}"
+
+# Authentication for OpenAI API
+# Can also be set via environment variables: OPENAI_API_KEY, OPENAI_ORG_ID, OPENAI_PROJECT_ID
+openai_api_key = "" # Your OpenAI API key (required)
+#openai_org_id = "org-EVPAPa0e5FSeelWefXSvJr8r" # Optional: Your OpenAI Organization ID
+#openai_project_id = "" # Optional: Your OpenAI Project ID
+
+# Authentication for Anthropic API (used when provider = "anthropic")
+# Can also be set via the ANTHROPIC_API_KEY environment variable
+#anthropic_api_key = ""
+#anthropic_version = "2023-06-01" # Optional: value of the anthropic-version header
+
+# Optional bearer token for OpenAI-compatible servers (provider = "openai_compatible")
+#llm_api_key = ""
+
+# Prompt template. {{{{input}}}} is replaced with the user's text (required); {{{{date}}}}
+# and variables sent with the request, such as {{{{app}}}} or {{{{language}}}}, are also available
+prompt_template = """{}"""
+
+# Optional system prompt, replacing the built-in one. Set it to "" to send no
+# system message, for models that ignore or reject the system role.
+#system_prompt = "You are a text improvement tool that corrects grammar and improves clarity without adding conversational elements. Follow the instructions exactly."
+
+# Optional named modes, selected with the "mode" field of a request.
+# Each mode can override prompt_template, system_prompt, model_name, llm_params and
+# preserve_language.
+#[modes.shorter]
+#description = "Make the text shorter"
+#prompt_template = "Rewrite the following text to be as short as possible without losing meaning:\n\n{{{{input}}}}"
+#llm_params = {{ temperature = 0.3 }}
+
+# Optional fallback backends, tried in order when the one before is unreachable
+# or returns a server error (5xx)
+#[[fallbacks]]
+#provider = "ollama"
+#llm_url = "http://localhost:11434/api/chat"
+#model_name = "mistral:latest"
+
+# Retry policy for rate limits (429) and transient server errors (5xx)
+[retry]
+max_attempts = 3      # Total attempts including the first; 1 disables retries
+base_delay_ms = 500   # Delay before the first retry, doubled for each further attempt
+max_delay_ms = 10000  # Longest single delay; a longer Retry-After fails immediately
+
+# Long inputs are split at paragraph and sentence boundaries into chunks that
+# are rewritten separately and cached per chunk
+[chunking]
+enabled = true
+max_chunk_tokens = 400  # Chunk budget, estimated at about four characters per token
+max_concurrency = 1     # Chunks processed at once; 1 keeps requests sequential
+
+# Length limit for each LLM response (per chunk for long inputs)
+[response_limit]
+max_length = 2000        # 0 disables the limit
+unit = "chars"           # "chars" or "tokens"
+on_overflow = "truncate" # "reject", "truncate" (at a sentence end) or "warn"
+
+# Code blocks, inline code, URLs, Jira keys and {{{{placeholders}}}} are swapped for
+# opaque tokens before the text is sent and restored in the response
+[masking]
+enabled = true
+max_attempts = 2  # Total attempts when the model drops or alters a protected span
+
+# Team glossary: terms to keep, preferred spellings and forbidden replacements.
+# Terms can be listed here or in a TOML or CSV file (columns: term,variants,forbidden;
+# lists separated by ";")
+[glossary]
+#path = "glossary.toml" # Relative to this directory
+auto_correct = false    # Fix spellings and forbidden replacements instead of only reporting them
+#[[glossary.terms]]
+#term = "Prod"
+#forbidden = ["production"]
+
+# Emails, phone numbers and custom patterns are replaced with placeholders before
+# the text is sent to a backend that is not local, and put back afterwards
+[redaction]
+enabled = true
+emails = true
+phone_numbers = true
+secrets = "block"  # API keys, tokens and private keys: "block" the request or "redact" them
+local_hosts = []   # Hosts treated as local besides localhost, e.g. ["gpu-box.lan"]
+#[[redaction.rules]]
+#name = "customer"
+#pattern = "\\b(Acme|Globex)\\b"
+
+# Clean-up of the model output, in this order. Steps: "remove_preamble" ("Here is
+# the improved text:"), "unfence" (```), "strip_quotes", "normalize_whitespace"
+# and "preserve_whitespace" (keep the selection's leading and trailing whitespace)
+[post_process]
+steps = ["remove_preamble", "unfence", "strip_quotes", "normalize_whitespace", "preserve_whitespace"]
+preambles = []  # More lead-ins to remove, e.g. ["Voici le texte corrigé :"]
+
+# The language of the request text is detected and available to prompt templates
+# as {{{{input_language}}}}. A response in another language is requested again with a
+# stricter instruction, and reported as language_mismatch if it still differs.
+[language]
+enabled = true
+languages = []    # ISO 639-3 codes to tell apart, e.g. ["eng", "ukr", "deu"]; all when empty
+min_letters = 20  # Shorter texts are not checked
+max_attempts = 2  # Total attempts when the model switches languages
+
+# Response caching configuration
+[cache]
+enabled = true        # Enable or disable the response cache
+ttl_days = 30         # Number of days to keep entries in the cache
+max_size_mb = 100     # Maximum size of the cache in MB; least recently used entries are evicted beyond it (0 = no limit)
+invalidate_changed_params = false  # On startup, remove entries made with another endpoint or other llm_params
+# How the text is normalised for the cache key: "trim", "collapse_whitespace", "nfc", "fold_quotes"
+normalize = ["trim", "nfc"]
+
+# Optional parameters for the LLM API request body
+[llm_params]
+temperature = 0.7
+max_output_tokens = 500
+top_p = 1
+"#,
+        config.port, config.llm_url, config.model_name, DEFAULT_PROMPT_TEMPLATE
+    )
+}
+
 // --- Configuration Loading ---
 pub fn find_config_path() -> Result<PathBuf, AppError> {
     // Only use ~/.config/writer_ai_service as the config directory
@@ -65,59 +427,69 @@ pub fn load_config() -> Result<AppConfig, AppError> {
         .add_source(Environment::with_prefix("WRITER_AI_SERVICE").separator("__"))
         .build()?;
 
-    let app_config: AppConfig = config_loader.try_deserialize()?;
+    let mut app_config: AppConfig = config_loader.try_deserialize()?;
+    app_config.validate_templates()?;
+    app_config.glossary.load(&config_dir)?;
+    app_config.redaction.validate()?;
+    app_config.language.validate()?;
     
     // Load auth variables from environment if not in config
     let mut updated_config = app_config.clone();
     let mut config_updated = false;
 
     // --- Handle OpenAI API Key ---
-    if updated_config.openai_api_key.is_none() {
-        if let Ok(api_key) = std::env::var("OPENAI_API_KEY") {
-            // Mask most of the key for security in logs
-            let masked_key = if api_key.len() > 8 {
-                format!("{}...{}", &api_key[..4], &api_key[api_key.len()-4..])
+    match &updated_config.openai_api_key {
+        Some(api_key) => {
+            info!("Using OPENAI_API_KEY from config: {}", mask_key(api_key));
+        }
+        None => {
+            if let Ok(api_key) = std::env::var("OPENAI_API_KEY") {
+                // Mask most of the key for security in logs
+                info!("Using OPENAI_API_KEY from environment: {}", mask_key(&api_key));
+                updated_config.openai_api_key = Some(api_key);
+                config_updated = true;
             } else {
-                "[too short]".to_string()
-            };
-            info!("Using OPENAI_API_KEY from environment: {}", masked_key);
-            updated_config.openai_api_key = Some(api_key);
-            config_updated = true;
-        } else {
-            warn!("No OPENAI_API_KEY found in config or environment");
+                warn!("No OPENAI_API_KEY found in config or environment");
+            }
         }
-    } else {
-        let api_key = updated_config.openai_api_key.as_ref().unwrap();
-        let masked_key = if api_key.len() > 8 {
-            format!("{}...{}", &api_key[..4], &api_key[api_key.len()-4..])
-        } else {
-            "[too short]".to_string()
-        };
-        info!("Using OPENAI_API_KEY from config: {}", masked_key);
     }
     
     // --- Handle OpenAI Organization ID ---
-    if updated_config.openai_org_id.is_none() {
-        if let Ok(org_id) = std::env::var("OPENAI_ORG_ID") {
-            info!("Using OPENAI_ORG_ID from environment: {}", org_id);
-            updated_config.openai_org_id = Some(org_id);
-            config_updated = true;
+    match &updated_config.openai_org_id {
+        Some(org_id) => info!("Using OPENAI_ORG_ID from config: {}", org_id),
+        None => {
+            if let Ok(org_id) = std::env::var("OPENAI_ORG_ID") {
+                info!("Using OPENAI_ORG_ID from environment: {}", org_id);
+                updated_config.openai_org_id = Some(org_id);
+                config_updated = true;
+            }
         }
-    } else {
-        info!("Using OPENAI_ORG_ID from config: {}", 
-              updated_config.openai_org_id.as_ref().unwrap());
     }
     
     // --- Handle OpenAI Project ID ---
-    if updated_config.openai_project_id.is_none() {
-        if let Ok(project_id) = std::env::var("OPENAI_PROJECT_ID") {
-            info!("Using OPENAI_PROJECT_ID from environment: {}", project_id);
-            updated_config.openai_project_id = Some(project_id);
-            config_updated = true;
+    match &updated_config.openai_project_id {
+        Some(project_id) => info!("Using OPENAI_PROJECT_ID from config: {}", project_id),
+        None => {
+            if let Ok(project_id) = std::env::var("OPENAI_PROJECT_ID") {
+                info!("Using OPENAI_PROJECT_ID from environment: {}", project_id);
+                updated_config.openai_project_id = Some(project_id);
+                config_updated = true;
+            }
+        }
+    }
+    
+    // --- Handle Anthropic API Key ---
+    match &updated_config.anthropic_api_key {
+        Some(api_key) => {
+            info!("Using ANTHROPIC_API_KEY from config: {}", mask_key(api_key));
+        }
+        None => {
+            if let Ok(api_key) = std::env::var("ANTHROPIC_API_KEY") {
+                info!("Using ANTHROPIC_API_KEY from environment: {}", mask_key(&api_key));
+                updated_config.anthropic_api_key = Some(api_key);
+                config_updated = true;
+            }
         }
-    } else {
-        info!("Using OPENAI_PROJECT_ID from config: {}", 
-              updated_config.openai_project_id.as_ref().unwrap());
     }
     
     // Return the updated config if any changes were made
@@ -137,71 +509,7 @@ pub fn load_config() -> Result<AppConfig, AppError> {
         }
 
         // Use potentially overridden defaults for the initial creation
-        let default_toml_content = format!(
-            r#"# Default LLM Service Configuration
-# Created because the file was missing. Review and adjust as needed.
-
-port = {}
-llm_url = "{}" # OpenAI API endpoint
-model_name = "{}"
-
-# Authentication for OpenAI API
-# Can also be set via environment variables: OPENAI_API_KEY, OPENAI_ORG_ID, OPENAI_PROJECT_ID
-openai_api_key = "" # Your OpenAI API key (required)
-#openai_org_id = "org-EVPAPa0e5FSeelWefXSvJr8r" # Optional: Your OpenAI Organization ID
-#openai_project_id = "" # Optional: Your OpenAI Project ID
-
-# Response caching configuration
-[cache]
-enabled = true        # Enable or disable the response cache
-ttl_days = 30         # Number of days to keep entries in the cache
-max_size_mb = 100     # Maximum size of the cache in MB
-
-# Optional parameters for the LLM API request body
-[llm_params]
-temperature = 0.7
-max_output_tokens = 500
-top_p = 1
-
-# Optional prompt template - uses {{input}} as placeholder for user text
-prompt_template = """Improve the provided text input for clarity, grammar, and overall communication, ensuring it's fluently expressed in English.
-
-# Steps
-
-1. **Identify Errors**: Examine the input text for grammatical, spelling, and punctuation errors.
-2. **Improve Clarity**: Rephrase sentences to improve clarity and flow while maintaining the original meaning.
-3. **Ensure Fluency**: Adjust the text to sound natural and fluent in English.
-4. **Check Consistency**: Ensure the tone remains consistent throughout the text.
-5. **Produce Improved Text**: Deliver the revised version focusing on correctness and readability.
-
-# Output Format
-
-- Provide a single improved version of the input text as a plain sentence or paragraph. 
-- Do not include the original text in the response.
-
-# Examples
-
-**Example 1:**
-
-- **Input**: "My English is no such god. Howe ar you?"
-- **Output**: "My English isn't very good. How are you?"
-
-**Example 2:**
-
-- **Input**: "Weather here change alot. I not used it."
-- **Output**: "The weather here changes a lot. I'm not used to it."
-
-# Notes
-
-- Maintain the main idea or intent of the original input.
-- Focus on improving readability and grammatical correctness.
-- Consider cultural nuances if necessary to preserve meaning.
-
-{{input}}
-"""
-"#,
-            app_config.port, app_config.llm_url, app_config.model_name
-        );
+        let default_toml_content = default_config_toml(&app_config);
 
         std::fs::write(&config_file_path, default_toml_content)?;
         info!("Created default config file at {:?}", config_file_path);
@@ -285,23 +593,14 @@ mod tests {
         // but not actually run the real code that creates files. 
         // In a real project, we'd refactor the code to be more testable.
         
-        // For now, let's create our own config manually
-        let config = AppConfig {
-            port: 8989,
-            llm_url: "https://api.openai.com/v1/responses".to_string(),
-            model_name: "gpt-4o".to_string(),
-            llm_params: None,
-            prompt_template: None,
-            openai_api_key: None,
-            openai_org_id: None,
-            openai_project_id: None,
-            cache: default_cache_config(),
-        };
+        // The built-in defaults, as used when nothing overrides them
+        let config = AppConfig::default();
         
         // Just verify that our default values match expectations
         assert_eq!(config.port, 8989, "Default port should be 8989");
         assert_eq!(config.llm_url, "https://api.openai.com/v1/responses", "Default LLM URL should be OpenAI API");
         assert_eq!(config.model_name, "gpt-4o", "Default model should be gpt-4o");
+        assert_eq!(config.provider, crate::providers::ProviderKind::OpenAi, "Default provider should be OpenAI");
     }
 
     #[test]
@@ -328,4 +627,212 @@ mod tests {
         assert_eq!(config.llm_url, "https://custom-llm-api.example.com", "LLM URL should be overridden");
         assert_eq!(config.model_name, "custom-model", "Model name should be overridden");
     }
+
+    #[test]
+    fn test_load_config_provider() {
+        // The provider is explicit, regardless of what the URL looks like
+        let config_loader = config::Config::builder()
+            .set_default("port", 8989).unwrap()
+            .set_default("llm_url", "https://api.openai.com/v1/responses").unwrap()
+            .set_default("model_name", "gpt-4o").unwrap()
+            .set_override("provider", "ollama").unwrap()
+            .set_override("llm_url", "http://gpu-box.internal:8080/api/chat").unwrap()
+            .build()
+            .unwrap();
+        let config: AppConfig = config_loader.try_deserialize().unwrap();
+
+        assert_eq!(config.provider, crate::providers::ProviderKind::Ollama);
+        assert_eq!(config.llm_url, "http://gpu-box.internal:8080/api/chat");
+    }
+
+    #[test]
+    fn test_load_config_fallbacks() {
+        let toml = r#"
+            port = 8989
+            provider = "openai"
+            llm_url = "https://api.openai.com/v1/responses"
+            model_name = "gpt-4o"
+            llm_api_key = "primary-token"
+
+            [llm_params]
+            temperature = 0.2
+
+            [[fallbacks]]
+            provider = "ollama"
+            llm_url = "http://localhost:11434/api/chat"
+            model_name = "mistral:latest"
+
+            [[fallbacks]]
+            provider = "openai_compatible"
+            llm_url = "http://localhost:8080/v1/chat/completions"
+            model_name = "qwen2.5-7b-instruct"
+            llm_params = { temperature = 0.5 }
+            llm_api_key = "local-token"
+        "#;
+        let config: AppConfig = config::Config::builder()
+            .add_source(config::File::from_str(toml, config::FileFormat::Toml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+
+        let backends = config.backends();
+        assert_eq!(backends.len(), 3);
+        assert_eq!(backends[0].model_name, "gpt-4o");
+
+        assert_eq!(backends[1].provider, crate::providers::ProviderKind::Ollama);
+        assert_eq!(backends[1].llm_url, "http://localhost:11434/api/chat");
+        assert_eq!(backends[1].model_name, "mistral:latest");
+        assert_eq!(backends[1].llm_params, Some(serde_json::json!({ "temperature": 0.2 })));
+        assert_eq!(backends[1].llm_api_key.as_deref(), Some("primary-token"));
+        assert!(backends[1].fallbacks.is_empty());
+
+        assert_eq!(backends[2].provider, crate::providers::ProviderKind::OpenAiCompatible);
+        assert_eq!(backends[2].llm_params, Some(serde_json::json!({ "temperature": 0.5 })));
+        assert_eq!(backends[2].llm_api_key.as_deref(), Some("local-token"));
+    }
+
+    #[test]
+    fn test_with_mode() {
+        let toml = r#"
+            port = 8989
+            llm_url = "https://api.openai.com/v1/responses"
+            model_name = "gpt-4o"
+            prompt_template = "Fix: {input}"
+
+            [llm_params]
+            temperature = 0.7
+            top_p = 1
+
+            [modes.formal]
+            description = "Make it formal"
+            prompt_template = "Make this formal: {input}"
+            system_prompt = "You are a formal editor."
+            model_name = "gpt-4o-mini"
+            llm_params = { temperature = 0.2 }
+
+            [modes.shorter]
+            prompt_template = "Shorten: {input}"
+        "#;
+        let config: AppConfig = config::Config::builder()
+            .add_source(config::File::from_str(toml, config::FileFormat::Toml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+
+        assert_eq!(config.modes.keys().collect::<Vec<_>>(), vec!["formal", "shorter"]);
+
+        let formal = config.with_mode(Some("formal")).unwrap();
+        assert_eq!(formal.prompt_template.as_deref(), Some("Make this formal: {input}"));
+        assert_eq!(formal.system_prompt.as_deref(), Some("You are a formal editor."));
+        assert_eq!(formal.model_name, "gpt-4o-mini");
+        assert_eq!(formal.llm_params, Some(serde_json::json!({ "temperature": 0.2, "top_p": 1 })));
+
+        // Unset fields keep the top-level values
+        let shorter = config.with_mode(Some("shorter")).unwrap();
+        assert_eq!(shorter.prompt_template.as_deref(), Some("Shorten: {input}"));
+        assert_eq!(shorter.system_prompt, None);
+        assert_eq!(shorter.model_name, "gpt-4o");
+        assert_eq!(shorter.llm_params, config.llm_params);
+
+        let default = config.with_mode(None).unwrap();
+        assert_eq!(default.prompt_template.as_deref(), Some("Fix: {input}"));
+
+        match config.with_mode(Some("poetic")) {
+            Err(AppError::InvalidRequest(msg)) => {
+                assert!(msg.contains("Unknown mode 'poetic'"));
+                assert!(msg.contains("formal, shorter"));
+            }
+            other => panic!("Expected InvalidRequest, got: {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_default_config_toml() {
+        let toml = r#"
+            port = 9000
+            llm_url = "https://api.openai.com/v1/responses"
+            model_name = "gpt-4o-mini"
+        "#;
+        let defaults: AppConfig = config::Config::builder()
+            .add_source(config::File::from_str(toml, config::FileFormat::Toml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+
+        // The file written on first start loads back with a valid top-level template
+        let written = default_config_toml(&defaults);
+        let config: AppConfig = config::Config::builder()
+            .add_source(config::File::from_str(&written, config::FileFormat::Toml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+
+        assert_eq!(config.port, 9000);
+        assert_eq!(config.model_name, "gpt-4o-mini");
+        assert_eq!(config.prompt_template.as_deref(), Some(DEFAULT_PROMPT_TEMPLATE));
+        assert!(config.prompt_template.unwrap().trim_end().ends_with("{{input}}"));
+        assert!(config.llm_params.unwrap().get("prompt_template").is_none());
+    }
+
+    #[test]
+    fn test_validate_templates() {
+        let toml = r#"
+            port = 8989
+            llm_url = "https://api.openai.com/v1/responses"
+            model_name = "gpt-4o"
+            prompt_template = "Fix: {{input}}"
+
+            [modes.broken]
+            prompt_template = "Make this formal."
+        "#;
+        let config: AppConfig = config::Config::builder()
+            .add_source(config::File::from_str(toml, config::FileFormat::Toml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+
+        match config.validate_templates() {
+            Err(AppError::Config(e)) => {
+                let msg = e.to_string();
+                assert!(msg.contains("Mode 'broken'"));
+                assert!(msg.contains("no {{input}} placeholder"));
+            }
+            other => panic!("Expected Config error, got: {:?}", other),
+        }
+
+        let mut config = config;
+        config.modes.clear();
+        assert!(config.validate_templates().is_ok());
+    }
+
+    #[test]
+    fn test_cache_defaults_without_cache_table() {
+        let toml = r#"
+            port = 8989
+            llm_url = "https://api.openai.com/v1/responses"
+            model_name = "gpt-4o"
+        "#;
+        let config: AppConfig = config::Config::builder()
+            .add_source(config::File::from_str(toml, config::FileFormat::Toml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+        assert_eq!(config.cache.normalize, crate::cache::default_normalize());
+
+        // A selection with a trailing newline hits the entry of the same text without one
+        let temp_dir = TempDir::new().unwrap();
+        let cache_manager =
+            crate::cache::CacheManager::new(temp_dir.path().join("test_cache.sled"), config.cache).unwrap();
+        cache_manager.store("I has a apple", "I have an apple.", "gpt-4o", "abc123").unwrap();
+        assert_eq!(
+            cache_manager.lookup("I has a apple\n", "gpt-4o", "abc123").unwrap().as_deref(),
+            Some("I have an apple.\n")
+        );
+    }
 }
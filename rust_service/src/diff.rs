@@ -0,0 +1,368 @@
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::ops::Range;
+
+/// Largest LCS table we build; beyond it the differing middle becomes one edit
+const MAX_TABLE_CELLS: usize = 4_000_000;
+
+/// Kind of a change between the original and the rewritten text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EditKind {
+    Insert,
+    Delete,
+    Replace,
+}
+
+/// One change, with offsets counted in Unicode characters
+///
+/// `original_start..original_end` is the changed range of the original text and
+/// `revised_start..revised_end` the range that replaces it in the rewritten one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiffSpan {
+    pub kind: EditKind,
+    pub original_start: usize,
+    pub original_end: usize,
+    pub revised_start: usize,
+    pub revised_end: usize,
+    pub original: String,
+    pub revised: String,
+    /// Character-level edits inside a replaced span, with the same absolute offsets
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub char_edits: Vec<DiffSpan>,
+}
+
+/// A word, a run of whitespace or a single punctuation character
+#[derive(Debug)]
+struct Token<'a> {
+    text: &'a str,
+    byte_start: usize,
+    char_start: usize,
+}
+
+#[derive(PartialEq)]
+enum TokenClass {
+    Word,
+    Space,
+    Other,
+}
+
+fn token_class(c: char) -> TokenClass {
+    if c.is_alphanumeric() || c == '_' {
+        TokenClass::Word
+    } else if c.is_whitespace() {
+        TokenClass::Space
+    } else {
+        TokenClass::Other
+    }
+}
+
+fn tokenize(text: &str) -> Vec<Token<'_>> {
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut previous = None;
+
+    for (char_index, (byte_index, c)) in text.char_indices().enumerate() {
+        let class = token_class(c);
+        let continues = class != TokenClass::Other && previous.as_ref() == Some(&class);
+        match tokens.last_mut() {
+            Some(last) if continues => last.text = &text[last.byte_start..byte_index + c.len_utf8()],
+            _ => tokens.push(Token {
+                text: &text[byte_index..byte_index + c.len_utf8()],
+                byte_start: byte_index,
+                char_start: char_index,
+            }),
+        }
+        previous = Some(class);
+    }
+    tokens
+}
+
+/// Ranges of `a` and `b` that differ, in order, based on a longest common subsequence
+fn changed_ranges<T: PartialEq>(a: &[T], b: &[T]) -> Vec<(Range<usize>, Range<usize>)> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+    let (n, m) = (a_mid.len(), b_mid.len());
+
+    // Matching index pairs inside the middle part
+    let mut matches = Vec::new();
+    if n > 0 && m > 0 && (n + 1) * (m + 1) <= MAX_TABLE_CELLS {
+        // lengths[i][j]: LCS length of a_mid[i..] and b_mid[j..]
+        let mut lengths = vec![0u32; (n + 1) * (m + 1)];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lengths[i * (m + 1) + j] = if a_mid[i] == b_mid[j] {
+                    lengths[(i + 1) * (m + 1) + j + 1] + 1
+                } else {
+                    lengths[(i + 1) * (m + 1) + j].max(lengths[i * (m + 1) + j + 1])
+                };
+            }
+        }
+
+        let (mut i, mut j) = (0, 0);
+        while i < n && j < m {
+            if a_mid[i] == b_mid[j] {
+                matches.push((prefix + i, prefix + j));
+                i += 1;
+                j += 1;
+            } else if lengths[(i + 1) * (m + 1) + j] >= lengths[i * (m + 1) + j + 1] {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+    }
+
+    let mut ranges = Vec::new();
+    let (mut a_pos, mut b_pos) = (prefix, prefix);
+    for (a_match, b_match) in matches.into_iter().chain([(a.len() - suffix, b.len() - suffix)]) {
+        if a_match > a_pos || b_match > b_pos {
+            ranges.push((a_pos..a_match, b_pos..b_match));
+        }
+        a_pos = a_match + 1;
+        b_pos = b_match + 1;
+    }
+    ranges
+}
+
+fn span_kind(original: &str, revised: &str) -> EditKind {
+    match (original.is_empty(), revised.is_empty()) {
+        (true, _) => EditKind::Insert,
+        (_, true) => EditKind::Delete,
+        _ => EditKind::Replace,
+    }
+}
+
+/// Character-level edits between two replaced strings starting at the given offsets
+fn char_edits(original: &str, revised: &str, original_start: usize, revised_start: usize) -> Vec<DiffSpan> {
+    let a: Vec<char> = original.chars().collect();
+    let b: Vec<char> = revised.chars().collect();
+
+    changed_ranges(&a, &b)
+        .into_iter()
+        .map(|(a_range, b_range)| {
+            let original: String = a[a_range.clone()].iter().collect();
+            let revised: String = b[b_range.clone()].iter().collect();
+            DiffSpan {
+                kind: span_kind(&original, &revised),
+                original_start: original_start + a_range.start,
+                original_end: original_start + a_range.end,
+                revised_start: revised_start + b_range.start,
+                revised_end: revised_start + b_range.end,
+                original,
+                revised,
+                char_edits: Vec::new(),
+            }
+        })
+        .collect()
+}
+
+/// Word-level changes from `original` to `revised`
+///
+/// Changes separated only by whitespace are reported as one span, so that a
+/// reworded phrase reads as a single edit.
+pub fn diff(original: &str, revised: &str) -> Vec<DiffSpan> {
+    let a = tokenize(original);
+    let b = tokenize(revised);
+    let a_texts: Vec<&str> = a.iter().map(|token| token.text).collect();
+    let b_texts: Vec<&str> = b.iter().map(|token| token.text).collect();
+
+    let mut ranges: Vec<(Range<usize>, Range<usize>)> = Vec::new();
+    for (a_range, b_range) in changed_ranges(&a_texts, &b_texts) {
+        if let Some((last_a, last_b)) = ranges.last_mut() {
+            let between = &a[last_a.end..a_range.start];
+            if between.iter().all(|token| token.text.trim().is_empty()) {
+                last_a.end = a_range.end;
+                last_b.end = b_range.end;
+                continue;
+            }
+        }
+        ranges.push((a_range, b_range));
+    }
+
+    // Byte and character position of a token boundary
+    fn position(tokens: &[Token], text: &str, index: usize) -> (usize, usize) {
+        match tokens.get(index) {
+            Some(token) => (token.byte_start, token.char_start),
+            None => (text.len(), tokens.last().map_or(0, |t| t.char_start + t.text.chars().count())),
+        }
+    }
+
+    ranges
+        .into_iter()
+        .map(|(mut a_range, mut b_range)| {
+            // The LCS may pair up a different space than the one a reader would;
+            // drop tokens both sides share at the edges of the span
+            while !a_range.is_empty() && !b_range.is_empty() && a_texts[a_range.end - 1] == b_texts[b_range.end - 1] {
+                a_range.end -= 1;
+                b_range.end -= 1;
+            }
+            while !a_range.is_empty() && !b_range.is_empty() && a_texts[a_range.start] == b_texts[b_range.start] {
+                a_range.start += 1;
+                b_range.start += 1;
+            }
+
+            let (a_start, original_start) = position(&a, original, a_range.start);
+            let (a_end, original_end) = position(&a, original, a_range.end);
+            let (b_start, revised_start) = position(&b, revised, b_range.start);
+            let (b_end, revised_end) = position(&b, revised, b_range.end);
+            let (original_text, revised_text) = (&original[a_start..a_end], &revised[b_start..b_end]);
+
+            let kind = span_kind(original_text, revised_text);
+            let char_edits = match kind {
+                EditKind::Replace => char_edits(original_text, revised_text, original_start, revised_start),
+                _ => Vec::new(),
+            };
+            DiffSpan {
+                kind,
+                original_start,
+                original_end,
+                revised_start,
+                revised_end,
+                original: original_text.to_string(),
+                revised: revised_text.to_string(),
+                char_edits,
+            }
+        })
+        .collect()
+}
+
+/// How alike two texts are, from 0.0 (nothing in common) to 1.0 (identical)
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+
+    let changed: usize = changed_ranges(&a, &b).iter().map(|(a_range, _)| a_range.len()).sum();
+    2.0 * (a.len() - changed) as f64 / (a.len() + b.len()) as f64
+}
+
+/// Hex SHA-256 of a text, identifying the original a change set was computed for
+pub fn text_hash(text: &str) -> String {
+    format!("{:x}", Sha256::digest(text.as_bytes()))
+}
+
+/// Apply the accepted spans (by index) to the original text, keeping the
+/// original wording everywhere else
+pub fn apply(original: &str, spans: &[DiffSpan], accepted: &BTreeSet<usize>) -> String {
+    let mut merged = String::with_capacity(original.len());
+    let mut chars = original.chars();
+    let mut position = 0;
+
+    for (index, span) in spans.iter().enumerate() {
+        merged.extend(chars.by_ref().take(span.original_start - position));
+        let kept: String = chars.by_ref().take(span.original_end - span.original_start).collect();
+        if accepted.contains(&index) {
+            merged.push_str(&span.revised);
+        } else {
+            merged.push_str(&kept);
+        }
+        position = span.original_end;
+    }
+    merged.extend(chars);
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(spans: &[DiffSpan]) -> Vec<(EditKind, &str, &str)> {
+        spans.iter().map(|s| (s.kind, s.original.as_str(), s.revised.as_str())).collect()
+    }
+
+    #[test]
+    fn test_identical_texts() {
+        assert!(diff("Nothing changed here.", "Nothing changed here.").is_empty());
+        assert!(diff("", "").is_empty());
+    }
+
+    #[test]
+    fn test_word_level_spans() {
+        let spans = diff("My English is no such god. Howe ar you?", "My English isn't very good. How are you?");
+
+        assert_eq!(
+            summary(&spans),
+            vec![
+                (EditKind::Replace, "is no such god", "isn't very good"),
+                (EditKind::Replace, "Howe ar", "How are"),
+            ]
+        );
+        assert_eq!((spans[0].original_start, spans[0].original_end), (11, 25));
+        assert_eq!((spans[0].revised_start, spans[0].revised_end), (11, 26));
+    }
+
+    #[test]
+    fn test_insert_and_delete() {
+        let spans = diff("I like tea.", "I really like tea!");
+        assert_eq!(
+            summary(&spans),
+            vec![(EditKind::Insert, "", "really "), (EditKind::Replace, ".", "!")]
+        );
+        assert_eq!((spans[0].original_start, spans[0].original_end), (2, 2));
+
+        let spans = diff("This is very very good.", "This is very good.");
+        assert_eq!(summary(&spans), vec![(EditKind::Delete, "very ", "")]);
+    }
+
+    #[test]
+    fn test_char_edits_inside_replace() {
+        let spans = diff("The weather here change alot.", "The weather here changes a lot.");
+
+        assert_eq!(summary(&spans), vec![(EditKind::Replace, "change alot", "changes a lot")]);
+        let chars: Vec<_> = spans[0]
+            .char_edits
+            .iter()
+            .map(|e| (e.kind, e.original_start, e.revised.as_str()))
+            .collect();
+        assert_eq!(chars, vec![(EditKind::Insert, 23, "s"), (EditKind::Insert, 25, " ")]);
+    }
+
+    #[test]
+    fn test_offsets_count_characters() {
+        let spans = diff("Привет мир", "Привет, мир");
+
+        assert_eq!(summary(&spans), vec![(EditKind::Insert, "", ",")]);
+        assert_eq!((spans[0].original_start, spans[0].revised_start, spans[0].revised_end), (6, 6, 7));
+    }
+
+    #[test]
+    fn test_apply_accepted_spans() {
+        let original = "Weather here change alot. I not used it.";
+        let revised = "The weather here changes a lot. I'm not used to it.";
+        let spans = diff(original, revised);
+        assert_eq!(spans.len(), 4);
+
+        assert_eq!(apply(original, &spans, &BTreeSet::new()), original);
+        assert_eq!(apply(original, &spans, &(0..spans.len()).collect()), revised);
+        assert_eq!(
+            apply(original, &spans, &BTreeSet::from([1, 3])),
+            "Weather here changes a lot. I not used to it."
+        );
+    }
+
+    #[test]
+    fn test_similarity() {
+        assert_eq!(similarity("", ""), 1.0);
+        assert_eq!(similarity("same text", "same text"), 1.0);
+        assert_eq!(similarity("abc", "xyz"), 0.0);
+        assert_eq!(similarity("abcd", "abxd"), 0.75);
+    }
+
+    #[test]
+    fn test_text_hash() {
+        assert_eq!(
+            text_hash(""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_ne!(text_hash("a"), text_hash("b"));
+    }
+}
@@ -17,6 +17,9 @@ pub enum AppError {
     SerdeJson(#[from] serde_json::Error),
     #[error("LLM API returned an error: {0}")]
     LlmApiError(String),
+    /// The backend could not be reached, timed out or returned a 5xx status
+    #[error("LLM backend unavailable: {0}")]
+    LlmUnavailable(String),
     #[error("IO Error: {0}")]
     Io(#[from] std::io::Error),
     // Removed unused variant: MissingConfigDir
@@ -26,6 +29,11 @@ pub enum AppError {
     Internal(String),
     #[error("Cache error: {0}")]
     CacheError(String),
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    /// The request refers to a text that has changed since
+    #[error("Conflict: {0}")]
+    Conflict(String),
 }
 
 // Convert AppError into an HTTP response
@@ -45,6 +53,7 @@ impl IntoResponse for AppError {
                 format!("JSON processing error: {}", e),
             ),
             AppError::LlmApiError(msg) => (StatusCode::BAD_GATEWAY, msg.clone()),
+            AppError::LlmUnavailable(msg) => (StatusCode::BAD_GATEWAY, msg.clone()),
             AppError::Io(e) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 format!("IO error: {}", e),
@@ -56,6 +65,8 @@ impl IntoResponse for AppError {
                 StatusCode::INTERNAL_SERVER_ERROR,
                 format!("Cache error: {}", msg),
             ),
+            AppError::InvalidRequest(msg) => (StatusCode::BAD_REQUEST, msg.clone()),
+            AppError::Conflict(msg) => (StatusCode::CONFLICT, msg.clone()),
         };
         error!("Error processing request: {}", error_message);
         (status, Json(serde_json::json!({ "error": error_message }))).into_response()
@@ -105,4 +116,31 @@ mod tests {
         // Just verify the status code for simplicity
         assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_llm_unavailable_into_response() {
+        let app_error = AppError::LlmUnavailable("OpenAI API error (Status 503 Service Unavailable)".to_string());
+
+        let response = app_error.into_response();
+
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    }
+
+    #[test]
+    fn test_invalid_request_into_response() {
+        let app_error = AppError::InvalidRequest("Unknown mode 'poetic'".to_string());
+
+        let response = app_error.into_response();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_conflict_into_response() {
+        let app_error = AppError::Conflict("The original text has changed".to_string());
+
+        let response = app_error.into_response();
+
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+    }
+}
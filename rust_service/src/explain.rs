@@ -0,0 +1,177 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::warn;
+
+use crate::config::AppConfig;
+use crate::providers::provider_for;
+
+/// Appended to the prompt when the request asks for explanations
+const EXPLAIN_INSTRUCTIONS: &str = r#"Respond with a single JSON object and nothing else, in this form:
+{"corrected": "<the full improved text>", "changes": [{"original": "<text as written>", "corrected": "<text as changed>", "category": "grammar|spelling|clarity|tone", "explanation": "<one short sentence on why>"}]}
+List every change you made, in the order they appear. Write the explanations in simple English for a non-native speaker."#;
+
+/// Kind of a change made by the model
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeCategory {
+    Grammar,
+    Spelling,
+    Clarity,
+    Tone,
+    /// Anything else the model came up with, e.g. "punctuation"
+    #[serde(other)]
+    Other,
+}
+
+/// One change the model made, with its reason
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Change {
+    pub original: String,
+    pub corrected: String,
+    pub category: ChangeCategory,
+    pub explanation: String,
+}
+
+/// The JSON object the model is asked for
+#[derive(Debug, Deserialize)]
+struct ExplainedOutput {
+    corrected: String,
+    #[serde(default)]
+    changes: Vec<Change>,
+}
+
+/// Model output in explain mode, after parsing
+#[derive(Debug, PartialEq)]
+pub struct Explained {
+    pub corrected: String,
+    /// `None` if the model did not return a valid list of changes
+    pub changes: Option<Vec<Change>>,
+}
+
+/// Change a request config so the model answers with explained changes
+///
+/// The instructions are appended to the prompt template, so they also become
+/// part of the cache key.
+pub fn configure(config: &mut AppConfig) {
+    let template = config.prompt_template.as_deref().unwrap_or("{{input}}");
+    config.prompt_template = Some(format!("{}\n\n{}", template, EXPLAIN_INSTRUCTIONS));
+}
+
+/// Whether `configure` has been applied to this request config
+pub fn is_configured(config: &AppConfig) -> bool {
+    config.prompt_template.as_deref().is_some_and(|template| template.ends_with(EXPLAIN_INSTRUCTIONS))
+}
+
+/// Ask a backend for JSON output, if its API has a switch for it
+pub fn request_json(config: &mut AppConfig) {
+    if let Some(Value::Object(params)) = provider_for(config.provider).json_output_params() {
+        for (key, value) in params {
+            config.set_llm_param(&key, value);
+        }
+    }
+}
+
+/// The JSON object in a model reply, without surrounding prose or code fences
+fn json_object(output: &str) -> Option<&str> {
+    let start = output.find('{')?;
+    let end = output.rfind('}')?;
+    (start < end).then(|| &output[start..=end])
+}
+
+/// Parse the model output, falling back to treating it as the corrected text
+///
+/// Models sometimes answer with plain prose or a JSON object that does not
+/// match the schema. The text is still returned in that case, just without
+/// a list of changes. Whitespace is left alone; the corrected text goes
+/// through post-processing like any other response.
+pub fn parse(output: &str) -> Explained {
+    let Some(json) = json_object(output) else {
+        warn!("Explain mode: model answered without JSON, returning the text as is");
+        return Explained { corrected: output.to_string(), changes: None };
+    };
+
+    match serde_json::from_str::<ExplainedOutput>(json) {
+        Ok(explained) => Explained {
+            corrected: explained.corrected,
+            changes: Some(explained.changes),
+        },
+        Err(e) => {
+            warn!("Explain mode: model output does not match the schema: {}", e);
+            // Keep the corrected text if at least that much is usable
+            let corrected = serde_json::from_str::<Value>(json)
+                .ok()
+                .and_then(|value| value.get("corrected")?.as_str().map(str::to_string));
+            Explained {
+                corrected: corrected.unwrap_or_else(|| output.to_string()),
+                changes: None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_valid_output() {
+        let output = r#"```json
+{"corrected": "How are you?", "changes": [
+  {"original": "Howe ar", "corrected": "How are", "category": "spelling", "explanation": "Both words were misspelled."},
+  {"original": "you", "corrected": "you?", "category": "punctuation", "explanation": "Questions end with a question mark."}
+]}
+```"#;
+        let explained = parse(output);
+
+        assert_eq!(explained.corrected, "How are you?");
+        let changes = explained.changes.unwrap();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].category, ChangeCategory::Spelling);
+        assert_eq!(changes[0].explanation, "Both words were misspelled.");
+        assert_eq!(changes[1].category, ChangeCategory::Other);
+    }
+
+    #[test]
+    fn test_parse_prose_falls_back_to_text() {
+        let explained = parse("  How are you?\n");
+        assert_eq!(explained, Explained { corrected: "  How are you?\n".to_string(), changes: None });
+    }
+
+    #[test]
+    fn test_parse_invalid_changes_keeps_corrected_text() {
+        let output = r#"{"corrected": "How are you?", "changes": [{"original": "Howe"}]}"#;
+        assert_eq!(parse(output), Explained { corrected: "How are you?".to_string(), changes: None });
+
+        // Braces that are not a JSON object
+        let explained = parse("Use {braces} sparingly.");
+        assert_eq!(explained.corrected, "Use {braces} sparingly.");
+        assert!(explained.changes.is_none());
+    }
+
+    #[test]
+    fn test_configure_appends_instructions() {
+        let toml = r#"
+            port = 8989
+            provider = "ollama"
+            llm_url = "http://localhost:11434/api/chat"
+            model_name = "llama3"
+            prompt_template = "Fix: {{input}}"
+        "#;
+        let mut config: AppConfig = config::Config::builder()
+            .add_source(config::File::from_str(toml, config::FileFormat::Toml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+        assert!(!is_configured(&config));
+        configure(&mut config);
+        request_json(&mut config);
+        assert!(is_configured(&config));
+
+        let template = config.prompt_template.unwrap();
+        assert!(template.starts_with("Fix: {{input}}\n\nRespond with a single JSON object"));
+        // The JSON example must not be mistaken for template variables
+        assert_eq!(crate::template::variables(&template).unwrap(), vec!["input"]);
+        assert_eq!(config.llm_params.unwrap()["format"], "json");
+    }
+}
@@ -0,0 +1,348 @@
+use std::path::{Path, PathBuf};
+
+use regex::{NoExpand, Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::errors::AppError;
+use crate::masking::mask_spans;
+
+/// Team glossary: terms the model must keep, preferred spellings and
+/// replacements it must not make
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct GlossaryConfig {
+    /// TOML or CSV file with more terms, relative to the config directory
+    pub path: Option<PathBuf>,
+    /// Fix violations in the response instead of only reporting them
+    pub auto_correct: bool,
+    pub terms: Vec<GlossaryTerm>,
+}
+
+/// One glossary entry
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct GlossaryTerm {
+    /// The term as it must be written, e.g. "Prod" or "GitHub"
+    pub term: String,
+    /// Other spellings to replace with `term`, matched case-sensitively
+    #[serde(default)]
+    pub variants: Vec<String>,
+    /// What the model must not turn `term` into, e.g. "production"; matched
+    /// in any case
+    #[serde(default)]
+    pub forbidden: Vec<String>,
+}
+
+/// Layout of a TOML glossary file
+#[derive(Deserialize)]
+struct GlossaryFile {
+    #[serde(default)]
+    terms: Vec<GlossaryTerm>,
+}
+
+/// How a response breaks the glossary
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ViolationKind {
+    /// A term from the request text is missing from the response
+    Missing,
+    /// A term was replaced with one of its forbidden replacements
+    Forbidden,
+    /// A term is spelled in a way the glossary does not prefer
+    Spelling,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GlossaryViolation {
+    pub term: String,
+    pub kind: ViolationKind,
+    /// The offending text in the response, if there is any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub found: Option<String>,
+    /// The response has been changed to use `term`
+    pub corrected: bool,
+}
+
+/// A response after the glossary check
+#[derive(Debug, PartialEq)]
+pub struct CheckedText {
+    pub text: String,
+    pub violations: Vec<GlossaryViolation>,
+}
+
+fn config_error(message: String) -> AppError {
+    AppError::Config(config::ConfigError::Message(message))
+}
+
+/// Split a `;`-separated CSV cell into its non-empty entries
+fn split_list(cell: &str) -> Vec<String> {
+    cell.split(';').map(str::trim).filter(|item| !item.is_empty()).map(str::to_string).collect()
+}
+
+/// Parse a CSV glossary with the columns `term,variants,forbidden`, after a
+/// header row; lists are separated by `;` and trailing columns may be left out
+fn parse_csv(contents: &str) -> Result<Vec<GlossaryTerm>, AppError> {
+    let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).flexible(true).from_reader(contents.as_bytes());
+    reader
+        .records()
+        .map(|record| {
+            let record = record.map_err(|e| config_error(format!("Invalid glossary row: {}", e)))?;
+            let column = |index: usize| record.get(index).unwrap_or_default();
+            Ok(GlossaryTerm {
+                term: column(0).to_string(),
+                variants: split_list(column(1)),
+                forbidden: split_list(column(2)),
+            })
+        })
+        .collect()
+}
+
+fn parse_toml(contents: &str) -> Result<Vec<GlossaryTerm>, AppError> {
+    let file: GlossaryFile = config::Config::builder()
+        .add_source(config::File::from_str(contents, config::FileFormat::Toml))
+        .build()?
+        .try_deserialize()?;
+    Ok(file.terms)
+}
+
+/// Match `phrase` as a whole word (at its word-character edges) in running text
+fn word_regex(phrase: &str, case_insensitive: bool) -> Regex {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let start = if phrase.starts_with(is_word) { r"\b" } else { "" };
+    let end = if phrase.ends_with(is_word) { r"\b" } else { "" };
+    RegexBuilder::new(&format!("{}{}{}", start, regex::escape(phrase), end))
+        .case_insensitive(case_insensitive)
+        .build()
+        .expect("escaped phrase is a valid pattern")
+}
+
+impl GlossaryTerm {
+    /// The term or one of its other spellings appears in `text`
+    fn is_mentioned(&self, text: &str) -> bool {
+        std::iter::once(&self.term)
+            .chain(&self.variants)
+            .chain(&self.forbidden)
+            .any(|phrase| word_regex(phrase, true).is_match(text))
+    }
+}
+
+impl GlossaryConfig {
+    /// Add the terms of the `path` file, if set, to the inline ones
+    pub fn load(&mut self, config_dir: &Path) -> Result<(), AppError> {
+        if let Some(path) = &self.path {
+            let path = config_dir.join(path);
+            let contents = std::fs::read_to_string(&path)
+                .map_err(|e| config_error(format!("Cannot read glossary {:?}: {}", path, e)))?;
+            let terms = match path.extension().and_then(|ext| ext.to_str()) {
+                Some("csv") => parse_csv(&contents)?,
+                _ => parse_toml(&contents)?,
+            };
+            info!("Loaded {} glossary terms from {:?}", terms.len(), path);
+            self.terms.extend(terms);
+        }
+        self.validate()
+    }
+
+    fn validate(&self) -> Result<(), AppError> {
+        for entry in &self.terms {
+            let mut phrases = std::iter::once(&entry.term).chain(&entry.variants).chain(&entry.forbidden);
+            if phrases.any(|phrase| phrase.trim().is_empty()) {
+                return Err(config_error(format!("Glossary term '{}' has an empty entry", entry.term)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Prompt addition listing the glossary terms relevant to `text`, if any
+    pub fn instructions(&self, text: &str) -> Option<String> {
+        let lines: Vec<String> = self
+            .terms
+            .iter()
+            .filter(|entry| entry.is_mentioned(text))
+            .map(|entry| {
+                let avoid: Vec<String> =
+                    entry.variants.iter().chain(&entry.forbidden).map(|phrase| format!("\"{}\"", phrase)).collect();
+                if avoid.is_empty() {
+                    format!("- \"{}\": keep exactly as written", entry.term)
+                } else {
+                    format!("- \"{}\": keep exactly as written, never {}", entry.term, avoid.join(" or "))
+                }
+            })
+            .collect();
+        (!lines.is_empty()).then(|| format!("Follow this glossary:\n{}", lines.join("\n")))
+    }
+
+    /// Check a response against the glossary, given the text it was made from
+    ///
+    /// Code spans and URLs are left out of the check. Spelling variants and
+    /// forbidden replacements are fixed when `auto_correct` is set; missing
+    /// terms are only reported.
+    pub fn check(&self, input: &str, output: &str) -> CheckedText {
+        if self.terms.is_empty() {
+            return CheckedText { text: output.to_string(), violations: Vec::new() };
+        }
+
+        let input = mask_spans(input).text;
+        let masked = mask_spans(output);
+        let mut text = masked.text.clone();
+        let mut violations = Vec::new();
+
+        for entry in &self.terms {
+            // Presence in any case; the preferred case is up to `variants`
+            let term = word_regex(&entry.term, true);
+            let in_input = term.is_match(&input);
+            let violations_before = violations.len();
+
+            for variant in &entry.variants {
+                let pattern = word_regex(variant, false);
+                if pattern.is_match(&text) {
+                    violations.push(self.violation(entry, ViolationKind::Spelling, variant.clone()));
+                    self.correct(&mut text, &pattern, entry);
+                }
+            }
+            // Replacements the user wrote themselves are not the model's doing;
+            // longer ones first, so "production environment" wins over "production"
+            if in_input {
+                let mut forbidden: Vec<&String> = entry.forbidden.iter().collect();
+                forbidden.sort_by_key(|phrase| std::cmp::Reverse(phrase.len()));
+                for phrase in forbidden {
+                    let pattern = word_regex(phrase, true);
+                    if pattern.is_match(&input) {
+                        continue;
+                    }
+                    if let Some(found) = pattern.find(&text) {
+                        let found = found.as_str().to_string();
+                        violations.push(self.violation(entry, ViolationKind::Forbidden, found));
+                        self.correct(&mut text, &pattern, entry);
+                    }
+                }
+            }
+
+            if in_input && violations.len() == violations_before && !term.is_match(&text) {
+                violations.push(GlossaryViolation {
+                    term: entry.term.clone(),
+                    kind: ViolationKind::Missing,
+                    found: None,
+                    corrected: false,
+                });
+            }
+        }
+
+        if !violations.is_empty() {
+            warn!("Response breaks the glossary in {} places", violations.len());
+        }
+        match masked.restore(&text) {
+            Ok(text) => CheckedText { text, violations },
+            // Only if the response itself contained placeholder-like text
+            Err(_) => CheckedText { text: output.to_string(), violations },
+        }
+    }
+
+    fn violation(&self, entry: &GlossaryTerm, kind: ViolationKind, found: String) -> GlossaryViolation {
+        GlossaryViolation { term: entry.term.clone(), kind, found: Some(found), corrected: self.auto_correct }
+    }
+
+    fn correct(&self, text: &mut String, pattern: &Regex, entry: &GlossaryTerm) {
+        if self.auto_correct {
+            *text = pattern.replace_all(text, NoExpand(&entry.term)).into_owned();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glossary(auto_correct: bool) -> GlossaryConfig {
+        let toml = r#"
+            auto_correct = false
+            [[terms]]
+            term = "Prod"
+            forbidden = ["production", "production environment"]
+
+            [[terms]]
+            term = "GitHub"
+            variants = ["Github", "github"]
+
+            [[terms]]
+            term = "VPC"
+        "#;
+        GlossaryConfig { auto_correct, terms: parse_toml(toml).unwrap(), ..GlossaryConfig::default() }
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        let csv = "term,variants,forbidden\nProd,,production; production environment\nGitHub,Github;github\nCI\n";
+        let terms = parse_csv(csv).unwrap();
+
+        assert_eq!(terms.len(), 3);
+        assert_eq!(terms[0].forbidden, vec!["production", "production environment"]);
+        assert_eq!(terms[1].variants, vec!["Github", "github"]);
+        assert_eq!(terms[2], GlossaryTerm { term: "CI".to_string(), variants: Vec::new(), forbidden: Vec::new() });
+    }
+
+    #[test]
+    fn test_load_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join("glossary.csv"), "term,variants,forbidden\nCI,,continuous integration\n").unwrap();
+
+        let mut config = glossary(false);
+        config.path = Some(PathBuf::from("glossary.csv"));
+        config.load(dir.path()).unwrap();
+        assert_eq!(config.terms.len(), 4);
+        assert_eq!(config.terms[3].term, "CI");
+
+        config.path = Some(PathBuf::from("missing.toml"));
+        assert!(matches!(config.load(dir.path()), Err(AppError::Config(_))));
+    }
+
+    #[test]
+    fn test_instructions_only_list_relevant_terms() {
+        let instructions = glossary(false).instructions("Deploy to prod after the github check").unwrap();
+        assert_eq!(
+            instructions,
+            "Follow this glossary:\n\
+             - \"Prod\": keep exactly as written, never \"production\" or \"production environment\"\n\
+             - \"GitHub\": keep exactly as written, never \"Github\" or \"github\""
+        );
+        assert!(glossary(false).instructions("Nothing relevant here").is_none());
+    }
+
+    #[test]
+    fn test_check_reports_violations() {
+        let checked = glossary(false).check(
+            "Deploy Prod in the VPC after the Github check",
+            "Deploy to production in the network after the Github check.",
+        );
+
+        assert_eq!(checked.text, "Deploy to production in the network after the Github check.");
+        let found: Vec<_> = checked.violations.iter().map(|v| (v.term.as_str(), v.kind, v.found.as_deref())).collect();
+        assert_eq!(
+            found,
+            vec![
+                ("Prod", ViolationKind::Forbidden, Some("production")),
+                ("GitHub", ViolationKind::Spelling, Some("Github")),
+                ("VPC", ViolationKind::Missing, None),
+            ]
+        );
+        assert!(checked.violations.iter().all(|v| !v.corrected));
+    }
+
+    #[test]
+    fn test_check_auto_corrects_outside_code_and_urls() {
+        let checked = glossary(true).check(
+            "Push Prod fixes to github",
+            "Push Production fixes to github, see https://github.com/org/repo and `github`.",
+        );
+
+        assert_eq!(checked.text, "Push Prod fixes to GitHub, see https://github.com/org/repo and `github`.");
+        assert_eq!(checked.violations.len(), 2);
+        assert!(checked.violations.iter().all(|v| v.corrected));
+    }
+
+    #[test]
+    fn test_check_allows_replacements_from_the_input() {
+        let checked = glossary(true).check("Prod is the production system", "Prod is the production system.");
+        assert!(checked.violations.is_empty());
+    }
+}
@@ -1,27 +1,203 @@
+use axum::response::sse::{Event, KeepAlive, Sse};
 use axum::Json;
+use futures_util::{stream, Stream, StreamExt, TryStreamExt};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::convert::Infallible;
 use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
 use tracing::{info, instrument, warn, debug};
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
 
-use crate::cache::CacheManager;
+use crate::cache::{canonical_json, canonical_url, CacheManager, EntryMetadata, Fingerprint};
+use crate::chunking::{self, estimate_tokens};
 use crate::config::AppConfig;
+use crate::diff::{self, DiffSpan};
 use crate::errors::AppError;
-use crate::llm::query_llm;
+use crate::explain::{self, Change};
+use crate::glossary::GlossaryViolation;
+use crate::language::{LanguageConfig, LanguageMismatch, LANGUAGE_VAR};
+use crate::llm::{finalize_response, query_llm_candidates, stream_llm};
+use crate::providers::ProviderKind;
+use crate::response_limit::LimitedResponse;
+use crate::template::{self, TemplateVars, INPUT_VAR};
 
 // --- Request/Response Structs ---
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, Default)]
 pub struct ProcessRequest {
     pub text: String,
+    /// Name of a `[modes.<name>]` table; the top-level settings are used without one
+    #[serde(default)]
+    pub mode: Option<String>,
+    /// Extra prompt template variables, e.g. `{"app": "Slack", "language": "German"}`
+    #[serde(default)]
+    pub variables: TemplateVars,
+    /// Return the changes between the request text and the response as `diff`
+    #[serde(default)]
+    pub include_diff: bool,
+    /// Number of alternative rewrites to return, from 1 to 5
+    #[serde(default)]
+    pub n: Option<usize>,
+    /// Ask the model to explain each change it makes, returned as `changes`
+    #[serde(default)]
+    pub explain: bool,
 }
 
 #[derive(Serialize, Debug)]
 pub struct ProcessResponse {
     pub response: String,
+    /// Backend that produced the response
+    pub provider: ProviderKind,
+    /// Model that produced the response
+    pub model: String,
+    /// The model's response was longer than `[response_limit]` allows; it has
+    /// been truncated unless `on_overflow = "warn"`
+    pub over_limit: bool,
+    /// Changes made to the request text, if `include_diff` was set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff: Option<Vec<DiffSpan>>,
+    /// Hash of the request text, to pass to `/apply` along with the diff
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_hash: Option<String>,
+    /// Distinct alternatives, best first, if more than one was requested with `n`;
+    /// the first one is `response`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alternatives: Option<Vec<String>>,
+    /// How many of the `n` alternatives were left out because they lost
+    /// protected text, switched languages or their request failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dropped_alternatives: Option<usize>,
+    /// The changes with their category and reason, if `explain` was set and
+    /// the model answered in the expected format
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub changes: Option<Vec<Change>>,
+    /// Places where the response breaks the `[glossary]`, if there are any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub glossary_violations: Option<Vec<GlossaryViolation>>,
+    /// The response is in another language than the request text, even after
+    /// asking again
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language_mismatch: Option<LanguageMismatch>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ApplyRequest {
+    pub original: String,
+    pub revised: String,
+    /// `original_hash` of the `/process` response the changes come from
+    pub original_hash: String,
+    /// Indices into the `diff` list of the changes to keep
+    #[serde(default)]
+    pub accepted: Vec<usize>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ApplyResponse {
+    pub text: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ModeInfo {
+    pub name: String,
+    pub description: Option<String>,
+    /// Model the mode sends requests to
+    pub model: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ModesResponse {
+    pub modes: Vec<ModeInfo>,
+}
+
+/// Template variables for a request: the built-in `date` and `input_language`
+/// plus the request's own
+fn template_vars(req: &ProcessRequest, language: &LanguageConfig) -> TemplateVars {
+    let mut vars = TemplateVars::new();
+    vars.insert("date".to_string(), chrono::Local::now().format("%Y-%m-%d").to_string());
+    if let Some(detected) = language.detect(&req.text) {
+        debug!("Detected language: {}", detected.eng_name());
+        vars.insert(LANGUAGE_VAR.to_string(), detected.eng_name().to_string());
+    }
+    vars.extend(req.variables.clone());
+    if vars.remove(INPUT_VAR).is_some() {
+        warn!("Ignoring request variable '{}'; it always holds the request text", INPUT_VAR);
+    }
+    vars
+}
+
+/// Fingerprint of the provider, endpoint and `llm_params` of `backend`
+///
+/// The endpoint and parameters are canonicalised first, so reordering the
+/// parameters or writing `1.0` for `1` keeps the cached responses.
+pub fn backend_fingerprint(backend: &AppConfig) -> String {
+    let mut fingerprint = Fingerprint::new();
+    fingerprint
+        .field("provider", serde_json::to_string(&backend.provider).unwrap_or_default())
+        .field("llm_url", canonical_url(&backend.llm_url));
+    if let Some(params) = &backend.llm_params {
+        fingerprint.field("llm_params", canonical_json(params));
+    }
+    fingerprint.finish()
+}
+
+/// Backend fingerprints of every backend a request can reach with `config`,
+/// in any mode and with or without `explain`
+pub fn backend_fingerprints(config: &AppConfig) -> BTreeSet<String> {
+    let modes = std::iter::once(None).chain(config.modes.keys().map(|name| Some(name.as_str())));
+    let mut fingerprints = BTreeSet::new();
+    for mode in modes {
+        // Modes come from the config itself, so this cannot fail
+        let Ok(config) = config.with_mode(mode) else { continue };
+        for mut backend in config.backends() {
+            fingerprints.insert(backend_fingerprint(&backend));
+            explain::request_json(&mut backend);
+            fingerprints.insert(backend_fingerprint(&backend));
+        }
+    }
+    fingerprints
+}
+
+/// Fingerprint of everything besides the text and the model that affects the
+/// response of `backend`, used as part of the cache key
+///
+/// Covers the backend (see `backend_fingerprint`), the prompt template and
+/// the variables it uses, the system prompt, the mode and the glossary. A set
+/// of `n` alternatives is stored as one entry, so it gets its own fingerprint.
+pub fn cache_fingerprint(backend: &AppConfig, mode: Option<&str>, vars: &TemplateVars, n: usize) -> String {
+    let mut fingerprint = Fingerprint::new();
+    fingerprint.field("backend", backend_fingerprint(backend));
+    if let Some(template) = &backend.prompt_template {
+        fingerprint.field("prompt_template", template);
+        for name in template::variables(template).unwrap_or_default() {
+            if let Some(value) = vars.get(name) {
+                fingerprint.field("var", name).field(name, value);
+            }
+        }
+    }
+    // Tagged, like every field, so that an empty (disabled) system prompt differs from none
+    if let Some(system_prompt) = &backend.system_prompt {
+        fingerprint.field("system_prompt", system_prompt);
+    }
+    if let Some(mode) = mode {
+        fingerprint.field("mode", mode);
+    }
+    // Glossary terms end up in the prompt
+    for term in &backend.glossary.terms {
+        fingerprint
+            .field("term", &term.term)
+            .field("variants", term.variants.join("\n"))
+            .field("forbidden", term.forbidden.join("\n"));
+    }
+    if n > 1 {
+        fingerprint.field("candidates", n.to_string());
+    }
+    fingerprint.finish()
 }
 
+/// Most alternatives a single request may ask for
+const MAX_ALTERNATIVES: usize = 5;
+
 // --- Request Handler ---
 #[instrument(skip_all)]
 pub async fn process_text_handler(
@@ -31,64 +207,456 @@ pub async fn process_text_handler(
     info!("Received text length: {}", req.text.len());
     // debug!("Received text content: {}", req.text); // Uncomment for verbose debugging
 
-    // Calculate prompt template hash for cache key
-    let prompt_template_hash = if let Some(template) = &config.prompt_template {
-        let mut hasher = DefaultHasher::new();
-        template.hash(&mut hasher);
-        hasher.finish()
+    // Apply the requested mode, then hash the prompt for the cache key
+    let mut config = config.with_mode(req.mode.as_deref())?;
+    if req.explain {
+        explain::configure(&mut config);
+    }
+    let vars = template_vars(&req, &config.language);
+
+    let n = req.n.unwrap_or(1);
+    if !(1..=MAX_ALTERNATIVES).contains(&n) {
+        return Err(AppError::InvalidRequest(format!("n must be between 1 and {}", MAX_ALTERNATIVES)));
+    }
+
+    let mut backends = config.backends();
+    if req.explain {
+        backends.iter_mut().for_each(explain::request_json);
+    }
+    let start_time = std::time::Instant::now();
+
+    let chunking = &config.chunking;
+    let (response, changes, violations, language_mismatch, alternatives, dropped_alternatives, backend) = if chunking.enabled && estimate_tokens(&req.text) > chunking.max_chunk_tokens {
+        if n > 1 {
+            return Err(AppError::InvalidRequest(format!(
+                "Alternatives are only available for texts up to {} tokens",
+                chunking.max_chunk_tokens
+            )));
+        }
+
+        let chunked = chunking::split(&req.text, chunking.max_chunk_tokens);
+        info!("Processing {} chunks of up to {} tokens", chunked.chunks.len(), chunking.max_chunk_tokens);
+
+        // `buffered` keeps the results in input order however many run at once
+        let requests: Vec<_> = chunked
+            .chunks
+            .iter()
+            .map(|chunk| process_chunk(chunk, &vars, &backends, req.mode.as_deref(), 1, &client, &cache_manager))
+            .collect();
+        let results: Vec<(Vec<String>, usize, &AppConfig)> = stream::iter(requests)
+            .buffered(chunking.max_concurrency.max(1))
+            .try_collect()
+            .await?;
+
+        // Report the backend of the first chunk; they differ only after a fallback
+        let backend = results.first().map_or(&backends[0], |(_, _, backend)| *backend);
+        let mut outputs = Vec::with_capacity(results.len());
+        for (chunk, (candidates, _, backend)) in chunked.chunks.iter().zip(results) {
+            outputs.extend(finish_candidates(backend, chunk, candidates, req.explain)?);
+        }
+
+        let over_limit = outputs.iter().any(|output| output.response.over_limit);
+        let texts: Vec<&str> = outputs.iter().map(|output| output.response.text.as_str()).collect();
+        let response = LimitedResponse { text: chunked.reassemble(&texts), over_limit };
+        // Only a complete list is useful, so one unexplained chunk drops it
+        let changes = outputs
+            .iter()
+            .map(|output| output.changes.clone())
+            .collect::<Option<Vec<_>>>()
+            .map(|changes| changes.concat());
+        let language_mismatch = outputs.iter().find_map(|output| output.language_mismatch.clone());
+        let violations = outputs.into_iter().flat_map(|output| output.violations).collect();
+        (response, changes, violations, language_mismatch, None, None, backend)
     } else {
-        0
+        let (candidates, dropped, backend) =
+            process_chunk(&req.text, &vars, &backends, req.mode.as_deref(), n, &client, &cache_manager).await?;
+        let mut candidates = finish_candidates(backend, &req.text, candidates, req.explain)?;
+
+        let alternatives = (n > 1).then(|| candidates.iter().map(|candidate| candidate.response.text.clone()).collect());
+        let dropped_alternatives = (dropped > 0).then_some(dropped);
+        let Candidate { response, changes, violations, language_mismatch } = candidates.swap_remove(0);
+
+        // Check for suspiciously long responses that might indicate LLM hallucinations
+        if response.text.len() > 1000 {
+            warn!("Response is unusually long ({}). Consider reviewing the prompt template.", response.text.len());
+        }
+        (response, changes, violations, language_mismatch, alternatives, dropped_alternatives, backend)
     };
 
-    // Try to get response from cache first
+    let elapsed = start_time.elapsed();
+    info!("Total response time: {:.3}ms", elapsed.as_secs_f64() * 1000.0);
+    info!("Sending back response length: {}", response.text.len());
+
+    let diff = req.include_diff.then(|| diff::diff(&req.text, &response.text));
+    let original_hash = req.include_diff.then(|| diff::text_hash(&req.text));
+    Ok(Json(ProcessResponse {
+        response: response.text,
+        provider: backend.provider,
+        model: backend.model_name.clone(),
+        over_limit: response.over_limit,
+        diff,
+        original_hash,
+        alternatives,
+        dropped_alternatives,
+        changes,
+        glossary_violations: (!violations.is_empty()).then_some(violations),
+        language_mismatch,
+    }))
+}
+
+/// Rewrite one piece of text into up to `n` candidates, trying each backend in turn
+///
+/// The cache is keyed by the text and the model that answered, so for chunked
+/// documents every chunk is cached on its own. The cache holds the raw model
+/// output, see `finish_candidates`. The returned list is never empty; it comes
+/// with the number of candidates that were dropped, which is 0 for cache hits.
+async fn process_chunk<'a>(
+    text: &str,
+    vars: &TemplateVars,
+    backends: &'a [AppConfig],
+    mode: Option<&str>,
+    n: usize,
+    client: &Client,
+    cache_manager: &CacheManager,
+) -> Result<(Vec<String>, usize, &'a AppConfig), AppError> {
     let start_time = std::time::Instant::now();
-    
-    if config.cache.enabled {
-        match cache_manager.lookup(&req.text, &config.model_name, prompt_template_hash) {
-            Ok(Some(cached_response)) => {
-                let elapsed = start_time.elapsed();
-                info!("Cache hit! Response time: {:.3}ms", elapsed.as_secs_f64() * 1000.0);
-                
-                return Ok(Json(ProcessResponse {
-                    response: cached_response,
-                }));
-            },
-            Ok(None) => {
-                debug!("Cache miss, querying LLM API");
-            },
-            Err(e) => {
-                warn!("Cache error: {}. Falling back to LLM API", e);
+
+    for (index, backend) in backends.iter().enumerate() {
+        let fingerprint = cache_fingerprint(backend, mode, vars, n);
+        if backend.cache.enabled {
+            match cache_manager.lookup_entry(text, &backend.model_name, &fingerprint) {
+                Ok(Some(entry)) => match decode_candidates(entry.response.clone(), n) {
+                    Some(candidates) => {
+                        let candidates = candidates.iter().map(|candidate| entry.response_for(candidate, text)).collect();
+                        let elapsed = start_time.elapsed();
+                        info!("Cache hit! Response time: {:.3}ms", elapsed.as_secs_f64() * 1000.0);
+                        return Ok((candidates, 0, backend));
+                    }
+                    None => warn!("Unreadable cached candidates, querying LLM API"),
+                },
+                Ok(None) => {
+                    debug!("Cache miss, querying LLM API");
+                },
+                Err(e) => {
+                    warn!("Cache error: {}. Falling back to LLM API", e);
+                }
             }
         }
-    }
 
-    // If we reach here, we need to query the LLM
-    let llm_response = query_llm(&req.text, &config, &client).await?;
-    let elapsed = start_time.elapsed();
-    
-    info!("LLM response time: {:.3}ms", elapsed.as_secs_f64() * 1000.0);
-    let response_len = llm_response.len();
-    info!("Sending back response length: {}", response_len);
-    
-    // Store successful response in cache
-    if config.cache.enabled {
-        if let Err(e) = cache_manager.store(&req.text, &llm_response, &config.model_name, prompt_template_hash) {
-            warn!("Failed to store in cache: {}", e);
-        } else {
-            debug!("Stored response in cache");
+        let output = match query_llm_candidates(text, vars, backend, client, n).await {
+            Ok(output) => output,
+            Err(AppError::LlmUnavailable(msg)) if index + 1 < backends.len() => {
+                let next = &backends[index + 1];
+                warn!(
+                    "{} is unavailable ({}), falling back to {} at {}",
+                    backend.model_name, msg, next.model_name, next.llm_url
+                );
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        let elapsed = start_time.elapsed();
+        info!("LLM response time: {:.3}ms", elapsed.as_secs_f64() * 1000.0);
+
+        // Store successful response in cache
+        if backend.cache.enabled {
+            let metadata = EntryMetadata {
+                model: backend.model_name.clone(),
+                mode: mode.map(str::to_string),
+                fingerprint,
+                backend: backend_fingerprint(backend),
+                usage: output.usage,
+                latency_ms: Some(elapsed.as_millis() as u64),
+            };
+            let encoded = encode_candidates(&output.candidates, n);
+            if let Err(e) = cache_manager.store_with_metadata(text, &encoded, metadata) {
+                warn!("Failed to store in cache: {}", e);
+            } else {
+                debug!("Stored response in cache");
+            }
         }
+
+        return Ok((output.candidates, output.dropped, backend));
     }
-    
-    // Check for suspiciously long responses that might indicate LLM hallucinations
-    if response_len > 1000 {
-        warn!("Response is unusually long ({}). Consider reviewing the prompt template.", response_len);
+
+    // `backends()` always contains the primary backend, so the loop returns
+    Err(AppError::Internal("No LLM backend configured".to_string()))
+}
+
+/// A rewrite ready to be returned
+struct Candidate {
+    response: LimitedResponse,
+    changes: Option<Vec<Change>>,
+    violations: Vec<GlossaryViolation>,
+    language_mismatch: Option<LanguageMismatch>,
+}
+
+/// Turn the raw model output for `source` into the text to return
+///
+/// Explanations are parsed out, if requested, and the corrected text is
+/// post-processed, since the model output as a whole was a JSON object. The
+/// text is checked against the glossary and for its language, and the
+/// backend's length limit is applied.
+fn finish_candidates(
+    backend: &AppConfig,
+    source: &str,
+    candidates: Vec<String>,
+    explain: bool,
+) -> Result<Vec<Candidate>, AppError> {
+    candidates
+        .into_iter()
+        .map(|candidate| {
+            let (text, changes) = if explain {
+                let explained = explain::parse(&candidate);
+                (finalize_response(&explained.corrected, source, backend), explained.changes)
+            } else {
+                (candidate, None)
+            };
+            let checked = backend.glossary.check(source, &text);
+            let language_mismatch = backend.language.check(source, &checked.text);
+            if let Some(mismatch) = &language_mismatch {
+                warn!("Response is in {} instead of {}", mismatch.detected, mismatch.expected);
+            }
+            Ok(Candidate {
+                response: backend.response_limit.apply(checked.text)?,
+                changes,
+                violations: checked.violations,
+                language_mismatch,
+            })
+        })
+        .collect()
+}
+
+/// Cache entry for a candidate set: the plain text for a single response, so
+/// that entries for `n = 1` stay readable by `/process/stream`
+fn encode_candidates(candidates: &[String], n: usize) -> String {
+    if n <= 1 {
+        candidates.first().cloned().unwrap_or_default()
+    } else {
+        serde_json::to_string(candidates).unwrap_or_default()
     }
-    
-    Ok(Json(ProcessResponse {
-        response: llm_response,
+}
+
+fn decode_candidates(cached: String, n: usize) -> Option<Vec<String>> {
+    if n <= 1 {
+        return Some(vec![cached]);
+    }
+    serde_json::from_str::<Vec<String>>(&cached).ok().filter(|candidates| !candidates.is_empty())
+}
+
+// --- Apply Handler ---
+/// Merge the accepted changes of a `/process` diff into the original text
+///
+/// The diff is recomputed from `original` and `revised`, so the indices refer
+/// to the same spans the client was shown. No LLM request is made.
+#[instrument(skip_all)]
+pub async fn apply_handler(Json(req): Json<ApplyRequest>) -> Result<Json<ApplyResponse>, AppError> {
+    if diff::text_hash(&req.original) != req.original_hash {
+        return Err(AppError::Conflict(
+            "The original text has changed since the changes were computed".to_string(),
+        ));
+    }
+
+    let spans = diff::diff(&req.original, &req.revised);
+    let accepted: BTreeSet<usize> = req.accepted.iter().copied().collect();
+    if let Some(index) = accepted.iter().find(|&&index| index >= spans.len()) {
+        return Err(AppError::InvalidRequest(format!(
+            "Change {} does not exist; there are {} changes",
+            index,
+            spans.len()
+        )));
+    }
+
+    info!("Applying {} of {} changes", accepted.len(), spans.len());
+    Ok(Json(ApplyResponse {
+        text: diff::apply(&req.original, &spans, &accepted),
     }))
 }
 
+// --- Modes Handler ---
+/// List the rewrite modes configured under `[modes.<name>]`
+#[instrument(skip_all)]
+pub async fn list_modes_handler(
+    axum::extract::State((config, _, _)): axum::extract::State<(Arc<AppConfig>, Arc<Client>, Arc<CacheManager>)>,
+) -> Json<ModesResponse> {
+    let modes = config
+        .modes
+        .iter()
+        .map(|(name, mode)| ModeInfo {
+            name: name.clone(),
+            description: mode.description.clone(),
+            model: mode.model_name.clone().unwrap_or_else(|| config.model_name.clone()),
+        })
+        .collect();
+
+    Json(ModesResponse { modes })
+}
+
+// --- Streaming Request Handler ---
+/// Stream the rewritten text as Server-Sent Events
+///
+/// Emits `delta` events (`{"text": ...}`) while the model generates, then a
+/// single `done` event (`{"response": ..., "cached": ..., "over_limit": ...}`)
+/// carrying the final text, after the glossary and the length limit have been
+/// applied. Glossary violations and a language mismatch are added to it as
+/// `glossary_violations` and `language_mismatch`; unlike `/process`, a
+/// response in another language is not requested again. Cache hits skip
+/// straight to `done`. Unavailable backends fall back as for `/process`
+/// until the stream has started; failures after that are reported as an
+/// `error` event (`{"error": ...}`).
+#[instrument(skip_all)]
+pub async fn process_stream_handler(
+    axum::extract::State((config, client, cache_manager)): axum::extract::State<(Arc<AppConfig>, Arc<Client>, Arc<CacheManager>)>,
+    Json(req): Json<ProcessRequest>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, AppError> {
+    info!("Received text length for streaming: {}", req.text.len());
+    if req.explain {
+        // The deltas would be raw JSON rather than text
+        return Err(AppError::InvalidRequest("explain is not supported for streaming, use /process".to_string()));
+    }
+    // Both need the whole response, so rather than ignore them the request is refused
+    if req.include_diff {
+        return Err(AppError::InvalidRequest("include_diff is not supported for streaming, use /process".to_string()));
+    }
+    if req.n.is_some_and(|n| n != 1) {
+        return Err(AppError::InvalidRequest("n is not supported for streaming, use /process".to_string()));
+    }
+
+    let config = config.with_mode(req.mode.as_deref())?;
+    let vars = template_vars(&req, &config.language);
+    let backends = config.backends();
+    let (tx, rx) = mpsc::channel::<Event>(32);
+    let start_time = std::time::Instant::now();
+
+    let mut started = None;
+    for (index, backend) in backends.iter().enumerate() {
+        let fingerprint = cache_fingerprint(backend, req.mode.as_deref(), &vars, 1);
+        if backend.cache.enabled {
+            match cache_manager.lookup(&req.text, &backend.model_name, &fingerprint) {
+                Ok(Some(cached_response)) => {
+                    info!("Cache hit! Replaying cached response as a single event");
+                    let candidate = finish_stream(backend, &req.text, cached_response)?;
+                    let _ = tx.send(done_event(&candidate, true)).await;
+                    return Ok(sse_response(rx));
+                }
+                Ok(None) => {
+                    debug!("Cache miss, streaming from LLM API");
+                }
+                Err(e) => {
+                    warn!("Cache error: {}. Falling back to LLM API", e);
+                }
+            }
+        }
+
+        // Connection and status errors surface as a regular error response
+        match stream_llm(&req.text, &vars, backend, &client).await {
+            Ok(deltas) => {
+                started = Some((Box::pin(deltas), backend.clone(), fingerprint));
+                break;
+            }
+            Err(AppError::LlmUnavailable(msg)) if index + 1 < backends.len() => {
+                let next = &backends[index + 1];
+                warn!(
+                    "{} is unavailable ({}), falling back to {} at {}",
+                    backend.model_name, msg, next.model_name, next.llm_url
+                );
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    // `backends()` always contains the primary backend, so the loop sets it
+    let Some((mut deltas, backend, fingerprint)) = started else {
+        return Err(AppError::Internal("No LLM backend configured".to_string()));
+    };
+
+    tokio::spawn(async move {
+        let mut assembled = String::new();
+
+        while let Some(delta) = deltas.next().await {
+            match delta {
+                Ok(text) => {
+                    assembled.push_str(&text);
+                    let event = Event::default()
+                        .event("delta")
+                        .data(serde_json::json!({ "text": text }).to_string());
+                    if tx.send(event).await.is_err() {
+                        info!("Client disconnected, abandoning stream");
+                        return;
+                    }
+                }
+                Err(e) => {
+                    warn!("LLM stream failed: {}", e);
+                    let _ = tx.send(error_event(&e)).await;
+                    return;
+                }
+            }
+        }
+
+        let llm_response = finalize_response(&assembled, &req.text, &backend);
+        info!("LLM stream completed in {:.3}ms", start_time.elapsed().as_secs_f64() * 1000.0);
+        info!("Streamed response length: {}", llm_response.len());
+
+        // Store the assembled response so later requests are served from cache
+        if backend.cache.enabled && !llm_response.is_empty() {
+            let metadata = EntryMetadata {
+                model: backend.model_name.clone(),
+                mode: req.mode.clone(),
+                fingerprint,
+                backend: backend_fingerprint(&backend),
+                usage: None,
+                latency_ms: Some(start_time.elapsed().as_millis() as u64),
+            };
+            if let Err(e) = cache_manager.store_with_metadata(&req.text, &llm_response, metadata) {
+                warn!("Failed to store in cache: {}", e);
+            } else {
+                debug!("Stored streamed response in cache");
+            }
+        }
+
+        let event = match finish_stream(&backend, &req.text, llm_response) {
+            Ok(candidate) => done_event(&candidate, false),
+            Err(e) => {
+                warn!("Streamed response rejected: {}", e);
+                error_event(&e)
+            }
+        };
+        let _ = tx.send(event).await;
+    });
+
+    Ok(sse_response(rx))
+}
+
+/// `finish_candidates` for the single response of a stream
+fn finish_stream(backend: &AppConfig, source: &str, response: String) -> Result<Candidate, AppError> {
+    finish_candidates(backend, source, vec![response], false).map(|mut candidates| candidates.swap_remove(0))
+}
+
+/// Final event of a stream, carrying the complete text
+fn done_event(candidate: &Candidate, cached: bool) -> Event {
+    let mut data = serde_json::json!({
+        "response": candidate.response.text,
+        "cached": cached,
+        "over_limit": candidate.response.over_limit,
+    });
+    if !candidate.violations.is_empty() {
+        data["glossary_violations"] = serde_json::json!(candidate.violations);
+    }
+    if let Some(mismatch) = &candidate.language_mismatch {
+        data["language_mismatch"] = serde_json::json!(mismatch);
+    }
+    Event::default().event("done").data(data.to_string())
+}
+
+fn error_event(error: &AppError) -> Event {
+    Event::default()
+        .event("error")
+        .data(serde_json::json!({ "error": error.to_string() }).to_string())
+}
+
+fn sse_response(rx: mpsc::Receiver<Event>) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    Sse::new(ReceiverStream::new(rx).map(Ok)).keep_alive(KeepAlive::default())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -119,19 +687,12 @@ mod tests {
         // Similar to the success test, but simulate a failure in query_llm
         // Create real dependencies
         let config = Arc::new(AppConfig {
-            port: 8989,
+            provider: crate::providers::ProviderKind::OpenAi,
             llm_url: "https://api.openai.com/v1/responses".to_string(),
             model_name: "gpt-4o".to_string(),
-            llm_params: None,
-            prompt_template: None,
             openai_api_key: None, // This will cause an error when query_llm is called
-            openai_org_id: None,
-            openai_project_id: None,
-            cache: crate::cache::CacheConfig {
-                enabled: false,
-                ttl_days: 30,
-                max_size_mb: 100,
-            },
+            cache: crate::cache::CacheConfig { enabled: false, ..Default::default() },
+            ..Default::default()
         });
         
         // Create a temporary directory for cache
@@ -145,6 +706,7 @@ mod tests {
         // Create test request
         let request = ProcessRequest {
             text: "Test input text".to_string(),
+            ..Default::default()
         };
         
         // We expect this to fail because the OpenAI API key is missing
@@ -173,6 +735,8 @@ mod tests {
             enabled: true,
             ttl_days: 30,
             max_size_mb: 100,
+            invalidate_changed_params: false,
+            normalize: Vec::new(),
         };
         
         // Initialize cache manager
@@ -181,15 +745,173 @@ mod tests {
         // Test storing and retrieving directly to verify cache works
         let text = "Test input text";
         let model = "test-model";
-        let prompt_hash = 12345u64;
+        let fingerprint = "abc123";
         let response = "Test response";
         
         // Store in cache
-        cache_manager.store(text, response, model, prompt_hash).unwrap();
+        cache_manager.store(text, response, model, fingerprint).unwrap();
         
         // Retrieve from cache
-        let cached_response = cache_manager.lookup(text, model, prompt_hash).unwrap();
+        let cached_response = cache_manager.lookup(text, model, fingerprint).unwrap();
         
         assert_eq!(cached_response, Some(response.to_string()));
     }
+
+    /// Config for the cache fingerprint tests, with a template that uses `app`
+    fn fingerprint_config() -> AppConfig {
+        AppConfig {
+            provider: crate::providers::ProviderKind::OpenAi,
+            llm_url: "https://api.openai.com/v1/responses".to_string(),
+            model_name: "gpt-4o".to_string(),
+            prompt_template: Some("Rewrite for {{app}}: {{input}}".to_string()),
+            cache: crate::cache::CacheConfig { enabled: false, ..Default::default() },
+            ..Default::default()
+        }
+    }
+
+    /// Template variables of a short request with the given `variables`
+    fn fingerprint_vars(variables: &[(&str, &str)]) -> TemplateVars {
+        let request = ProcessRequest {
+            text: "hello".to_string(),
+            variables: variables.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            ..Default::default()
+        };
+        template_vars(&request, &LanguageConfig::default())
+    }
+
+    #[test]
+    fn test_cache_fingerprint_mode() {
+        let config = fingerprint_config();
+        let vars = fingerprint_vars(&[("app", "Slack")]);
+        assert_ne!(cache_fingerprint(&config, None, &vars, 1), cache_fingerprint(&config, Some("formal"), &vars, 1));
+        assert_ne!(cache_fingerprint(&config, Some("formal"), &vars, 1), cache_fingerprint(&config, Some("casual"), &vars, 1));
+    }
+
+    #[test]
+    fn test_cache_fingerprint_template_vars() {
+        let config = fingerprint_config();
+        let slack = fingerprint_vars(&[("app", "Slack")]);
+        let mail = fingerprint_vars(&[("app", "Mail")]);
+        let slack_with_unused = fingerprint_vars(&[("app", "Slack"), ("tone", "casual")]);
+
+        // Values the template uses change the key; others (and `date`, unused here) do not
+        assert_ne!(cache_fingerprint(&config, None, &slack, 1), cache_fingerprint(&config, None, &mail, 1));
+        assert_eq!(cache_fingerprint(&config, None, &slack, 1), cache_fingerprint(&config, None, &slack_with_unused, 1));
+    }
+
+    #[test]
+    fn test_template_vars() {
+        // `input` can't be overridden, and `date` is always provided
+        let vars = fingerprint_vars(&[("input", "other text"), ("app", "Slack")]);
+        assert!(!vars.contains_key("input"));
+        assert!(vars.contains_key("date"));
+        assert_eq!(vars.get("app").map(String::as_str), Some("Slack"));
+    }
+
+    #[test]
+    fn test_cache_fingerprint_system_prompt() {
+        let config = fingerprint_config();
+        let vars = fingerprint_vars(&[("app", "Slack")]);
+
+        // A custom or disabled system prompt gets its own cache entries
+        let mut custom = config.clone();
+        custom.system_prompt = Some("You are a translator.".to_string());
+        let mut disabled = config.clone();
+        disabled.system_prompt = Some(String::new());
+        assert_ne!(cache_fingerprint(&config, None, &vars, 1), cache_fingerprint(&custom, None, &vars, 1));
+        assert_ne!(cache_fingerprint(&config, None, &vars, 1), cache_fingerprint(&disabled, None, &vars, 1));
+        assert_ne!(cache_fingerprint(&custom, None, &vars, 1), cache_fingerprint(&disabled, None, &vars, 1));
+    }
+
+    #[test]
+    fn test_cache_fingerprint_alternatives() {
+        let config = fingerprint_config();
+        let vars = fingerprint_vars(&[("app", "Slack")]);
+
+        // A set of alternatives is cached apart from a single response and from other set sizes
+        assert_ne!(cache_fingerprint(&config, None, &vars, 1), cache_fingerprint(&config, None, &vars, 3));
+        assert_ne!(cache_fingerprint(&config, None, &vars, 2), cache_fingerprint(&config, None, &vars, 3));
+    }
+
+    #[test]
+    fn test_cache_fingerprint_language() {
+        let mut config = fingerprint_config();
+        config.prompt_template = Some("Keep the text in {{input_language}}: {{input}}".to_string());
+        let vars = |text: &str| {
+            let request = ProcessRequest { text: text.to_string(), ..Default::default() };
+            template_vars(&request, &LanguageConfig::default())
+        };
+
+        // The language of a long enough text is detected, that of a short one is not
+        let german = vars("Wir veröffentlichen das Release am Freitag nach der letzten Prüfung.");
+        let french = vars("Nous publierons la nouvelle version vendredi prochain, après la dernière vérification de toute l'équipe.");
+        assert_eq!(german.get(LANGUAGE_VAR).map(String::as_str), Some("German"));
+        assert_eq!(french.get(LANGUAGE_VAR).map(String::as_str), Some("French"));
+        assert!(!fingerprint_vars(&[]).contains_key(LANGUAGE_VAR));
+
+        // A template that uses the language is cached per language
+        assert_ne!(cache_fingerprint(&config, None, &german, 1), cache_fingerprint(&config, None, &french, 1));
+    }
+
+    #[test]
+    fn test_cache_fingerprint_endpoint() {
+        let config = fingerprint_config();
+        let vars = fingerprint_vars(&[("app", "Slack")]);
+
+        // Another endpoint gets its own cache entries, another spelling of it does not
+        let mut other_host = config.clone();
+        other_host.llm_url = "https://eu.api.openai.com/v1/responses".to_string();
+        let mut same_host = config.clone();
+        same_host.llm_url = "HTTPS://API.OpenAI.com:443/v1/responses/".to_string();
+        let fingerprint = cache_fingerprint(&config, None, &vars, 1);
+        assert_ne!(fingerprint, cache_fingerprint(&other_host, None, &vars, 1));
+        assert_eq!(fingerprint, cache_fingerprint(&same_host, None, &vars, 1));
+        assert_eq!(fingerprint.len(), 64);
+    }
+
+    #[test]
+    fn test_cache_fingerprint_params() {
+        let config = fingerprint_config();
+        let vars = fingerprint_vars(&[("app", "Slack")]);
+
+        // Other parameters get their own cache entries
+        let mut warmer = config.clone();
+        warmer.set_llm_param("temperature", serde_json::json!(0.9));
+        assert_ne!(cache_fingerprint(&config, None, &vars, 1), cache_fingerprint(&warmer, None, &vars, 1));
+
+        // Equivalent spellings of the same parameters share entries
+        let mut params_a = config.clone();
+        params_a.set_llm_param("temperature", serde_json::json!(1));
+        params_a.set_llm_param("max_output_tokens", serde_json::json!(500));
+        let mut params_b = config.clone();
+        params_b.set_llm_param("max_output_tokens", serde_json::json!(500.0));
+        params_b.set_llm_param("temperature", serde_json::json!(1.0));
+        assert_eq!(cache_fingerprint(&params_a, None, &vars, 1), cache_fingerprint(&params_b, None, &vars, 1));
+        assert_eq!(backend_fingerprint(&params_a), backend_fingerprint(&params_b));
+        assert_ne!(backend_fingerprint(&config), backend_fingerprint(&params_a));
+    }
+
+    #[test]
+    fn test_backend_fingerprints() {
+        let config = fingerprint_config();
+        let mut warmer = config.clone();
+        warmer.set_llm_param("temperature", serde_json::json!(0.9));
+
+        // Every mode and `explain` variant of the backends is current
+        let mut with_modes = config.clone();
+        with_modes.modes.insert(
+            "creative".to_string(),
+            crate::config::ModeConfig {
+                llm_params: Some(serde_json::json!({ "temperature": 1.2 })),
+                ..Default::default()
+            },
+        );
+        let mut creative = with_modes.with_mode(Some("creative")).unwrap();
+        let current = backend_fingerprints(&with_modes);
+        assert!(current.contains(&backend_fingerprint(&config)));
+        assert!(current.contains(&backend_fingerprint(&creative)));
+        explain::request_json(&mut creative);
+        assert!(current.contains(&backend_fingerprint(&creative)));
+        assert!(!current.contains(&backend_fingerprint(&warmer)));
+    }
 }
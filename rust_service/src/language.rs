@@ -0,0 +1,162 @@
+use serde::{Deserialize, Serialize};
+use whatlang::{Detector, Lang};
+
+use crate::errors::AppError;
+use crate::masking::strip_spans;
+
+/// Template variable holding the English name of the request text's language
+pub const LANGUAGE_VAR: &str = "input_language";
+
+/// Detection of the request text's language, so the response keeps it
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LanguageConfig {
+    pub enabled: bool,
+    /// ISO 639-3 codes of the languages to tell apart, e.g. `["eng", "ukr", "deu"]`;
+    /// all languages when empty
+    pub languages: Vec<String>,
+    /// Texts with fewer letters than this are too short to detect reliably
+    pub min_letters: usize,
+    /// Total attempts when the model answers in another language
+    pub max_attempts: u32,
+}
+
+impl Default for LanguageConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            languages: Vec::new(),
+            min_letters: 20,
+            max_attempts: 2,
+        }
+    }
+}
+
+/// The response is in another language than the request text
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct LanguageMismatch {
+    /// Language of the request text
+    pub expected: String,
+    /// Language of the response
+    pub detected: String,
+}
+
+impl LanguageConfig {
+    /// Check the language codes
+    pub fn validate(&self) -> Result<(), AppError> {
+        for code in &self.languages {
+            if Lang::from_code(code.as_str()).is_none() {
+                return Err(AppError::Config(config::ConfigError::Message(format!(
+                    "Unknown language code '{}'; use ISO 639-3 codes such as \"eng\", \"ukr\" or \"deu\"",
+                    code
+                ))));
+            }
+        }
+        Ok(())
+    }
+
+    /// Language of `text`, if detection is enabled and the text says enough
+    ///
+    /// Code spans and URLs are left out, since they are not in any language.
+    pub fn detect(&self, text: &str) -> Option<Lang> {
+        if !self.enabled {
+            return None;
+        }
+        let prose = strip_spans(text);
+        if prose.chars().filter(|c| c.is_alphabetic()).count() < self.min_letters {
+            return None;
+        }
+
+        let detector = if self.languages.is_empty() {
+            Detector::new()
+        } else {
+            // Checked by `validate` when the config is loaded
+            Detector::with_allowlist(self.languages.iter().filter_map(|code| Lang::from_code(code.as_str())).collect())
+        };
+        detector.detect(&prose).filter(|info| info.is_reliable()).map(|info| info.lang())
+    }
+
+    /// Compare the languages of the request text and the response
+    ///
+    /// Only reports a mismatch when both languages could be detected.
+    pub fn check(&self, input: &str, output: &str) -> Option<LanguageMismatch> {
+        let expected = self.detect(input)?;
+        let detected = self.detect(output)?;
+        (expected != detected).then(|| LanguageMismatch {
+            expected: expected.eng_name().to_string(),
+            detected: detected.eng_name().to_string(),
+        })
+    }
+}
+
+/// Prompt addition for another attempt after the model answered in the wrong language
+///
+/// In explain mode only the corrected text is meant to be in that language;
+/// the explanations are asked for in English.
+pub fn instructions(language: Lang, explain: bool) -> String {
+    if explain {
+        return format!(
+            "Important: the text is written in {0}. Write the corrected text in {0} only. Do not translate it into English or any other language.",
+            language.eng_name()
+        );
+    }
+    format!(
+        "Important: the text is written in {0}. Answer in {0} only. Do not translate it into English or any other language.",
+        language.eng_name()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENGLISH: &str = "We will ship the release on Friday after the final review of the docs.";
+    const UKRAINIAN: &str = "Ми випустимо реліз у пʼятницю після остаточної перевірки документації.";
+    const GERMAN: &str = "Wir veröffentlichen das Release am Freitag nach der letzten Prüfung der Dokumentation.";
+
+    #[test]
+    fn test_detect() {
+        let config = LanguageConfig::default();
+        assert_eq!(config.detect(ENGLISH), Some(Lang::Eng));
+        assert_eq!(config.detect(UKRAINIAN), Some(Lang::Ukr));
+        assert_eq!(config.detect(GERMAN), Some(Lang::Deu));
+
+        // Too short, or nothing but code
+        assert_eq!(config.detect("Danke!"), None);
+        assert_eq!(config.detect("`let release = ship(friday, final_review);`"), None);
+
+        let config = LanguageConfig { enabled: false, ..LanguageConfig::default() };
+        assert_eq!(config.detect(GERMAN), None);
+    }
+
+    #[test]
+    fn test_detect_with_allowlist() {
+        let config = LanguageConfig {
+            languages: vec!["eng".to_string(), "ukr".to_string(), "deu".to_string()],
+            ..LanguageConfig::default()
+        };
+        assert!(config.validate().is_ok());
+        assert_eq!(config.detect(UKRAINIAN), Some(Lang::Ukr));
+
+        let config = LanguageConfig { languages: vec!["english".to_string()], ..LanguageConfig::default() };
+        assert!(matches!(config.validate(), Err(AppError::Config(_))));
+    }
+
+    #[test]
+    fn test_check() {
+        let config = LanguageConfig::default();
+        assert_eq!(config.check(GERMAN, GERMAN), None);
+        assert_eq!(
+            config.check(UKRAINIAN, ENGLISH),
+            Some(LanguageMismatch { expected: "Ukrainian".to_string(), detected: "English".to_string() })
+        );
+        // A short answer cannot be judged
+        assert_eq!(config.check(UKRAINIAN, "OK."), None);
+    }
+
+    #[test]
+    fn test_instructions() {
+        assert!(instructions(Lang::Ukr, false).contains("Answer in Ukrainian only"));
+        assert!(instructions(Lang::Ukr, true).contains("Write the corrected text in Ukrainian only"));
+    }
+}
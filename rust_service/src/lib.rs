@@ -1,6 +1,19 @@
 // Export all modules needed for integration tests
 pub mod cache;
+pub mod chunking;
 pub mod config;
+pub mod diff;
 pub mod errors;
+pub mod explain;
+pub mod glossary;
 pub mod http;
-pub mod llm;
\ No newline at end of file
+pub mod language;
+pub mod llm;
+pub mod masking;
+pub mod postprocess;
+pub mod providers;
+pub mod redaction;
+pub mod response_limit;
+pub mod retry;
+pub mod stream;
+pub mod template;
\ No newline at end of file
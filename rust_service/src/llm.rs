@@ -1,227 +1,495 @@
-use reqwest::{header, Client};
+use futures_util::future::join_all;
+use futures_util::{Stream, StreamExt};
+use reqwest::{Client, RequestBuilder, Response};
 use serde_json::Value;
 use tracing::{debug, error, info, instrument, warn};
 
 use crate::config::AppConfig;
+use crate::diff;
 use crate::errors::AppError;
+use crate::explain;
+use crate::language::{self, LanguageMismatch};
+use crate::masking::{placeholder_instructions, MaskedText};
+use crate::providers::{provider_for, LlmProvider, TokenUsage};
+use crate::stream::json_messages;
+use crate::template::{self, TemplateVars, INPUT_VAR};
 
-// --- LLM Query Function ---
-#[instrument(skip_all)]
-pub async fn query_llm(
-    text: &str,
-    config: &AppConfig,
-    client: &Client,
-) -> Result<String, AppError> {
-    // Apply prompt template if configured
-    let final_prompt = if let Some(template) = &config.prompt_template {
+/// Apply the prompt template, if configured, to the user's text
+///
+/// Glossary terms that occur in the text are listed after it.
+fn build_prompt(text: &str, vars: &TemplateVars, config: &AppConfig) -> Result<String, AppError> {
+    let prompt = if let Some(template) = &config.prompt_template {
         debug!("Using prompt template: {}", template);
-        template.replace("{input}", text)
+        let mut vars = vars.clone();
+        vars.insert(INPUT_VAR.to_string(), text.to_string());
+        template::render(template, &vars)?
     } else {
         debug!("No prompt template configured, using raw text");
         text.to_string()
     };
+    Ok(match config.glossary.instructions(text) {
+        Some(glossary) => format!("{}\n\n{}", prompt, glossary),
+        None => prompt,
+    })
+}
 
-    // Construct payload format based on the LLM URL
-    let mut payload = if config.llm_url.contains("ollama")
-        || config.llm_url.contains("localhost:11434")
-    {
-        // Ollama API format for chat endpoint
-        serde_json::json!({
-            "model": config.model_name,
-            "messages": [
-                {
-                    "role": "system",
-                    "content": "You are a text improvement tool that corrects grammar and improves clarity without adding conversational elements. Follow the instructions exactly."
-                },
-                {
-                    "role": "user",
-                    "content": final_prompt
-                }
-            ],
-            "temperature": 0.3,
-            "top_p": 0.8,
-            "stream": false
-        })
+/// Redact and mask `text` for a backend; restore the output in the reverse order
+fn protect(text: &str, config: &AppConfig) -> Result<(MaskedText, MaskedText), AppError> {
+    let redacted = config.redaction.redact(text, &config.llm_url)?;
+    let masked = config.masking.mask(&redacted.text);
+    Ok((redacted, masked))
+}
+
+/// Put the masked spans, then the redacted values back into the model output
+///
+/// Explain mode replies are JSON objects, restored inside their strings.
+fn unprotect(output: &str, (redacted, masked): &(MaskedText, MaskedText), explain: bool) -> Result<String, AppError> {
+    if explain {
+        redacted.restore_explain(&masked.restore_explain(output)?)
     } else {
-        // OpenAI API format for /v1/responses endpoint
-        serde_json::json!({
-            "model": config.model_name,
-            "input": [
-                {
-                    "role": "system",
-                    "content": [
-                        {
-                            "type": "input_text",
-                            "text": "You are a text improvement tool that corrects grammar and improves clarity without adding conversational elements. Follow the instructions exactly."
-                        }
-                    ]
-                },
-                {
-                    "role": "user",
-                    "content": [
-                        {
-                            "type": "input_text",
-                            "text": final_prompt
-                        }
-                    ]
+        redacted.restore(&masked.restore(output)?)
+    }
+}
+
+/// Build the prompt for protected text, asking the model to keep the
+/// placeholders of each layer
+fn build_protected_prompt(
+    text: &str,
+    layers: &[&MaskedText],
+    vars: &TemplateVars,
+    config: &AppConfig,
+) -> Result<String, AppError> {
+    let prompt = build_prompt(text, vars, config)?;
+    Ok(match placeholder_instructions(layers) {
+        Some(instructions) => format!("{}\n\n{}", prompt, instructions),
+        None => prompt,
+    })
+}
+
+/// Send the request and turn transport failures and error statuses into `AppError`s
+///
+/// Retryable failures are retried according to `config.retry`.
+#[instrument(skip_all, fields(provider = provider.name()))]
+async fn send_request(
+    provider: &dyn LlmProvider,
+    req_builder: RequestBuilder,
+    config: &AppConfig,
+) -> Result<Response, AppError> {
+    let retry = &config.retry;
+    let max_attempts = retry.max_attempts.max(1);
+    let mut req_builder = req_builder;
+    let mut attempt = 1;
+
+    loop {
+        // Keep a copy for the next attempt; `send` consumes the builder
+        let next_builder = req_builder.try_clone();
+        info!("Sending request to {} API (attempt {}/{})", provider.name(), attempt, max_attempts);
+
+        let (err, delay) = match req_builder.send().await {
+            Ok(res) if res.status().is_success() => return Ok(res),
+            Ok(res) => {
+                let status = res.status();
+                let delay = retry.delay_after_status(status, res.headers(), attempt);
+                let error_body = res
+                    .text()
+                    .await
+                    .unwrap_or_else(|_| "Failed to read error body".to_string());
+                error!(
+                    "{} API returned error status {}: {}",
+                    provider.name(), status, error_body
+                );
+                let message = format!(
+                    "{} API error (Status {}): {}",
+                    provider.name(), status, error_body
+                );
+                let err = if status.is_server_error() {
+                    AppError::LlmUnavailable(message)
+                } else {
+                    AppError::LlmApiError(message)
+                };
+                (err, delay)
+            }
+            Err(e) => {
+                // Log detailed error information
+                error!("{} API request failed: {}", provider.name(), e);
+                if e.is_timeout() {
+                    error!("Request timed out - consider increasing the timeout value");
                 }
-            ],
-            "text": {
-                "format": {
-                    "type": "text"
+                if e.is_connect() {
+                    error!("Connection error - check your network connection and {} API status", provider.name());
                 }
-            },
-            "reasoning": {},
-            "tools": [],
-            "temperature": 0.7,
-            "max_output_tokens": 2048,
-            "top_p": 0.8,
-            "store": true
-        })
-    };
+                let delay = retry.delay_after_error(&e, attempt);
+                let message = format!("{} API request failed: {}", provider.name(), e);
+                let err = if e.is_connect() || e.is_timeout() {
+                    AppError::LlmUnavailable(message)
+                } else {
+                    AppError::LlmApiError(message)
+                };
+                (err, delay)
+            }
+        };
 
-    // Merge optional parameters from config file if they exist
-    if let Some(params_value) = &config.llm_params {
-        if let Some(params_map) = params_value.as_object() {
-            if let Some(payload_map) = payload.as_object_mut() {
-                for (key, value) in params_map {
-                    // Skip 'prompt_template' if it exists in the llm_params
-                    if key != "prompt_template" {
-                        payload_map.insert(key.clone(), value.clone());
-                    } else {
-                        warn!("Skipping 'prompt_template' parameter, as it should not be sent to the API");
-                    }
-                }
-            } else {
-                warn!("Payload is not a JSON object, cannot merge llm_params.");
+        match (delay, next_builder) {
+            (Some(delay), Some(next_builder)) => {
+                warn!(
+                    "Retrying {} API request in {}ms (attempt {}/{})",
+                    provider.name(),
+                    delay.as_millis(),
+                    attempt + 1,
+                    max_attempts
+                );
+                tokio::time::sleep(delay).await;
+                req_builder = next_builder;
+                attempt += 1;
             }
-        } else {
-            warn!("llm_params in config is not a JSON object.");
+            _ => return Err(err),
         }
     }
+}
 
-    info!("Sending request to LLM API");
-    debug!(target: "request_payload", "LLM Payload: {}", payload);
-
-    // Build the request with appropriate headers based on LLM provider
-    let mut req_builder = client
-        .post(&config.llm_url)
-        .header(header::CONTENT_TYPE, "application/json");
-
-    // Add authentication and headers based on LLM provider
-    if !(config.llm_url.contains("ollama") || config.llm_url.contains("localhost:11434")) {
-        // For OpenAI, we need API key authentication
-        let api_key = config.openai_api_key.clone().ok_or_else(|| {
-            error!("Missing OpenAI API key. Set OPENAI_API_KEY environment variable.");
-            AppError::LlmApiError("Missing OpenAI API key".to_string())
-        })?;
+/// Clean up the raw model output for `input` before it is returned or cached
+///
+/// Runs the `[post_process]` steps. Length limits are applied afterwards, see
+/// `ResponseLimitConfig`.
+pub fn finalize_response(content: &str, input: &str, config: &AppConfig) -> String {
+    config.post_process.apply(content, input)
+}
 
-        req_builder = req_builder.header("Authorization", format!("Bearer {}", api_key));
+// --- LLM Query Function ---
+/// Rewrite `text`, keeping its code spans, URLs, placeholders and language intact
+///
+/// Sensitive values are redacted first for non-local backends. If the model
+/// drops or alters a placeholder the request is sent again, up to
+/// `config.masking.max_attempts` times in total. A response in another
+/// language than the text is requested again with a stricter instruction, up
+/// to `config.language.max_attempts` times, and then returned as it is.
+#[instrument(skip_all)]
+pub async fn query_llm(
+    text: &str,
+    vars: &TemplateVars,
+    config: &AppConfig,
+    client: &Client,
+) -> Result<String, AppError> {
+    query_llm_metered(text, vars, config, client).await.map(|(output, _)| output)
+}
 
-        // Add optional organization ID if specified
-        if let Some(org_id) = &config.openai_org_id {
-            req_builder = req_builder.header("OpenAI-Organization", org_id);
-        }
+/// `query_llm`, also returning the tokens used by all attempts together
+async fn query_llm_metered(
+    text: &str,
+    vars: &TemplateVars,
+    config: &AppConfig,
+    client: &Client,
+) -> Result<(String, Option<TokenUsage>), AppError> {
+    let protected = protect(text, config)?;
+    let mut final_prompt = build_protected_prompt(&protected.1.text, &[&protected.0, &protected.1], vars, config)?;
+    let max_attempts = config.masking.max_attempts.max(1);
+    let max_language_attempts = config.language.max_attempts.max(1);
+    let language = config.language.detect(text);
+    let explain = explain::is_configured(config);
+    let mut attempt = 1;
+    let mut language_attempt = 1;
+    let mut usage = None;
 
-        // Add optional project ID if specified
-        if let Some(project_id) = &config.openai_project_id {
-            req_builder = req_builder.header("OpenAI-Project", project_id);
-        }
-    }
-    // For Ollama, no additional headers needed
-
-    // Finalize and send the request
-    let res = match req_builder.json(&payload).send().await {
-        Ok(response) => response,
-        Err(e) => {
-            // Log detailed error information
-            error!("OpenAI API request failed: {}", e);
-            if e.is_timeout() {
-                error!("Request timed out - consider increasing the timeout value");
+    loop {
+        let (content, attempt_usage) = query_prompt(&final_prompt, config, client).await?;
+        usage = TokenUsage::total([usage, attempt_usage]);
+        match check_output(&content, text, &protected, config, explain) {
+            CheckedOutput::Passed(restored) => return Ok((restored, usage)),
+            CheckedOutput::LostSpans(e) if attempt < max_attempts => {
+                warn!("{} (attempt {}/{}), asking again", e, attempt, max_attempts);
+                attempt += 1;
             }
-            if e.is_connect() {
-                error!("Connection error - check your internet connection and OpenAI API status");
+            CheckedOutput::LostSpans(e) => return Err(e),
+            CheckedOutput::WrongLanguage(_, mismatch) if language_attempt < max_language_attempts => {
+                warn!(
+                    "Model answered in {} instead of {} (attempt {}/{}), asking again",
+                    mismatch.detected, mismatch.expected, language_attempt, max_language_attempts
+                );
+                if let (1, Some(language)) = (language_attempt, language) {
+                    final_prompt = format!("{}\n\n{}", final_prompt, language::instructions(language, explain));
+                }
+                language_attempt += 1;
             }
-            return Err(AppError::LlmApiError(format!(
-                "OpenAI API request failed: {}",
-                e
-            )));
+            CheckedOutput::WrongLanguage(restored, _) => return Ok((restored, usage)),
         }
+    }
+}
+
+/// A model output after `check_output`
+enum CheckedOutput {
+    /// Restored, and in the language of the request text
+    Passed(String),
+    /// A placeholder was dropped or altered
+    LostSpans(AppError),
+    /// Restored, but in another language than the request text
+    WrongLanguage(String, LanguageMismatch),
+}
+
+/// Clean up and restore one model output for `text`, then check its language
+fn check_output(
+    content: &str,
+    text: &str,
+    protected: &(MaskedText, MaskedText),
+    config: &AppConfig,
+    explain: bool,
+) -> CheckedOutput {
+    // Before unmasking, so that fences and quotes in protected spans are kept
+    let content = finalize_response(content, &protected.1.text, config);
+    let restored = match unprotect(&content, protected, explain) {
+        Ok(restored) => restored,
+        Err(e) => return CheckedOutput::LostSpans(e),
     };
 
-    let status = res.status();
-    if !status.is_success() {
-        let error_body = res
-            .text()
-            .await
-            .unwrap_or_else(|_| "Failed to read error body".to_string());
-        error!(
-            "OpenAI API returned error status {}: {}",
-            status, error_body
-        );
-        return Err(AppError::LlmApiError(format!(
-            "OpenAI API error (Status {}): {}",
-            status, error_body
-        )));
+    // In explain mode the output is a JSON object with English explanations;
+    // only its corrected text has to keep the language
+    let mismatch = if explain {
+        config.language.check(text, &explain::parse(&restored).corrected)
+    } else {
+        config.language.check(text, &restored)
+    };
+    match mismatch {
+        Some(mismatch) => CheckedOutput::WrongLanguage(restored, mismatch),
+        None => CheckedOutput::Passed(restored),
     }
+}
+
+/// Send a finished prompt and return the model output and the tokens it used
+async fn query_prompt(
+    final_prompt: &str,
+    config: &AppConfig,
+    client: &Client,
+) -> Result<(String, Option<TokenUsage>), AppError> {
+    // The configured provider decides the payload, headers and response format
+    let provider = provider_for(config.provider);
+    let req_builder = provider.build_request(client, config, final_prompt, false)?;
+    let res = send_request(provider.as_ref(), req_builder, config).await?;
 
-    // Parse response based on API used
     let response_data = res.json::<Value>().await?;
     debug!("Received LLM response data: {:?}", response_data);
 
-    // Parse Ollama or OpenAI response format
-    if config.llm_url.contains("ollama") || config.llm_url.contains("localhost:11434") {
-        // Parse Ollama response format
-        if let Some(message) = response_data.get("message") {
-            if let Some(content) = message.get("content").and_then(Value::as_str) {
-                let trimmed = content.trim();
-                let max_length = 2000; // Limit response to 2000 characters
-                if trimmed.len() > max_length {
-                    info!(
-                        "LLM response was truncated from {} to {} characters",
-                        trimmed.len(),
-                        max_length
+    if let Some(content) = provider.parse_response(&response_data) {
+        return Ok((content, provider.parse_usage(&response_data)));
+    }
+
+    Err(unrecognized_response(&response_data))
+}
+
+/// Error for a successful response whose body we cannot find the text in
+fn unrecognized_response(response_data: &Value) -> AppError {
+    warn!("LLM response format not recognized: {:?}", response_data);
+    AppError::LlmApiError(format!(
+        "Unrecognized LLM response format. Received: {}",
+        serde_json::to_string(response_data)
+            .unwrap_or_else(|_| "Non-serializable response".to_string())
+    ))
+}
+
+/// Temperature of the first alternative when `llm_params` sets none
+const DEFAULT_CANDIDATE_TEMPERATURE: f64 = 0.7;
+/// Temperature increase for each further alternative
+const CANDIDATE_TEMPERATURE_STEP: f64 = 0.2;
+const MAX_CANDIDATE_TEMPERATURE: f64 = 1.5;
+/// Candidates at least this similar (ignoring case and spacing) count as duplicates
+const DUPLICATE_SIMILARITY: f64 = 0.97;
+
+/// Drop candidates that are near-identical to an earlier one
+fn dedup_candidates(candidates: Vec<String>) -> Vec<String> {
+    let normalize = |text: &str| text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+
+    let mut kept: Vec<(String, String)> = Vec::new();
+    for candidate in candidates {
+        let normalized = normalize(&candidate);
+        if kept.iter().any(|(_, other)| diff::similarity(&normalized, other) >= DUPLICATE_SIMILARITY) {
+            debug!("Dropping near-duplicate candidate");
+            continue;
+        }
+        kept.push((candidate, normalized));
+    }
+    kept.into_iter().map(|(candidate, _)| candidate).collect()
+}
+
+/// Rewrites returned by the backend, best first
+#[derive(Debug)]
+pub struct LlmOutput {
+    /// Never empty
+    pub candidates: Vec<String>,
+    /// Tokens used by every request made for them, if the backend reports it
+    pub usage: Option<TokenUsage>,
+    /// Candidates left out because they failed a check or their request
+    /// failed; near-duplicates are not counted
+    pub dropped: usize,
+}
+
+/// Ask for up to `n` alternative rewrites, in the order the model ranks them
+///
+/// Providers that return several candidates per response get a single request
+/// with `n` set. Each candidate is checked as by `query_llm`: candidates that
+/// lost a protected span or switched languages are dropped, and the request is
+/// sent again only if none is left. For the other providers one request per
+/// candidate is sent in parallel, each at a higher temperature than the one
+/// before, and the request only fails if all of them do. Near-identical
+/// candidates are dropped.
+#[instrument(skip_all)]
+pub async fn query_llm_candidates(
+    text: &str,
+    vars: &TemplateVars,
+    config: &AppConfig,
+    client: &Client,
+    n: usize,
+) -> Result<LlmOutput, AppError> {
+    if n <= 1 {
+        let (output, usage) = query_llm_metered(text, vars, config, client).await?;
+        return Ok(LlmOutput { candidates: vec![output], usage, dropped: 0 });
+    }
+
+    let provider = provider_for(config.provider);
+    let (candidates, usage, dropped) = if provider.supports_multiple_candidates() {
+        let protected = protect(text, config)?;
+        let explain = explain::is_configured(config);
+        let language = config.language.detect(text);
+        let mut final_prompt = build_protected_prompt(&protected.1.text, &[&protected.0, &protected.1], vars, config)?;
+        let mut config = config.clone();
+        config.set_llm_param("n", Value::from(n));
+        let max_attempts = config.masking.max_attempts.max(1);
+        let max_language_attempts = config.language.max_attempts.max(1);
+        let mut attempt = 1;
+        let mut language_attempt = 1;
+        let mut usage = None;
+
+        loop {
+            let req_builder = provider.build_request(client, &config, &final_prompt, false)?;
+            let res = send_request(provider.as_ref(), req_builder, &config).await?;
+            let response_data = res.json::<Value>().await?;
+            let outputs = provider.parse_candidates(&response_data);
+            if outputs.is_empty() {
+                return Err(unrecognized_response(&response_data));
+            }
+            usage = TokenUsage::total([usage, provider.parse_usage(&response_data)]);
+
+            let mut passed = Vec::new();
+            let mut wrong_language = Vec::new();
+            let mut first_error = None;
+            for output in &outputs {
+                match check_output(output, text, &protected, &config, explain) {
+                    CheckedOutput::Passed(candidate) => passed.push(candidate),
+                    CheckedOutput::WrongLanguage(candidate, mismatch) => {
+                        warn!("Alternative is in {} instead of {}", mismatch.detected, mismatch.expected);
+                        wrong_language.push(candidate);
+                    }
+                    CheckedOutput::LostSpans(e) => {
+                        warn!("Dropping alternative: {}", e);
+                        first_error.get_or_insert(e);
+                    }
+                }
+            }
+
+            if !passed.is_empty() {
+                let dropped = outputs.len() - passed.len();
+                break (passed, usage, dropped);
+            }
+            if !wrong_language.is_empty() {
+                if language_attempt < max_language_attempts {
+                    warn!(
+                        "Every alternative switched languages (attempt {}/{}), asking again",
+                        language_attempt, max_language_attempts
                     );
-                    return Ok(trimmed[..max_length].to_string() + "...");
+                    if let (1, Some(language)) = (language_attempt, language) {
+                        final_prompt = format!("{}\n\n{}", final_prompt, language::instructions(language, explain));
+                    }
+                    language_attempt += 1;
+                    continue;
+                }
+                // As for a single response, the last attempt is returned as it is
+                let dropped = outputs.len() - wrong_language.len();
+                break (wrong_language, usage, dropped);
+            }
+            match first_error {
+                Some(e) if attempt < max_attempts => {
+                    warn!("{} in every alternative (attempt {}/{}), asking again", e, attempt, max_attempts);
+                    attempt += 1;
                 }
-                return Ok(trimmed.to_string());
+                Some(e) => return Err(e),
+                None => return Err(unrecognized_response(&response_data)),
             }
         }
     } else {
-        // Parse OpenAI response format for /v1/responses endpoint
-        if let Some(output_array) = response_data.get("output").and_then(Value::as_array) {
-            // Look for the first message in the output array
-            if let Some(first_output) = output_array.first() {
-                // Check for content array in the message
-                if let Some(content_array) = first_output.get("content").and_then(Value::as_array) {
-                    // Look for text in the first content item
-                    if let Some(first_content) = content_array.first() {
-                        if let Some(text) = first_content.get("text").and_then(Value::as_str) {
-                            let trimmed = text.trim();
-                            let max_length = 2000; // Limit response to 2000 characters
-                            if trimmed.len() > max_length {
-                                info!(
-                                    "LLM response was truncated from {} to {} characters",
-                                    trimmed.len(),
-                                    max_length
-                                );
-                                return Ok(trimmed[..max_length].to_string() + "...");
-                            }
-                            return Ok(trimmed.to_string());
-                        }
-                    }
+        let base_temperature = config
+            .llm_params
+            .as_ref()
+            .and_then(|params| params.get("temperature").or_else(|| params.pointer("/options/temperature")))
+            .and_then(Value::as_f64)
+            .unwrap_or(DEFAULT_CANDIDATE_TEMPERATURE);
+        let configs: Vec<AppConfig> = (0..n)
+            .map(|index| {
+                let mut config = config.clone();
+                if index > 0 {
+                    let temperature = (base_temperature + CANDIDATE_TEMPERATURE_STEP * index as f64)
+                        .min(MAX_CANDIDATE_TEMPERATURE);
+                    config.set_llm_param("temperature", Value::from((temperature * 100.0).round() / 100.0));
+                }
+                config
+            })
+            .collect();
+
+        let results = join_all(configs.iter().map(|config| query_llm_metered(text, vars, config, client))).await;
+        let mut candidates = Vec::new();
+        let mut usage = None;
+        let mut first_error = None;
+        for result in results {
+            match result {
+                Ok((candidate, candidate_usage)) => {
+                    candidates.push(candidate);
+                    usage = TokenUsage::total([usage, candidate_usage]);
+                }
+                Err(e) => {
+                    warn!("Alternative request failed: {}", e);
+                    first_error.get_or_insert(e);
                 }
             }
         }
-    }
+        if let Some(e) = first_error.filter(|_| candidates.is_empty()) {
+            return Err(e);
+        }
+        let dropped = n - candidates.len();
+        (candidates, usage, dropped)
+    };
 
-    // Fallback if response format is unexpected
-    warn!("LLM response format not recognized: {:?}", response_data);
-    Err(AppError::LlmApiError(format!(
-        "Unrecognized LLM response format. Received: {}",
-        serde_json::to_string(&response_data)
-            .unwrap_or_else(|_| "Non-serializable response".to_string())
-    )))
+    info!("Received {} candidates for {} requested", candidates.len(), n);
+    Ok(LlmOutput { candidates: dedup_candidates(candidates), usage, dropped })
+}
+
+// --- LLM Streaming Function ---
+/// Start a streaming request and return the text deltas as they arrive
+///
+/// Errors before the first byte (bad status, connection failure) are returned
+/// directly; errors mid-stream are yielded as items. Text is redacted and
+/// masked as for `query_llm` and put back into the deltas. Since the deltas
+/// are forwarded as they arrive, a dropped placeholder cannot be asked for
+/// again and fails the stream at its end instead.
+#[instrument(skip_all)]
+pub async fn stream_llm(
+    text: &str,
+    vars: &TemplateVars,
+    config: &AppConfig,
+    client: &Client,
+) -> Result<impl Stream<Item = Result<String, AppError>> + Send + 'static, AppError> {
+    let (redacted, masked) = protect(text, config)?;
+    let final_prompt = build_protected_prompt(&masked.text, &[&redacted, &masked], vars, config)?;
+
+    let provider = provider_for(config.provider);
+    let req_builder = provider.build_request(client, config, &final_prompt, true)?;
+    let res = send_request(provider.as_ref(), req_builder, config).await?;
+
+    let messages = json_messages(res.bytes_stream(), provider.stream_format());
+    let deltas = messages.filter_map(move |message| {
+        // Messages without text (role headers, final `done` markers) are dropped
+        let delta = message
+            .and_then(|chunk| provider.parse_stream_chunk(&chunk))
+            .map(|delta| delta.filter(|text| !text.is_empty()))
+            .transpose();
+        std::future::ready(delta)
+    });
+    Ok(redacted.restore_deltas(masked.restore_deltas(deltas)))
 }
 
 #[cfg(test)]
@@ -229,9 +497,45 @@ mod tests {
     use super::*;
     use serde_json::json;
     use wiremock::{MockServer, Mock, ResponseTemplate};
-    use wiremock::matchers::method;
+    use wiremock::matchers::{body_partial_json, header, method, path};
     use crate::config::AppConfig;
 
+    #[test]
+    fn test_dedup_candidates() {
+        let candidates = vec![
+            "I'm not used to it.".to_string(),
+            "I'm  not used to it".to_string(),
+            "I am not used to it yet.".to_string(),
+            "i'm not used to it.".to_string(),
+        ];
+        assert_eq!(
+            dedup_candidates(candidates),
+            vec!["I'm not used to it.", "I am not used to it yet."]
+        );
+    }
+
+    #[test]
+    fn test_build_prompt() {
+        let mut config = AppConfig {
+            provider: crate::providers::ProviderKind::Ollama,
+            llm_url: "http://localhost:11434/api/chat".to_string(),
+            model_name: "mistral:latest".to_string(),
+            prompt_template: Some("Rewrite for {{app}}:\n\n{{input}}".to_string()),
+            cache: crate::cache::CacheConfig { enabled: false, ..Default::default() },
+            ..Default::default()
+        };
+        let mut vars = TemplateVars::new();
+        vars.insert("app".to_string(), "Slack".to_string());
+        // The user's text always wins over a request variable named `input`
+        vars.insert("input".to_string(), "not this".to_string());
+
+        let prompt = build_prompt("hey whats up", &vars, &config).unwrap();
+        assert_eq!(prompt, "Rewrite for Slack:\n\nhey whats up");
+
+        config.prompt_template = None;
+        assert_eq!(build_prompt("hey whats up", &vars, &config).unwrap(), "hey whats up");
+    }
+
     #[tokio::test]
     #[ignore] // Mark as ignored because wiremock implementation is complex
     async fn test_prompt_template_application() {
@@ -319,19 +623,11 @@ mod tests {
         // Test error handling for various scenarios
         // 1. API Error Response
         let config = AppConfig {
-            port: 8989,
+            provider: crate::providers::ProviderKind::OpenAi,
             llm_url: "https://api.example.com/v1/chat".to_string(),
             model_name: "test-model".to_string(),
-            llm_params: None,
-            prompt_template: None,
             openai_api_key: Some("test-key".to_string()),
-            openai_org_id: None,
-            openai_project_id: None,
-            cache: crate::cache::CacheConfig {
-                enabled: true,
-                ttl_days: 30,
-                max_size_mb: 100,
-            },
+            ..Default::default()
         };
 
         // Set up a mock HTTP server for error response
@@ -357,7 +653,7 @@ mod tests {
         let client = Client::new();
 
         // Call query_llm with test input
-        let result = query_llm("Test input", &test_config, &client).await;
+        let result = query_llm("Test input", &TemplateVars::new(), &test_config, &client).await;
 
         // Verify the error
         assert!(result.is_err());
@@ -371,4 +667,247 @@ mod tests {
             }
         }
     }
+
+    #[tokio::test]
+    async fn test_ollama_provider_on_custom_host() {
+        // An Ollama server that is neither on localhost:11434 nor has "ollama" in its URL
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/api/chat"))
+            .and(body_partial_json(json!({ "model": "mistral:latest", "stream": false })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "message": {
+                    "role": "assistant",
+                    "content": "  Improved text from a remote Ollama  "
+                }
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let config = AppConfig {
+            provider: crate::providers::ProviderKind::Ollama,
+            llm_url: format!("{}/api/chat", mock_server.uri()),
+            model_name: "mistral:latest".to_string(),
+            cache: crate::cache::CacheConfig { enabled: false, ..Default::default() },
+            ..Default::default()
+        };
+
+        let result = query_llm("Test input", &TemplateVars::new(), &config, &Client::new()).await.unwrap();
+        assert_eq!(result, "Improved text from a remote Ollama");
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_provider() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/v1/messages"))
+            .and(header("x-api-key", "sk-ant-test-key"))
+            .and(header("anthropic-version", "2023-06-01"))
+            .and(body_partial_json(json!({
+                "model": "claude-sonnet-4-5",
+                "messages": [{ "role": "user", "content": "Test input" }]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "msg_01",
+                "type": "message",
+                "role": "assistant",
+                "content": [{ "type": "text", "text": "Improved text from Claude" }],
+                "stop_reason": "end_turn"
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut config = AppConfig {
+            provider: crate::providers::ProviderKind::Anthropic,
+            llm_url: format!("{}/v1/messages", mock_server.uri()),
+            model_name: "claude-sonnet-4-5".to_string(),
+            anthropic_api_key: Some("sk-ant-test-key".to_string()),
+            cache: crate::cache::CacheConfig { enabled: false, ..Default::default() },
+            ..Default::default()
+        };
+
+        let client = Client::new();
+        let result = query_llm("Test input", &TemplateVars::new(), &config, &client).await.unwrap();
+        assert_eq!(result, "Improved text from Claude");
+
+        // Server errors from the Messages API are reported like any other provider's
+        Mock::given(method("POST"))
+            .and(path("/v1/overloaded"))
+            .respond_with(ResponseTemplate::new(500).set_body_json(json!({
+                "type": "error",
+                "error": { "type": "overloaded_error", "message": "Overloaded" }
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        config.llm_url = format!("{}/v1/overloaded", mock_server.uri());
+        config.retry.max_attempts = 1;
+        match query_llm("Test input", &TemplateVars::new(), &config, &client).await {
+            Err(AppError::LlmUnavailable(msg)) => {
+                assert!(msg.contains("Anthropic API error"));
+                assert!(msg.contains("Overloaded"));
+            }
+            other => panic!("Expected LlmUnavailable, got: {:?}", other),
+        }
+    }
+
+    fn retry_test_config(llm_url: String) -> AppConfig {
+        AppConfig {
+            provider: crate::providers::ProviderKind::OpenAiCompatible,
+            llm_url,
+            model_name: "qwen2.5-7b-instruct".to_string(),
+            retry: crate::retry::RetryConfig {
+                base_delay_ms: 10,
+                ..crate::retry::RetryConfig::default()
+            },
+            cache: crate::cache::CacheConfig { enabled: false, ..Default::default() },
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_on_rate_limit() {
+        let mock_server = MockServer::start().await;
+
+        // First attempt is rate limited, the retry succeeds
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "0"))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "choices": [{ "message": { "role": "assistant", "content": "Recovered" } }]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let config = retry_test_config(format!("{}/v1/chat/completions", mock_server.uri()));
+        let result = query_llm("Test input", &TemplateVars::new(), &config, &Client::new()).await.unwrap();
+        assert_eq!(result, "Recovered");
+    }
+
+    #[tokio::test]
+    async fn test_retry_gives_up_after_max_attempts() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503).set_body_string("Service Unavailable"))
+            .expect(3)
+            .mount(&mock_server)
+            .await;
+
+        let config = retry_test_config(format!("{}/v1/chat/completions", mock_server.uri()));
+        match query_llm("Test input", &TemplateVars::new(), &config, &Client::new()).await {
+            Err(AppError::LlmUnavailable(msg)) => assert!(msg.contains("Status 503")),
+            other => panic!("Expected LlmUnavailable, got: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_no_retry_on_client_error() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(400).set_body_string("Bad Request"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let config = retry_test_config(format!("{}/v1/chat/completions", mock_server.uri()));
+        assert!(query_llm("Test input", &TemplateVars::new(), &config, &Client::new()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_retry_on_connection_error() {
+        // Nothing listens on this port, so every attempt fails to connect
+        let mut config = retry_test_config("http://127.0.0.1:9/v1/chat/completions".to_string());
+        config.retry.max_attempts = 2;
+
+        let start = std::time::Instant::now();
+        match query_llm("Test input", &TemplateVars::new(), &config, &Client::new()).await {
+            Err(AppError::LlmUnavailable(msg)) => assert!(msg.contains("API request failed")),
+            other => panic!("Expected LlmUnavailable, got: {:?}", other),
+        }
+        // One backoff delay (5-10ms with jitter) was spent between the attempts
+        assert!(start.elapsed() >= std::time::Duration::from_millis(5));
+    }
+
+    fn chat_reply(content: &str) -> ResponseTemplate {
+        ResponseTemplate::new(200).set_body_json(json!({
+            "choices": [{ "message": { "role": "assistant", "content": content } }]
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_masked_spans_are_restored() {
+        let mock_server = MockServer::start().await;
+
+        // The model "fixes" the code span the first time, so it is asked again
+        Mock::given(method("POST"))
+            .respond_with(chat_reply("Please run cargo test for ABC-12."))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(chat_reply("Please run [[KEEP_0]] for [[KEEP_1]]."))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let config = retry_test_config(format!("{}/v1/chat/completions", mock_server.uri()));
+        let result = query_llm("pls run `cargo tset` for ABC-12", &TemplateVars::new(), &config, &Client::new())
+            .await
+            .unwrap();
+        assert_eq!(result, "Please run `cargo tset` for ABC-12.");
+
+        let requests = mock_server.received_requests().await.unwrap();
+        let body: Value = serde_json::from_slice(&requests[0].body).unwrap();
+        let prompt = body["messages"][1]["content"].as_str().unwrap();
+        assert!(prompt.starts_with("pls run [[KEEP_0]] for [[KEEP_1]]\n\n"));
+        assert!(!prompt.contains("cargo tset"));
+    }
+
+    #[tokio::test]
+    async fn test_masked_span_dropped_on_every_attempt() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .respond_with(chat_reply("See the docs."))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let config = retry_test_config(format!("{}/v1/chat/completions", mock_server.uri()));
+        match query_llm("See https://docs.rs/regex.", &TemplateVars::new(), &config, &Client::new()).await {
+            Err(AppError::LlmApiError(msg)) => assert!(msg.contains("https://docs.rs/regex")),
+            other => panic!("Expected LlmApiError, got: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_model_chatter_is_removed() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .respond_with(chat_reply("Here is the corrected text:\n\n\"Please run [[KEEP_0]].\"  "))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let config = retry_test_config(format!("{}/v1/chat/completions", mock_server.uri()));
+        let result = query_llm("pls run `\"x\"`\n", &TemplateVars::new(), &config, &Client::new()).await.unwrap();
+        // The quotes of the code span survive, and the trailing newline is kept
+        assert_eq!(result, "Please run `\"x\"`.\n");
+    }
 }
@@ -1,10 +1,23 @@
 mod cache;
+mod chunking;
 mod config;
+mod diff;
 mod errors;
+mod explain;
+mod glossary;
 mod http;
+mod language;
 mod llm;
+mod masking;
+mod postprocess;
+mod providers;
+mod redaction;
+mod response_limit;
+mod retry;
+mod stream;
+mod template;
 
-use axum::{routing::post, Router};
+use axum::{routing::{get, post}, Router};
 use reqwest::Client;
 use std::{net::SocketAddr, sync::Arc, path::PathBuf};
 use tokio::net::TcpListener;
@@ -14,7 +27,8 @@ use tracing_subscriber::{fmt, EnvFilter};
 use crate::cache::CacheManager;
 use crate::config::load_config;
 use crate::errors::AppError;
-use crate::http::process_text_handler;
+use crate::http::{apply_handler, backend_fingerprints, list_modes_handler, process_stream_handler, process_text_handler};
+use crate::providers::provider_for;
 
 // --- Main Application Logic ---
 #[tokio::main]
@@ -36,129 +50,26 @@ async fn main() -> Result<(), AppError> {
     let shared_config = Arc::new(config);
 
     // Build HTTP client
+    // No total timeout, which would cut off long streams; instead a backend
+    // has to connect within 10 seconds and send something at least every 60
     let http_client = Client::builder()
-        .timeout(std::time::Duration::from_secs(60)) // 60 seconds timeout for LLMs
+        .connect_timeout(std::time::Duration::from_secs(10))
+        .read_timeout(std::time::Duration::from_secs(60))
         .build()?;
     let shared_client = Arc::new(http_client.clone());
     
-    // Test LLM API connectivity on startup based on the configured LLM provider
-    let is_ollama = shared_config.llm_url.contains("ollama") || shared_config.llm_url.contains("localhost:11434");
-    
-    if is_ollama {
-        // Ollama doesn't require API key
-        info!("Testing connection to Ollama API");
-        info!("Using Ollama endpoint: {}", shared_config.llm_url);
-        info!("Using model: {}", shared_config.model_name);
-        
-        // Test connection to Ollama API using the list models endpoint
-        let test_url = if shared_config.llm_url.ends_with("/chat") {
-            shared_config.llm_url.replace("/chat", "/models")
-        } else {
-            "http://localhost:11434/api/models".to_string()
-        };
-        
-        info!("Testing Ollama API connectivity...");
-        match http_client.get(&test_url).timeout(std::time::Duration::from_secs(5)).send().await {
-            Ok(resp) => {
-                if resp.status().is_success() {
-                    info!("✅ Successfully connected to Ollama API");
-                    let model_name = &shared_config.model_name;
-                    info!("Using model: {}", model_name);
-                } else {
-                    warn!("⚠️ Ollama API responded with status code: {}", resp.status());
-                    match resp.text().await {
-                        Ok(body) => warn!("Response body: {}", body),
-                        Err(_) => warn!("Could not read error response body"),
-                    }
-                }
-            },
+    // Test LLM API connectivity on startup for every configured backend
+    for backend in shared_config.backends() {
+        let provider = provider_for(backend.provider);
+        info!("Testing connection to {} API", provider.name());
+        info!("Using endpoint: {}", backend.llm_url);
+        info!("Using model: {}", backend.model_name);
+
+        match provider.health_check(&http_client, &backend).await {
+            Ok(()) => info!("✅ Successfully connected to {} API", provider.name()),
             Err(e) => {
-                warn!("⚠️ Failed to connect to Ollama API: {}", e);
-                if e.is_timeout() {
-                    warn!("Connection timed out - Ollama may not be running");
-                } else if e.is_connect() {
-                    warn!("Connection error - check if Ollama is running and accessible at localhost:11434");
-                }
-            }
-        }
-    } else {
-        // OpenAI API connectivity test
-        info!("Testing connection to OpenAI API");
-        
-        // Verify API key is configured
-        match &shared_config.openai_api_key {
-            Some(api_key) if !api_key.is_empty() => {
-                // Only show masking for actual keys, not empty ones
-                if api_key.len() > 8 {
-                    let masked_key = format!("{}...{}", &api_key[..4], &api_key[api_key.len()-4..]);
-                    info!("✅ OpenAI API key is configured: {}", masked_key);
-                } else {
-                    info!("✅ OpenAI API key is configured");
-                }
-                
-                // Log org ID if present
-                if let Some(org_id) = &shared_config.openai_org_id {
-                    if !org_id.is_empty() {
-                        info!("✅ Using OpenAI Organization ID: {}", org_id);
-                    }
-                }
-                
-                // Log project ID if present
-                if let Some(project_id) = &shared_config.openai_project_id {
-                    if !project_id.is_empty() {
-                        info!("✅ Using OpenAI Project ID: {}", project_id);
-                    }
-                }
-                
-                // Test connection to OpenAI API using the models endpoint
-                info!("Testing OpenAI API connectivity...");
-                let mut req_builder = http_client
-                    .get("https://api.openai.com/v1/models")
-                    .timeout(std::time::Duration::from_secs(5))
-                    .header("Authorization", format!("Bearer {}", api_key));
-                
-                // Add org ID if configured
-                if let Some(org_id) = &shared_config.openai_org_id {
-                    if !org_id.is_empty() {
-                        req_builder = req_builder.header("OpenAI-Organization", org_id);
-                    }
-                }
-                
-                // Add project ID if configured
-                if let Some(project_id) = &shared_config.openai_project_id {
-                    if !project_id.is_empty() {
-                        req_builder = req_builder.header("OpenAI-Project", project_id);
-                    }
-                }
-                
-                // Send test request
-                match req_builder.send().await {
-                    Ok(resp) => {
-                        if resp.status().is_success() {
-                            info!("✅ Successfully connected to OpenAI API");
-                            let model_name = &shared_config.model_name;
-                            info!("Using model: {}", model_name);
-                        } else {
-                            warn!("⚠️ OpenAI API responded with status code: {}", resp.status());
-                            match resp.text().await {
-                                Ok(body) => warn!("Response body: {}", body),
-                                Err(_) => warn!("Could not read error response body"),
-                            }
-                        }
-                    },
-                    Err(e) => {
-                        warn!("⚠️ Failed to connect to OpenAI API: {}", e);
-                        if e.is_timeout() {
-                            warn!("Connection timed out - OpenAI API may be temporarily unavailable");
-                        } else if e.is_connect() {
-                            warn!("Connection error - check your internet connection and firewall settings");
-                        }
-                    }
-                }
-            },
-            _ => {
-                warn!("⚠️ No OpenAI API key configured! The service will not work with OpenAI.");
-                warn!("Set the OPENAI_API_KEY environment variable or add it to the config file.");
+                warn!("⚠️ {} API health check failed: {}", provider.name(), e);
+                warn!("The service will start, but requests may fail until the backend is reachable.");
             }
         }
     }
@@ -169,7 +80,7 @@ async fn main() -> Result<(), AppError> {
         .join("writer_ai_service");
     
     std::fs::create_dir_all(&cache_dir)
-        .map_err(|e| AppError::Io(e))?;
+        .map_err(AppError::Io)?;
     
     let cache_path = cache_dir.join("response_cache.sled");
     info!("Initializing cache at: {:?}", cache_path);
@@ -183,6 +94,12 @@ async fn main() -> Result<(), AppError> {
         info!("Response caching is enabled (TTL: {} days, Max size: {} MB)", 
             shared_config.cache.ttl_days, 
             shared_config.cache.max_size_mb);
+        if shared_config.cache.invalidate_changed_params {
+            let removed = cache_manager.invalidate_other_backends(&backend_fingerprints(&shared_config))?;
+            if removed > 0 {
+                info!("Removed {} cache entries made with other backend settings", removed);
+            }
+        }
     } else {
         info!("Response caching is disabled");
     }
@@ -193,6 +110,9 @@ async fn main() -> Result<(), AppError> {
     // Build application router
     let app = Router::new()
         .route("/process", post(process_text_handler))
+        .route("/process/stream", post(process_stream_handler))
+        .route("/modes", get(list_modes_handler))
+        .route("/apply", post(apply_handler))
         .with_state(app_state);
 
     // Define the server address
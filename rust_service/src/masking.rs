@@ -0,0 +1,369 @@
+use std::collections::BTreeSet;
+use std::ops::Range;
+use std::sync::LazyLock;
+
+use futures_util::{stream, Stream, StreamExt};
+use regex::{Captures, Regex};
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::debug;
+
+use crate::errors::AppError;
+
+/// Spans the model must not rewrite: fenced code blocks, inline code,
+/// `{{template}}` placeholders, URLs and Jira issue keys
+static PROTECTED: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(concat!(
+        r"(?s:```.*?```)",
+        r"|(?s:~~~.*?~~~)",
+        r"|`[^`\n]+`",
+        r"|\{\{[^{}\n]*\}\}",
+        // Trailing punctuation belongs to the sentence, not the URL
+        r#"|(?:https?://|www\.)[^\s<>\[\]]*[^\s<>\[\].,;:!?'")]"#,
+        r"|\b[A-Z][A-Z0-9]+-[0-9]+\b",
+    ))
+    .expect("protected span pattern is valid")
+});
+
+/// Placeholders as they appear in the model output, altered ones included
+static PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[\[([A-Z][A-Z0-9]*)_([0-9]+)\]\]").expect("placeholder pattern is valid"));
+
+/// The start of a placeholder at the very end of a text
+static PARTIAL_PLACEHOLDER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\[(?:\[(?:[A-Z][A-Z0-9]*(?:_[0-9]*\]?)?)?)?\z").expect("partial placeholder pattern is valid")
+});
+
+/// Label of the placeholders for protected spans
+const PROTECTED_LABEL: &str = "KEEP";
+
+/// Protection of code, URLs and placeholders from being rewritten
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MaskingConfig {
+    pub enabled: bool,
+    /// Total attempts when the model drops or alters a placeholder
+    pub max_attempts: u32,
+}
+
+impl Default for MaskingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_attempts: 2,
+        }
+    }
+}
+
+/// Text with some spans swapped for opaque `[[LABEL_n]]` placeholders
+#[derive(Debug, PartialEq)]
+pub struct MaskedText {
+    pub text: String,
+    /// Placeholder and original text of each span, in placeholder order
+    spans: Vec<(String, String)>,
+}
+
+impl MaskingConfig {
+    /// Replace the protected spans of `text`, unless masking is disabled
+    pub fn mask(&self, text: &str) -> MaskedText {
+        if !self.enabled {
+            return MaskedText::new(text);
+        }
+        let masked = mask_spans(text);
+        if !masked.spans.is_empty() {
+            debug!("Masked {} protected spans", masked.spans.len());
+        }
+        masked
+    }
+}
+
+/// Replace the protected spans of `text`, whatever the config says
+///
+/// Also used to keep post-processing away from code and URLs.
+pub(crate) fn mask_spans(text: &str) -> MaskedText {
+    let mut masked = MaskedText::new(text);
+    masked.mask_matches(&PROTECTED, PROTECTED_LABEL, |_| true);
+    masked
+}
+
+/// Byte ranges of the protected spans of `text`, e.g. to keep chunks from splitting them
+pub(crate) fn protected_ranges(text: &str) -> Vec<Range<usize>> {
+    PROTECTED.find_iter(text).map(|m| m.range()).collect()
+}
+
+/// `text` without its protected spans, for checks that only concern the prose
+pub(crate) fn strip_spans(text: &str) -> String {
+    PROTECTED.replace_all(text, " ").into_owned()
+}
+
+/// Prompt addition telling the model to keep the placeholders, if there are any
+pub fn placeholder_instructions(layers: &[&MaskedText]) -> Option<String> {
+    let examples: Vec<&str> = layers
+        .iter()
+        .filter_map(|layer| layer.spans.first())
+        .map(|(placeholder, _)| placeholder.as_str())
+        .collect();
+    (!examples.is_empty()).then(|| {
+        format!(
+            "The text contains placeholders such as {}. Keep every placeholder exactly as written, in the same place.",
+            examples.join(" and ")
+        )
+    })
+}
+
+impl MaskedText {
+    /// Text with nothing masked yet
+    pub fn new(text: &str) -> Self {
+        MaskedText { text: text.to_string(), spans: Vec::new() }
+    }
+
+    /// Swap each match of `pattern` that passes `accept` for a `[[LABEL_n]]`
+    /// placeholder and return how many were swapped; repeated values share one
+    pub(crate) fn mask_matches(&mut self, pattern: &Regex, label: &str, accept: impl Fn(&str) -> bool) -> usize {
+        let mut count = 0;
+        let spans = &mut self.spans;
+        let masked = pattern.replace_all(&self.text, |caps: &Captures| {
+            let value = &caps[0];
+            if !accept(value) {
+                return value.to_string();
+            }
+            count += 1;
+            let existing = spans.iter().find(|(placeholder, original)| {
+                original == value && placeholder.starts_with(&format!("[[{}_", label))
+            });
+            match existing {
+                Some((placeholder, _)) => placeholder.clone(),
+                None => {
+                    let placeholder = format!("[[{}_{}]]", label, spans.len());
+                    spans.push((placeholder.clone(), value.to_string()));
+                    placeholder
+                }
+            }
+        });
+        self.text = masked.into_owned();
+        count
+    }
+
+    /// Put the original spans back into the model output
+    ///
+    /// Fails if a placeholder is missing or was altered. The output is
+    /// otherwise returned as it is, even if it happens to be JSON.
+    pub fn restore(&self, output: &str) -> Result<String, AppError> {
+        if self.spans.is_empty() {
+            return Ok(output.to_string());
+        }
+        self.verify(output)?;
+        Ok(self.restore_str(output))
+    }
+
+    /// `restore` for explain mode, whose reply is a JSON object
+    ///
+    /// The spans are put back inside its strings, so quotes in the original
+    /// text stay escaped. Output that is not a JSON object is restored as text.
+    pub fn restore_explain(&self, output: &str) -> Result<String, AppError> {
+        if self.spans.is_empty() {
+            return Ok(output.to_string());
+        }
+        self.verify(output)?;
+
+        match serde_json::from_str::<Value>(output) {
+            Ok(mut value @ Value::Object(_)) => {
+                self.restore_json(&mut value);
+                Ok(value.to_string())
+            }
+            _ => Ok(self.restore_str(output)),
+        }
+    }
+
+    /// This text's span for a placeholder match, `None` for other labels
+    fn span(&self, caps: &Captures) -> Option<Option<&str>> {
+        if !self.spans.iter().any(|(placeholder, _)| placeholder.starts_with(&format!("[[{}_", &caps[1]))) {
+            return None;
+        }
+        Some(self.spans.iter().find(|(placeholder, _)| placeholder == &caps[0]).map(|(_, original)| original.as_str()))
+    }
+
+    fn verify(&self, output: &str) -> Result<(), AppError> {
+        let mut found: BTreeSet<&str> = BTreeSet::new();
+        let mut unknown = false;
+        for caps in PLACEHOLDER.captures_iter(output) {
+            match self.span(&caps) {
+                Some(Some(_)) => {
+                    found.insert(caps.get(0).map_or("", |m| m.as_str()));
+                }
+                Some(None) => unknown = true,
+                None => {}
+            }
+        }
+
+        let missing: Vec<&str> = self
+            .spans
+            .iter()
+            .filter(|(placeholder, _)| !found.contains(placeholder.as_str()))
+            .map(|(_, original)| original.as_str())
+            .collect();
+        if missing.is_empty() && !unknown {
+            return Ok(());
+        }
+
+        let shown: Vec<String> = missing.iter().map(|span| span.chars().take(40).collect()).collect();
+        Err(AppError::LlmApiError(format!(
+            "Model output did not preserve protected text ({} missing{}): {}",
+            missing.len(),
+            if unknown { ", unknown placeholders added" } else { "" },
+            shown.join(", ")
+        )))
+    }
+
+    /// Replace this text's placeholders, leaving unknown ones as they are
+    pub(crate) fn restore_str(&self, text: &str) -> String {
+        PLACEHOLDER
+            .replace_all(text, |caps: &Captures| match self.span(caps) {
+                Some(Some(original)) => original.to_string(),
+                _ => caps[0].to_string(),
+            })
+            .into_owned()
+    }
+
+    /// Restore placeholders in streamed text as soon as each is complete
+    ///
+    /// A delta that ends inside a placeholder is held back until the rest
+    /// arrives. Dropped or altered placeholders are reported as an error
+    /// once the stream ends, since the text before them has been sent.
+    pub fn restore_deltas<S>(self, deltas: S) -> impl Stream<Item = Result<String, AppError>> + Send + 'static
+    where
+        S: Stream<Item = Result<String, AppError>> + Send + 'static,
+    {
+        let state = (Box::pin(deltas), String::new(), String::new(), self, false);
+        stream::unfold(state, |(mut deltas, mut pending, mut output, masked, finished)| async move {
+            if finished {
+                return None;
+            }
+            loop {
+                match deltas.next().await {
+                    Some(Ok(delta)) => {
+                        output.push_str(&delta);
+                        pending.push_str(&delta);
+                        let ready = PARTIAL_PLACEHOLDER.find(&pending).map_or(pending.len(), |m| m.start());
+                        if ready > 0 {
+                            let text: String = pending.drain(..ready).collect();
+                            return Some((Ok(masked.restore_str(&text)), (deltas, pending, output, masked, false)));
+                        }
+                    }
+                    Some(Err(e)) => return Some((Err(e), (deltas, pending, output, masked, false))),
+                    None => {
+                        if let Err(e) = masked.verify(&output) {
+                            return Some((Err(e), (deltas, pending, output, masked, true)));
+                        }
+                        if pending.is_empty() {
+                            return None;
+                        }
+                        let text = masked.restore_str(&pending);
+                        return Some((Ok(text), (deltas, String::new(), output, masked, true)));
+                    }
+                }
+            }
+        })
+    }
+
+    fn restore_json(&self, value: &mut Value) {
+        match value {
+            Value::String(text) => *text = self.restore_str(text),
+            Value::Array(items) => items.iter_mut().for_each(|item| self.restore_json(item)),
+            Value::Object(fields) => fields.values_mut().for_each(|field| self.restore_json(field)),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mask_protected_spans() {
+        let text = "Run `cargo test` before PROJ-123 is merged, see https://example.com/a?b=1. Hi {{name}}!\n\n```\nlet x = 1;\n```";
+        let masked = MaskingConfig::default().mask(text);
+
+        assert_eq!(
+            masked.text,
+            "Run [[KEEP_0]] before [[KEEP_1]] is merged, see [[KEEP_2]]. Hi [[KEEP_3]]!\n\n[[KEEP_4]]"
+        );
+        let originals: Vec<&str> = masked.spans.iter().map(|(_, original)| original.as_str()).collect();
+        assert_eq!(
+            originals,
+            vec!["`cargo test`", "PROJ-123", "https://example.com/a?b=1", "{{name}}", "```\nlet x = 1;\n```"]
+        );
+        assert!(placeholder_instructions(&[&masked]).unwrap().contains("[[KEEP_0]]"));
+    }
+
+    #[test]
+    fn test_mask_disabled_or_nothing_to_protect() {
+        let config = MaskingConfig { enabled: false, ..MaskingConfig::default() };
+        let masked = config.mask("See `main.rs`.");
+        assert_eq!(masked.text, "See `main.rs`.");
+        assert!(placeholder_instructions(&[&masked]).is_none());
+
+        let masked = MaskingConfig::default().mask("Nothing to see here.");
+        assert_eq!(masked.restore("Nothing to see here!").unwrap(), "Nothing to see here!");
+    }
+
+    #[test]
+    fn test_restore() {
+        let masked = MaskingConfig::default().mask("pls check `foo()` and www.example.com");
+        let restored = masked.restore("Please check [[KEEP_0]] and [[KEEP_1]].").unwrap();
+        assert_eq!(restored, "Please check `foo()` and www.example.com.");
+    }
+
+    #[test]
+    fn test_restore_rejects_dropped_or_altered_placeholders() {
+        let masked = MaskingConfig::default().mask("Fix `foo()` in ABC-42");
+
+        match masked.restore("Fix foo() in [[KEEP_1]]") {
+            Err(AppError::LlmApiError(msg)) => assert!(msg.contains("1 missing") && msg.contains("`foo()`")),
+            other => panic!("Expected LlmApiError, got: {:?}", other),
+        }
+        match masked.restore("Fix [[KEEP_0]] in [[KEEP_1]] and [[KEEP_7]]") {
+            Err(AppError::LlmApiError(msg)) => assert!(msg.contains("unknown placeholders")),
+            other => panic!("Expected LlmApiError, got: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_restore_deltas() {
+        let masked = MaskingConfig::default().mask("See `foo()` and `bar()`");
+        let deltas = ["Please see [", "[KEE", "P_0]] and [[KEEP_1]", "]."].map(|delta| Ok(delta.to_string()));
+
+        let restored: Vec<String> =
+            masked.restore_deltas(stream::iter(deltas)).map(Result::unwrap).collect().await;
+        assert_eq!(restored, vec!["Please see ", "`foo()` and ", "`bar()`."]);
+
+        // A dropped placeholder fails the stream once it ends
+        let masked = MaskingConfig::default().mask("See `foo()` and `bar()`");
+        let deltas = ["Please see [[KEEP_0]]", " and bar()."].map(|delta| Ok(delta.to_string()));
+        let restored: Vec<Result<String, AppError>> = masked.restore_deltas(stream::iter(deltas)).collect().await;
+        assert_eq!(restored.len(), 3);
+        assert_eq!(restored[0].as_deref().unwrap(), "Please see `foo()`");
+        match &restored[2] {
+            Err(AppError::LlmApiError(msg)) => assert!(msg.contains("`bar()`")),
+            other => panic!("Expected LlmApiError, got: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_restore_explain_output() {
+        let masked = MaskingConfig::default().mask(r#"Call `greet("bob")` now"#);
+        let output = r#"{"corrected": "Call [[KEEP_0]] now.", "changes": []}"#;
+
+        let restored: Value = serde_json::from_str(&masked.restore_explain(output).unwrap()).unwrap();
+        assert_eq!(restored["corrected"], r#"Call `greet("bob")` now."#);
+    }
+
+    #[test]
+    fn test_restore_keeps_json_replies_as_they_are() {
+        let masked = MaskingConfig::default().mask("fix `x`");
+        let output = "{\n  \"b\": 1,\n  \"a\": \"[[KEEP_0]]\"\n}";
+
+        assert_eq!(masked.restore(output).unwrap(), "{\n  \"b\": 1,\n  \"a\": \"`x`\"\n}");
+    }
+}
@@ -0,0 +1,229 @@
+use std::sync::LazyLock;
+
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+use tracing::debug;
+
+/// "Here is the improved text:" and similar lead-ins, with an optional
+/// "Sure!" before them
+///
+/// Only a lead-in on a line of its own counts, so a response that starts
+/// with "Corrected text: ..." is left alone.
+static PREAMBLE: LazyLock<Regex> = LazyLock::new(|| {
+    RegexBuilder::new(concat!(
+        r"\A\s*(?:(?:sure|certainly|of course|okay|ok)\b[,!.]?\s*)?",
+        r"(?:here(?:'s| is| are)\s+(?:the\s+|your\s+|my\s+|an?\s+)?(?:[\w-]+\s+){0,3}?",
+        r"|(?:the\s+)?(?:improved|corrected|revised|rewritten|edited|updated|polished|fixed)\s+)",
+        r"(?:text|version|rewrite|sentence|sentences|message|paragraph|translation)\b[^\n:]{0,40}:[ \t]*(?:\r?\n)+",
+    ))
+    .case_insensitive(true)
+    .build()
+    .expect("preamble pattern is valid")
+});
+
+/// A whole text fenced as a Markdown code block
+static FENCE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\A```[\w+-]*[ \t]*\n(?s:(.*?))\n?```\z").expect("fence pattern is valid"));
+
+/// Opening and closing characters of the quotes models wrap text in
+const QUOTE_PAIRS: [(char, char); 5] = [('"', '"'), ('\'', '\''), ('“', '”'), ('‘', '’'), ('«', '»')];
+
+/// One clean-up step for model output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PostProcessStep {
+    /// Remove lead-ins such as "Here is the improved text:"
+    RemovePreamble,
+    /// Unwrap a response fenced as a Markdown code block
+    Unfence,
+    /// Remove quotes around the whole response
+    StripQuotes,
+    /// Trim the response and the end of each line, and collapse runs of
+    /// blank lines into one
+    NormalizeWhitespace,
+    /// Give the response the leading and trailing whitespace of the request
+    /// text, so it can replace the selection as is
+    PreserveWhitespace,
+}
+
+/// Ordered clean-up of the raw model output
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PostProcessConfig {
+    pub steps: Vec<PostProcessStep>,
+    /// More lead-ins to remove, matched at the start in any case,
+    /// e.g. "Voici le texte corrigé :"
+    pub preambles: Vec<String>,
+}
+
+impl Default for PostProcessConfig {
+    fn default() -> Self {
+        Self {
+            steps: vec![
+                PostProcessStep::RemovePreamble,
+                PostProcessStep::Unfence,
+                PostProcessStep::StripQuotes,
+                PostProcessStep::NormalizeWhitespace,
+                PostProcessStep::PreserveWhitespace,
+            ],
+            preambles: Vec::new(),
+        }
+    }
+}
+
+impl PostProcessConfig {
+    /// Run the steps in order on the model output for `input`
+    ///
+    /// Steps that would undo something the request text itself does, such as
+    /// unfencing a fenced input, leave the output alone.
+    pub fn apply(&self, output: &str, input: &str) -> String {
+        let mut text = output.to_string();
+        for step in &self.steps {
+            let next = match step {
+                PostProcessStep::RemovePreamble => self.remove_preamble(&text, input),
+                PostProcessStep::Unfence => unfence(&text, input),
+                PostProcessStep::StripQuotes => strip_quotes(&text, input),
+                PostProcessStep::NormalizeWhitespace => normalize_whitespace(&text),
+                PostProcessStep::PreserveWhitespace => preserve_whitespace(&text, input),
+            };
+            if next != text {
+                debug!("Post-processing step {:?} changed the response", step);
+            }
+            text = next;
+        }
+        text
+    }
+
+    fn remove_preamble(&self, text: &str, input: &str) -> String {
+        let trimmed = text.trim_start();
+        for preamble in &self.preambles {
+            let matches = trimmed.get(..preamble.len()).is_some_and(|start| start.eq_ignore_ascii_case(preamble));
+            if matches && !input.trim_start().to_lowercase().starts_with(&preamble.to_lowercase()) {
+                return trimmed[preamble.len()..].trim_start().to_string();
+            }
+        }
+
+        match PREAMBLE.find(text) {
+            // Keep it if the user wrote it
+            Some(found) if !PREAMBLE.is_match(input) && found.end() < text.len() => text[found.end()..].to_string(),
+            _ => text.to_string(),
+        }
+    }
+}
+
+fn unfence(text: &str, input: &str) -> String {
+    if input.trim_start().starts_with("```") {
+        return text.to_string();
+    }
+    match FENCE.captures(text.trim()) {
+        Some(caps) => caps[1].to_string(),
+        None => text.to_string(),
+    }
+}
+
+fn strip_quotes(text: &str, input: &str) -> String {
+    let trimmed = text.trim();
+    let input = input.trim();
+    for (open, close) in QUOTE_PAIRS {
+        let Some(inner) = trimmed.strip_prefix(open).and_then(|rest| rest.strip_suffix(close)) else {
+            continue;
+        };
+        // `"a" and "b"` is not one quoted text, and quoted input stays quoted
+        let wrapped_input = input.starts_with(open) && input.ends_with(close);
+        if !inner.contains([open, close]) && !inner.trim().is_empty() && !wrapped_input {
+            return inner.to_string();
+        }
+    }
+    text.to_string()
+}
+
+fn normalize_whitespace(text: &str) -> String {
+    let mut lines: Vec<&str> = Vec::new();
+    for line in text.trim().lines().map(str::trim_end) {
+        if line.is_empty() && lines.last().is_some_and(|last| last.is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+fn preserve_whitespace(text: &str, input: &str) -> String {
+    let leading = &input[..input.len() - input.trim_start().len()];
+    let trailing = &input[input.trim_end().len()..];
+    format!("{}{}{}", leading, text.trim(), trailing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> PostProcessConfig {
+        PostProcessConfig::default()
+    }
+
+    #[test]
+    fn test_remove_preamble() {
+        let input = "i has a apple";
+        for output in [
+            "Here is the improved text:\n\nI have an apple.",
+            "Sure! Here's the corrected version of your text:\n\nI have an apple.",
+            "Corrected text:\r\nI have an apple.",
+            "here are the revised sentences: \nI have an apple.",
+        ] {
+            assert_eq!(config().remove_preamble(output, input).trim(), "I have an apple.", "{}", output);
+        }
+
+        // Ordinary text, a lead-in the user wrote, and a lead-in with nothing after it
+        assert_eq!(config().remove_preamble("Here is the plan: ship it.", "here is the plan ship it"), "Here is the plan: ship it.");
+        assert_eq!(config().remove_preamble("Here is the text: hello", "here is the text: hello"), "Here is the text: hello");
+        assert_eq!(config().remove_preamble("Here is the text:", "text"), "Here is the text:");
+
+        // A lead-in followed by text on the same line is part of the text
+        for output in ["Corrected text: I have an apple.", "Here is the updated message: the build is green.\nThanks!"] {
+            assert_eq!(config().remove_preamble(output, input), output);
+        }
+
+        let config = PostProcessConfig { preambles: vec!["Voici le texte corrigé :".to_string()], ..config() };
+        assert_eq!(config.remove_preamble("voici le texte corrigé : Bonjour.", "bonjour"), "Bonjour.");
+    }
+
+    #[test]
+    fn test_unfence() {
+        assert_eq!(unfence("```\nI have an apple.\n```", "i has a apple"), "I have an apple.");
+        assert_eq!(unfence("```text\nLine one.\nLine two.\n```\n", "x"), "Line one.\nLine two.");
+        assert_eq!(unfence("Use ```code``` here", "x"), "Use ```code``` here");
+        // A fenced request keeps its fence
+        assert_eq!(unfence("```\nlet x = 1;\n```", "```\nlet x=1\n```"), "```\nlet x = 1;\n```");
+    }
+
+    #[test]
+    fn test_strip_quotes() {
+        assert_eq!(strip_quotes("\"I have an apple.\"", "i has a apple"), "I have an apple.");
+        assert_eq!(strip_quotes(" “I have an apple.” ", "i has a apple"), "I have an apple.");
+        assert_eq!(strip_quotes("«Bonjour.»", "bonjour"), "Bonjour.");
+        assert_eq!(strip_quotes("\"Yes\" and \"no\"", "yes and no"), "\"Yes\" and \"no\"");
+        assert_eq!(strip_quotes("\"Quoted.\"", "\"quoted\""), "\"Quoted.\"");
+    }
+
+    #[test]
+    fn test_normalize_whitespace() {
+        assert_eq!(normalize_whitespace("  One.  \n\n\n\nTwo. \t\nThree.\n\n"), "One.\n\nTwo.\nThree.");
+    }
+
+    #[test]
+    fn test_preserve_whitespace() {
+        assert_eq!(preserve_whitespace("\nHello.\n", "  hello\n\n"), "  Hello.\n\n");
+        assert_eq!(preserve_whitespace(" Hello. ", "hello"), "Hello.");
+    }
+
+    #[test]
+    fn test_apply_runs_steps_in_order() {
+        let output = "Sure! Here is the improved text:\n\n```\n\"I have an apple.\"  \n```";
+        assert_eq!(config().apply(output, " i has a apple\n"), " I have an apple.\n");
+
+        // Without steps the output is returned as it came
+        let config = PostProcessConfig { steps: Vec::new(), ..config() };
+        assert_eq!(config.apply(output, "x"), output);
+    }
+}
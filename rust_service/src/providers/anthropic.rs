@@ -0,0 +1,255 @@
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder};
+use serde_json::Value;
+use tracing::{error, info};
+
+use super::{json_post, merge_llm_params, probe_json, rename_param, sibling_url, stream_error, system_prompt, LlmProvider, TokenUsage};
+use crate::config::{mask_key, AppConfig};
+use crate::errors::AppError;
+
+/// API version sent in the `anthropic-version` header unless overridden
+pub const DEFAULT_ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Anthropic Messages API requires `max_tokens`, so use this when the config has none
+const DEFAULT_MAX_TOKENS: u64 = 2048;
+
+/// Anthropic `/v1/messages` API
+pub struct AnthropicProvider;
+
+impl AnthropicProvider {
+    /// Add the API key and version headers
+    fn authorize(&self, req_builder: RequestBuilder, config: &AppConfig) -> Result<RequestBuilder, AppError> {
+        let api_key = config.anthropic_api_key.as_deref().filter(|k| !k.is_empty()).ok_or_else(|| {
+            error!("Missing Anthropic API key. Set ANTHROPIC_API_KEY environment variable.");
+            AppError::LlmApiError("Missing Anthropic API key".to_string())
+        })?;
+
+        let version = config
+            .anthropic_version
+            .as_deref()
+            .filter(|v| !v.is_empty())
+            .unwrap_or(DEFAULT_ANTHROPIC_VERSION);
+
+        Ok(req_builder
+            .header("x-api-key", api_key)
+            .header("anthropic-version", version))
+    }
+}
+
+#[async_trait]
+impl LlmProvider for AnthropicProvider {
+    fn name(&self) -> &'static str {
+        "Anthropic"
+    }
+
+    fn build_request(
+        &self,
+        client: &Client,
+        config: &AppConfig,
+        prompt: &str,
+        stream: bool,
+    ) -> Result<RequestBuilder, AppError> {
+        // Anthropic Messages API format: the system prompt is a top-level field
+        let mut payload = serde_json::json!({
+            "model": config.model_name,
+            "messages": [
+                {
+                    "role": "user",
+                    "content": prompt
+                }
+            ],
+            "max_tokens": DEFAULT_MAX_TOKENS,
+            "temperature": 0.7
+        });
+        if let Some(system) = system_prompt(config) {
+            payload["system"] = Value::from(system);
+        }
+        merge_llm_params(&mut payload, config);
+        payload["stream"] = Value::Bool(stream);
+
+        // Configs written for OpenAI use `max_output_tokens`, which the Messages API rejects
+        rename_param(&mut payload, "max_output_tokens", "max_tokens");
+
+        self.authorize(json_post(client, &config.llm_url, &payload), config)
+    }
+
+    fn parse_response(&self, response: &Value) -> Option<String> {
+        // The reply is a list of content blocks; join all text blocks
+        let texts: Vec<&str> = response
+            .get("content")?
+            .as_array()?
+            .iter()
+            .filter(|block| block.get("type").and_then(Value::as_str) == Some("text"))
+            .filter_map(|block| block.get("text").and_then(Value::as_str))
+            .collect();
+
+        if texts.is_empty() {
+            None
+        } else {
+            Some(texts.concat())
+        }
+    }
+
+    fn parse_usage(&self, response: &Value) -> Option<TokenUsage> {
+        TokenUsage::from_pointers(response, "/usage/input_tokens", "/usage/output_tokens")
+    }
+
+    fn parse_stream_chunk(&self, chunk: &Value) -> Result<Option<String>, AppError> {
+        match chunk.get("type").and_then(Value::as_str) {
+            Some("content_block_delta") => Ok(chunk
+                .get("delta")
+                .filter(|delta| delta.get("type").and_then(Value::as_str) == Some("text_delta"))
+                .and_then(|delta| delta.get("text"))
+                .and_then(Value::as_str)
+                .map(str::to_string)),
+            Some("error") => Err(stream_error(self.name(), chunk.get("error").unwrap_or(chunk))),
+            _ => Ok(None),
+        }
+    }
+
+    async fn list_models(&self, client: &Client, config: &AppConfig) -> Result<Vec<String>, AppError> {
+        let url = sibling_url(&config.llm_url, "/messages", "/models");
+        let req_builder = self.authorize(client.get(url), config)?;
+        let body = probe_json(self.name(), req_builder).await?;
+
+        Ok(body
+            .get("data")
+            .and_then(Value::as_array)
+            .map(|models| {
+                models
+                    .iter()
+                    .filter_map(|m| m.get("id").and_then(Value::as_str).map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    async fn health_check(&self, client: &Client, config: &AppConfig) -> Result<(), AppError> {
+        if let Some(api_key) = config.anthropic_api_key.as_deref().filter(|k| !k.is_empty()) {
+            info!("✅ Anthropic API key is configured: {}", mask_key(api_key));
+        }
+
+        self.list_models(client, config).await.map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn test_config() -> AppConfig {
+        AppConfig {
+            provider: crate::providers::ProviderKind::Anthropic,
+            llm_url: "https://api.anthropic.com/v1/messages".to_string(),
+            model_name: "claude-sonnet-4-5".to_string(),
+            llm_params: Some(json!({ "temperature": 0.2, "max_output_tokens": 500 })),
+            anthropic_api_key: Some("sk-ant-test-key".to_string()),
+            cache: crate::cache::CacheConfig { enabled: false, ..Default::default() },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_build_request() {
+        let request = AnthropicProvider
+            .build_request(&Client::new(), &test_config(), "Fix this", false)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        assert_eq!(request.url().as_str(), "https://api.anthropic.com/v1/messages");
+        assert_eq!(request.headers()["x-api-key"], "sk-ant-test-key");
+        assert_eq!(request.headers()["anthropic-version"], DEFAULT_ANTHROPIC_VERSION);
+        assert!(request.headers().get("Authorization").is_none());
+
+        let body: Value = serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!(body["model"], "claude-sonnet-4-5");
+        assert!(body["system"].as_str().unwrap().starts_with("You are a text improvement tool"));
+        assert_eq!(body["messages"][0]["role"], "user");
+        assert_eq!(body["messages"][0]["content"], "Fix this");
+        assert_eq!(body["temperature"], 0.2);
+        assert_eq!(body["max_tokens"], 500);
+        assert!(body.get("max_output_tokens").is_none());
+    }
+
+    #[test]
+    fn test_build_request_default_max_tokens() {
+        let mut config = test_config();
+        config.llm_params = None;
+        config.anthropic_version = Some("2024-01-01".to_string());
+
+        let request = AnthropicProvider
+            .build_request(&Client::new(), &config, "Fix this", false)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        assert_eq!(request.headers()["anthropic-version"], "2024-01-01");
+        let body: Value = serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!(body["max_tokens"], DEFAULT_MAX_TOKENS);
+    }
+
+    #[test]
+    fn test_build_request_without_api_key() {
+        let mut config = test_config();
+        config.anthropic_api_key = None;
+
+        match AnthropicProvider.build_request(&Client::new(), &config, "Fix this", false) {
+            Err(AppError::LlmApiError(msg)) => assert!(msg.contains("Missing Anthropic API key")),
+            other => panic!("Expected LlmApiError, got: {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_build_request_system_prompt() {
+        let mut config = test_config();
+        config.system_prompt = Some("You are a translator.".to_string());
+        let request = AnthropicProvider.build_request(&Client::new(), &config, "Hallo", false).unwrap().build().unwrap();
+        let body: Value = serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!(body["system"], "You are a translator.");
+
+        // An empty system prompt omits the field altogether
+        config.system_prompt = Some(String::new());
+        let request = AnthropicProvider.build_request(&Client::new(), &config, "Hallo", false).unwrap().build().unwrap();
+        let body: Value = serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+        assert!(body.get("system").is_none());
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let response = json!({
+            "id": "msg_01",
+            "type": "message",
+            "role": "assistant",
+            "content": [
+                { "type": "text", "text": "Test response " },
+                { "type": "text", "text": "from Claude" }
+            ],
+            "stop_reason": "end_turn"
+        });
+        assert_eq!(
+            AnthropicProvider.parse_response(&response),
+            Some("Test response from Claude".to_string())
+        );
+        assert_eq!(AnthropicProvider.parse_response(&json!({ "content": [] })), None);
+
+        let usage = json!({ "usage": { "input_tokens": 12, "output_tokens": 7 } });
+        assert_eq!(AnthropicProvider.parse_usage(&usage), Some(TokenUsage { input_tokens: 12, output_tokens: 7 }));
+    }
+
+    #[test]
+    fn test_parse_stream_chunk() {
+        let delta = json!({ "type": "content_block_delta", "index": 0, "delta": { "type": "text_delta", "text": "Hel" } });
+        assert_eq!(AnthropicProvider.parse_stream_chunk(&delta).unwrap(), Some("Hel".to_string()));
+
+        let ping = json!({ "type": "ping" });
+        assert_eq!(AnthropicProvider.parse_stream_chunk(&ping).unwrap(), None);
+
+        let error = json!({ "type": "error", "error": { "type": "overloaded_error", "message": "Overloaded" } });
+        match AnthropicProvider.parse_stream_chunk(&error) {
+            Err(AppError::LlmApiError(msg)) => assert!(msg.contains("Overloaded")),
+            other => panic!("Expected LlmApiError, got: {:?}", other),
+        }
+    }
+}
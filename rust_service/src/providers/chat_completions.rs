@@ -0,0 +1,251 @@
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder};
+use serde_json::Value;
+
+use super::{chat_messages, json_post, merge_llm_params, probe_json, rename_param, sibling_url, stream_error, LlmProvider, TokenUsage};
+use crate::config::AppConfig;
+use crate::errors::AppError;
+
+/// OpenAI-compatible `/v1/chat/completions` API (llama.cpp, vLLM, LM Studio, ...)
+pub struct ChatCompletionsProvider;
+
+impl ChatCompletionsProvider {
+    /// Add a bearer token if one is configured; most local servers don't need it
+    fn authorize(&self, req_builder: RequestBuilder, config: &AppConfig) -> RequestBuilder {
+        match config.llm_api_key.as_deref().filter(|k| !k.is_empty()) {
+            Some(api_key) => req_builder.header("Authorization", format!("Bearer {}", api_key)),
+            None => req_builder,
+        }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for ChatCompletionsProvider {
+    fn name(&self) -> &'static str {
+        "OpenAI-compatible"
+    }
+
+    fn build_request(
+        &self,
+        client: &Client,
+        config: &AppConfig,
+        prompt: &str,
+        stream: bool,
+    ) -> Result<RequestBuilder, AppError> {
+        // Chat Completions API format
+        let mut payload = serde_json::json!({
+            "model": config.model_name,
+            "messages": chat_messages(config, prompt),
+            "temperature": 0.7,
+            "stream": false
+        });
+        merge_llm_params(&mut payload, config);
+        payload["stream"] = Value::Bool(stream);
+
+        // Configs written for the Responses API use `max_output_tokens`
+        rename_param(&mut payload, "max_output_tokens", "max_tokens");
+
+        Ok(self.authorize(json_post(client, &config.llm_url, &payload), config))
+    }
+
+    fn parse_response(&self, response: &Value) -> Option<String> {
+        response
+            .get("choices")?
+            .as_array()?
+            .first()?
+            .get("message")?
+            .get("content")?
+            .as_str()
+            .map(str::to_string)
+    }
+
+    fn parse_usage(&self, response: &Value) -> Option<TokenUsage> {
+        TokenUsage::from_pointers(response, "/usage/prompt_tokens", "/usage/completion_tokens")
+    }
+
+    fn json_output_params(&self) -> Option<Value> {
+        Some(serde_json::json!({ "response_format": { "type": "json_object" } }))
+    }
+
+    fn supports_multiple_candidates(&self) -> bool {
+        true
+    }
+
+    fn parse_candidates(&self, response: &Value) -> Vec<String> {
+        let choices = response.get("choices").and_then(Value::as_array);
+        choices
+            .into_iter()
+            .flatten()
+            .filter_map(|choice| choice.pointer("/message/content").and_then(Value::as_str))
+            .map(str::to_string)
+            .collect()
+    }
+
+    fn parse_stream_chunk(&self, chunk: &Value) -> Result<Option<String>, AppError> {
+        if let Some(error) = chunk.get("error") {
+            return Err(stream_error(self.name(), error));
+        }
+        Ok(chunk
+            .pointer("/choices/0/delta/content")
+            .and_then(Value::as_str)
+            .map(str::to_string))
+    }
+
+    async fn list_models(&self, client: &Client, config: &AppConfig) -> Result<Vec<String>, AppError> {
+        let url = sibling_url(&config.llm_url, "/chat/completions", "/models");
+        let req_builder = self.authorize(client.get(url), config);
+        let body = probe_json(self.name(), req_builder).await?;
+
+        Ok(body
+            .get("data")
+            .and_then(Value::as_array)
+            .map(|models| {
+                models
+                    .iter()
+                    .filter_map(|m| m.get("id").and_then(Value::as_str).map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn test_config(llm_url: String) -> AppConfig {
+        AppConfig {
+            provider: crate::providers::ProviderKind::OpenAiCompatible,
+            llm_url,
+            model_name: "qwen2.5-7b-instruct".to_string(),
+            llm_params: Some(json!({ "temperature": 0.2, "max_output_tokens": 512 })),
+            openai_api_key: Some("sk-must-not-leak".to_string()),
+            cache: crate::cache::CacheConfig { enabled: false, ..Default::default() },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_build_request() {
+        let config = test_config("http://localhost:8080/v1/chat/completions".to_string());
+        let request = ChatCompletionsProvider
+            .build_request(&Client::new(), &config, "Fix this", false)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        assert_eq!(request.url().as_str(), "http://localhost:8080/v1/chat/completions");
+        // The OpenAI key is never sent to a third-party server
+        assert!(request.headers().get("Authorization").is_none());
+
+        let body: Value = serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!(body["model"], "qwen2.5-7b-instruct");
+        assert_eq!(body["messages"][0]["role"], "system");
+        assert_eq!(body["messages"][1]["content"], "Fix this");
+        assert_eq!(body["temperature"], 0.2);
+        assert_eq!(body["max_tokens"], 512);
+        assert!(body.get("max_output_tokens").is_none());
+    }
+
+    #[test]
+    fn test_build_request_with_api_key() {
+        let mut config = test_config("http://vllm.internal/v1/chat/completions".to_string());
+        config.llm_api_key = Some("local-token".to_string());
+
+        let request = ChatCompletionsProvider
+            .build_request(&Client::new(), &config, "Fix this", false)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        assert_eq!(request.headers()["Authorization"], "Bearer local-token");
+    }
+
+    #[test]
+    fn test_build_request_without_system_prompt() {
+        let mut config = test_config("http://localhost:8080/v1/chat/completions".to_string());
+        config.system_prompt = Some(String::new());
+
+        let request = ChatCompletionsProvider
+            .build_request(&Client::new(), &config, "Fix this", false)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let body: Value = serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!(body["messages"], json!([{ "role": "user", "content": "Fix this" }]));
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let response = json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "choices": [
+                {
+                    "index": 0,
+                    "message": { "role": "assistant", "content": "Test response from llama.cpp" },
+                    "finish_reason": "stop"
+                }
+            ]
+        });
+        assert_eq!(
+            ChatCompletionsProvider.parse_response(&response),
+            Some("Test response from llama.cpp".to_string())
+        );
+        assert_eq!(ChatCompletionsProvider.parse_response(&json!({ "choices": [] })), None);
+
+        let usage = json!({ "usage": { "prompt_tokens": 12, "completion_tokens": 7, "total_tokens": 19 } });
+        assert_eq!(
+            ChatCompletionsProvider.parse_usage(&usage),
+            Some(TokenUsage { input_tokens: 12, output_tokens: 7 })
+        );
+        assert_eq!(ChatCompletionsProvider.parse_usage(&response), None);
+    }
+
+    #[test]
+    fn test_parse_candidates() {
+        let response = json!({
+            "choices": [
+                { "index": 0, "message": { "role": "assistant", "content": "First" } },
+                { "index": 1, "message": { "role": "assistant", "content": "Second" } }
+            ]
+        });
+        assert!(ChatCompletionsProvider.supports_multiple_candidates());
+        assert_eq!(ChatCompletionsProvider.parse_candidates(&response), vec!["First", "Second"]);
+        assert!(ChatCompletionsProvider.parse_candidates(&json!({})).is_empty());
+    }
+
+    #[test]
+    fn test_parse_stream_chunk() {
+        let chunk = json!({ "choices": [{ "index": 0, "delta": { "content": "Hel" } }] });
+        assert_eq!(ChatCompletionsProvider.parse_stream_chunk(&chunk).unwrap(), Some("Hel".to_string()));
+
+        let role_only = json!({ "choices": [{ "index": 0, "delta": { "role": "assistant" } }] });
+        assert_eq!(ChatCompletionsProvider.parse_stream_chunk(&role_only).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_list_models() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v1/models"))
+            .and(header("Authorization", "Bearer local-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "list",
+                "data": [{ "id": "qwen2.5-7b-instruct", "object": "model" }]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut config = test_config(format!("{}/v1/chat/completions", mock_server.uri()));
+        config.llm_api_key = Some("local-token".to_string());
+        let models = ChatCompletionsProvider.list_models(&Client::new(), &config).await.unwrap();
+
+        assert_eq!(models, vec!["qwen2.5-7b-instruct"]);
+    }
+}
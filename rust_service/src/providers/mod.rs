@@ -0,0 +1,289 @@
+use async_trait::async_trait;
+use reqwest::{header, Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Duration;
+use tracing::{debug, error, warn};
+
+use crate::config::AppConfig;
+use crate::errors::AppError;
+use crate::stream::StreamFormat;
+
+mod anthropic;
+mod chat_completions;
+mod ollama;
+mod openai;
+
+pub use anthropic::AnthropicProvider;
+pub use chat_completions::ChatCompletionsProvider;
+pub use ollama::OllamaProvider;
+pub use openai::OpenAiProvider;
+
+/// System prompt used when the config does not set one
+pub const DEFAULT_SYSTEM_PROMPT: &str = "You are a text improvement tool that corrects grammar and improves clarity without adding conversational elements. Follow the instructions exactly.";
+
+/// Timeout used for lightweight calls such as health checks and model listing
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Tokens a request used, as reported by the backend
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TokenUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+impl TokenUsage {
+    /// Read the counts at two JSON pointers of a response body
+    fn from_pointers(response: &Value, input: &str, output: &str) -> Option<Self> {
+        Some(Self {
+            input_tokens: response.pointer(input)?.as_u64()?,
+            output_tokens: response.pointer(output)?.as_u64()?,
+        })
+    }
+
+    /// Sum of the usage of several requests; unknown if none reported any
+    pub fn total(usages: impl IntoIterator<Item = Option<Self>>) -> Option<Self> {
+        usages.into_iter().flatten().reduce(|a, b| Self {
+            input_tokens: a.input_tokens + b.input_tokens,
+            output_tokens: a.output_tokens + b.output_tokens,
+        })
+    }
+}
+
+/// The LLM backend selected with the `provider` config key
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    #[default]
+    OpenAi,
+    Ollama,
+    Anthropic,
+    /// Any server exposing the OpenAI `/v1/chat/completions` API
+    #[serde(rename = "openai_compatible")]
+    OpenAiCompatible,
+}
+
+/// A backend that knows how to talk to one LLM API
+///
+/// Each implementation owns the request shape, authentication and response
+/// format of its API, so callers never need to inspect `llm_url`.
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// Name used in logs and error messages
+    fn name(&self) -> &'static str;
+
+    /// Build the request (URL, headers and JSON body) for the given prompt
+    ///
+    /// With `stream` set the backend is asked to send the reply incrementally.
+    fn build_request(
+        &self,
+        client: &Client,
+        config: &AppConfig,
+        prompt: &str,
+        stream: bool,
+    ) -> Result<RequestBuilder, AppError>;
+
+    /// Extract the generated text from a successful response body
+    fn parse_response(&self, response: &Value) -> Option<String>;
+
+    /// Extract the token usage from a successful response body, if reported
+    fn parse_usage(&self, _response: &Value) -> Option<TokenUsage> {
+        None
+    }
+
+    /// Whether one request can return several candidates via the `n` parameter
+    ///
+    /// Only Chat Completions has `n`. OpenAI's Responses API, Ollama and the
+    /// Anthropic Messages API return one candidate per request, so callers
+    /// send one request per candidate instead.
+    fn supports_multiple_candidates(&self) -> bool {
+        false
+    }
+
+    /// Request parameters that make the backend answer with a JSON object, if
+    /// its API has a switch for that; merged like `llm_params`
+    fn json_output_params(&self) -> Option<Value> {
+        None
+    }
+
+    /// Extract every generated candidate from a successful response body
+    fn parse_candidates(&self, response: &Value) -> Vec<String> {
+        self.parse_response(response).into_iter().collect()
+    }
+
+    /// Wire format used by the backend for streamed replies
+    fn stream_format(&self) -> StreamFormat {
+        StreamFormat::Sse
+    }
+
+    /// Extract the text delta from one streamed message, if it carries any
+    fn parse_stream_chunk(&self, chunk: &Value) -> Result<Option<String>, AppError>;
+
+    /// List the models available on the backend
+    async fn list_models(&self, client: &Client, config: &AppConfig) -> Result<Vec<String>, AppError>;
+
+    /// Check that the backend is reachable and accepts our credentials
+    async fn health_check(&self, client: &Client, config: &AppConfig) -> Result<(), AppError> {
+        self.list_models(client, config).await.map(|_| ())
+    }
+}
+
+/// Get the provider implementation for the given kind
+pub fn provider_for(kind: ProviderKind) -> Box<dyn LlmProvider> {
+    match kind {
+        ProviderKind::OpenAi => Box::new(OpenAiProvider),
+        ProviderKind::Ollama => Box::new(OllamaProvider),
+        ProviderKind::Anthropic => Box::new(AnthropicProvider),
+        ProviderKind::OpenAiCompatible => Box::new(ChatCompletionsProvider),
+    }
+}
+
+/// Build a JSON POST request for the given payload
+fn json_post(client: &Client, url: &str, payload: &Value) -> RequestBuilder {
+    debug!(target: "request_payload", "LLM Payload: {}", payload);
+    client
+        .post(url)
+        .header(header::CONTENT_TYPE, "application/json")
+        .json(payload)
+}
+
+/// The system prompt to send, if any
+///
+/// Falls back to the built-in prompt when none is configured; an empty
+/// `system_prompt` disables it for models that ignore the system role.
+fn system_prompt(config: &AppConfig) -> Option<&str> {
+    match config.system_prompt.as_deref() {
+        Some(prompt) if prompt.trim().is_empty() => None,
+        Some(prompt) => Some(prompt),
+        None => Some(DEFAULT_SYSTEM_PROMPT),
+    }
+}
+
+/// Chat-style message list: the system message, if any, followed by the prompt
+fn chat_messages(config: &AppConfig, prompt: &str) -> Value {
+    let mut messages = Vec::new();
+    if let Some(system) = system_prompt(config) {
+        messages.push(serde_json::json!({ "role": "system", "content": system }));
+    }
+    messages.push(serde_json::json!({ "role": "user", "content": prompt }));
+    Value::Array(messages)
+}
+
+/// Merge the optional `llm_params` table from the config into the payload
+fn merge_llm_params(payload: &mut Value, config: &AppConfig) {
+    if let Some(params_value) = &config.llm_params {
+        if let Some(params_map) = params_value.as_object() {
+            if let Some(payload_map) = payload.as_object_mut() {
+                for (key, value) in params_map {
+                    // Skip 'prompt_template' if it exists in the llm_params
+                    if key != "prompt_template" {
+                        payload_map.insert(key.clone(), value.clone());
+                    } else {
+                        warn!("Skipping 'prompt_template' parameter, as it should not be sent to the API");
+                    }
+                }
+            } else {
+                warn!("Payload is not a JSON object, cannot merge llm_params.");
+            }
+        } else {
+            warn!("llm_params in config is not a JSON object.");
+        }
+    }
+}
+
+/// Build the error for an error message embedded in a streamed reply
+fn stream_error(provider: &str, error: &Value) -> AppError {
+    let message = error
+        .get("message")
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .or_else(|| error.as_str().map(str::to_string))
+        .unwrap_or_else(|| error.to_string());
+    AppError::LlmApiError(format!("{} API stream error: {}", provider, message))
+}
+
+/// Rename a payload parameter, e.g. `max_output_tokens` for APIs that expect `max_tokens`
+fn rename_param(payload: &mut Value, from: &str, to: &str) {
+    if let Some(payload_map) = payload.as_object_mut() {
+        if let Some(value) = payload_map.remove(from) {
+            payload_map.insert(to.to_string(), value);
+        }
+    }
+}
+
+/// Derive a sibling endpoint from `llm_url`, e.g. `/api/chat` -> `/api/tags`
+///
+/// If the URL does not end with `suffix` the replacement is appended instead.
+fn sibling_url(llm_url: &str, suffix: &str, replacement: &str) -> String {
+    let base = llm_url.trim_end_matches('/');
+    match base.strip_suffix(suffix) {
+        Some(prefix) => format!("{}{}", prefix, replacement),
+        None => format!("{}{}", base, replacement),
+    }
+}
+
+/// Send a short-lived GET request and return the JSON body of a successful response
+async fn probe_json(provider: &str, req_builder: RequestBuilder) -> Result<Value, AppError> {
+    let res = req_builder.timeout(PROBE_TIMEOUT).send().await.map_err(|e| {
+        if e.is_timeout() {
+            error!("Connection to {} API timed out", provider);
+        } else if e.is_connect() {
+            error!("Connection error - check that the {} API is running and reachable", provider);
+        }
+        AppError::LlmApiError(format!("{} API request failed: {}", provider, e))
+    })?;
+
+    let status = res.status();
+    if !status.is_success() {
+        let error_body = res
+            .text()
+            .await
+            .unwrap_or_else(|_| "Failed to read error body".to_string());
+        return Err(AppError::LlmApiError(format!(
+            "{} API error (Status {}): {}",
+            provider, status, error_body
+        )));
+    }
+
+    Ok(res.json::<Value>().await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_provider_kind_deserialization() {
+        let kind: ProviderKind = serde_json::from_value(json!("ollama")).unwrap();
+        assert_eq!(kind, ProviderKind::Ollama);
+
+        let kind: ProviderKind = serde_json::from_value(json!("openai")).unwrap();
+        assert_eq!(kind, ProviderKind::OpenAi);
+
+        let kind: ProviderKind = serde_json::from_value(json!("anthropic")).unwrap();
+        assert_eq!(kind, ProviderKind::Anthropic);
+
+        let kind: ProviderKind = serde_json::from_value(json!("openai_compatible")).unwrap();
+        assert_eq!(kind, ProviderKind::OpenAiCompatible);
+
+        assert!(serde_json::from_value::<ProviderKind>(json!("unknown")).is_err());
+        assert_eq!(ProviderKind::default(), ProviderKind::OpenAi);
+    }
+
+    #[test]
+    fn test_sibling_url() {
+        assert_eq!(
+            sibling_url("http://gpu-box:8080/api/chat", "/chat", "/tags"),
+            "http://gpu-box:8080/api/tags"
+        );
+        assert_eq!(
+            sibling_url("https://api.openai.com/v1/responses/", "/responses", "/models"),
+            "https://api.openai.com/v1/models"
+        );
+        assert_eq!(
+            sibling_url("http://proxy.local", "/responses", "/models"),
+            "http://proxy.local/models"
+        );
+    }
+}
@@ -0,0 +1,229 @@
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder};
+use serde_json::Value;
+
+use super::{chat_messages, json_post, merge_llm_params, probe_json, sibling_url, stream_error, LlmProvider, TokenUsage};
+use crate::config::AppConfig;
+use crate::errors::AppError;
+use crate::stream::StreamFormat;
+
+/// Ollama `/api/chat` API
+pub struct OllamaProvider;
+
+/// Top-level fields of an `/api/chat` request that may come from `llm_params`
+const TOP_LEVEL_PARAMS: &[&str] = &["format", "keep_alive", "tools", "think"];
+
+/// Merge `llm_params` into an `/api/chat` payload
+///
+/// Ollama ignores sampling settings such as `temperature` at the top level
+/// and only reads them from `options`, so every key that is not a top-level
+/// field goes there. An `options` table in `llm_params` is merged as well.
+fn merge_ollama_params(payload: &mut Value, config: &AppConfig) {
+    let mut params = Value::Object(serde_json::Map::new());
+    merge_llm_params(&mut params, config);
+    let Value::Object(params) = params else { return };
+
+    for (key, value) in params {
+        match (key.as_str(), value) {
+            ("options", Value::Object(options)) => {
+                for (option, value) in options {
+                    payload["options"][option] = value;
+                }
+            }
+            (key, value) if TOP_LEVEL_PARAMS.contains(&key) => payload[key] = value,
+            (key, value) => payload["options"][key] = value,
+        }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OllamaProvider {
+    fn name(&self) -> &'static str {
+        "Ollama"
+    }
+
+    fn build_request(
+        &self,
+        client: &Client,
+        config: &AppConfig,
+        prompt: &str,
+        stream: bool,
+    ) -> Result<RequestBuilder, AppError> {
+        // Ollama API format for chat endpoint
+        let mut payload = serde_json::json!({
+            "model": config.model_name,
+            "messages": chat_messages(config, prompt),
+            "options": { "temperature": 0.3, "top_p": 0.8 },
+            "stream": false
+        });
+        merge_ollama_params(&mut payload, config);
+        payload["stream"] = Value::Bool(stream);
+
+        // Ollama doesn't require authentication
+        Ok(json_post(client, &config.llm_url, &payload))
+    }
+
+    fn parse_response(&self, response: &Value) -> Option<String> {
+        response
+            .get("message")?
+            .get("content")?
+            .as_str()
+            .map(str::to_string)
+    }
+
+    fn parse_usage(&self, response: &Value) -> Option<TokenUsage> {
+        TokenUsage::from_pointers(response, "/prompt_eval_count", "/eval_count")
+    }
+
+    fn json_output_params(&self) -> Option<Value> {
+        Some(serde_json::json!({ "format": "json" }))
+    }
+
+    fn stream_format(&self) -> StreamFormat {
+        StreamFormat::Ndjson
+    }
+
+    fn parse_stream_chunk(&self, chunk: &Value) -> Result<Option<String>, AppError> {
+        if let Some(error) = chunk.get("error") {
+            return Err(stream_error(self.name(), error));
+        }
+        Ok(self.parse_response(chunk))
+    }
+
+    async fn list_models(&self, client: &Client, config: &AppConfig) -> Result<Vec<String>, AppError> {
+        let url = sibling_url(&config.llm_url, "/chat", "/tags");
+        let body = probe_json(self.name(), client.get(url)).await?;
+
+        Ok(body
+            .get("models")
+            .and_then(Value::as_array)
+            .map(|models| {
+                models
+                    .iter()
+                    .filter_map(|m| m.get("name").and_then(Value::as_str).map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn test_config(llm_url: String) -> AppConfig {
+        AppConfig {
+            provider: crate::providers::ProviderKind::Ollama,
+            llm_url,
+            model_name: "mistral:latest".to_string(),
+            cache: crate::cache::CacheConfig { enabled: false, ..Default::default() },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_build_request() {
+        let config = test_config("http://gpu-box:8080/api/chat".to_string());
+        let request = OllamaProvider
+            .build_request(&Client::new(), &config, "Fix this", false)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        assert_eq!(request.url().as_str(), "http://gpu-box:8080/api/chat");
+        assert!(request.headers().get("Authorization").is_none());
+
+        let body: Value = serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!(body["model"], "mistral:latest");
+        assert_eq!(body["messages"][1]["content"], "Fix this");
+        assert_eq!(body["stream"], false);
+        assert_eq!(body["options"], json!({ "temperature": 0.3, "top_p": 0.8 }));
+    }
+
+    #[test]
+    fn test_build_request_llm_params() {
+        let mut config = test_config("http://localhost:11434/api/chat".to_string());
+        config.llm_params = Some(json!({
+            "temperature": 0.9,
+            "format": "json",
+            "keep_alive": "5m",
+            "options": { "num_ctx": 8192 }
+        }));
+        let request = OllamaProvider.build_request(&Client::new(), &config, "Fix this", false).unwrap().build().unwrap();
+        let body: Value = serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+
+        // Sampling settings are only read from `options`
+        assert_eq!(body["options"], json!({ "temperature": 0.9, "top_p": 0.8, "num_ctx": 8192 }));
+        assert!(body.get("temperature").is_none());
+        assert_eq!(body["format"], "json");
+        assert_eq!(body["keep_alive"], "5m");
+    }
+
+    #[test]
+    fn test_build_request_system_prompt() {
+        let mut config = test_config("http://localhost:11434/api/chat".to_string());
+        config.system_prompt = Some("You are a translator.".to_string());
+        let request = OllamaProvider.build_request(&Client::new(), &config, "Hallo", false).unwrap().build().unwrap();
+        let body: Value = serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!(body["messages"][0], json!({ "role": "system", "content": "You are a translator." }));
+
+        // An empty system prompt leaves only the user message
+        config.system_prompt = Some(String::new());
+        let request = OllamaProvider.build_request(&Client::new(), &config, "Hallo", false).unwrap().build().unwrap();
+        let body: Value = serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!(body["messages"], json!([{ "role": "user", "content": "Hallo" }]));
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let response = json!({
+            "message": {
+                "content": "Test response from Ollama"
+            }
+        });
+        assert_eq!(
+            OllamaProvider.parse_response(&response),
+            Some("Test response from Ollama".to_string())
+        );
+        assert_eq!(OllamaProvider.parse_response(&json!({ "output": [] })), None);
+
+        let usage = json!({ "done": true, "prompt_eval_count": 12, "eval_count": 7 });
+        assert_eq!(OllamaProvider.parse_usage(&usage), Some(TokenUsage { input_tokens: 12, output_tokens: 7 }));
+    }
+
+    #[test]
+    fn test_parse_stream_chunk() {
+        let chunk = json!({ "model": "mistral:latest", "message": { "role": "assistant", "content": "Hel" }, "done": false });
+        assert_eq!(OllamaProvider.parse_stream_chunk(&chunk).unwrap(), Some("Hel".to_string()));
+
+        let error = json!({ "error": "model 'mistral:latest' not found" });
+        match OllamaProvider.parse_stream_chunk(&error) {
+            Err(AppError::LlmApiError(msg)) => assert!(msg.contains("not found")),
+            other => panic!("Expected LlmApiError, got: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_models() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/tags"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "models": [
+                    { "name": "mistral:latest" },
+                    { "name": "gemma3:12b" }
+                ]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let config = test_config(format!("{}/api/chat", mock_server.uri()));
+        let models = OllamaProvider.list_models(&Client::new(), &config).await.unwrap();
+
+        assert_eq!(models, vec!["mistral:latest", "gemma3:12b"]);
+    }
+}
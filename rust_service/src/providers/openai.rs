@@ -0,0 +1,264 @@
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder};
+use serde_json::Value;
+use tracing::{error, info};
+
+use super::{json_post, merge_llm_params, probe_json, sibling_url, stream_error, system_prompt, LlmProvider, TokenUsage};
+use crate::config::{mask_key, AppConfig};
+use crate::errors::AppError;
+
+/// OpenAI `/v1/responses` API
+pub struct OpenAiProvider;
+
+impl OpenAiProvider {
+    /// Add the API key and optional organization/project headers
+    fn authorize(&self, req_builder: RequestBuilder, config: &AppConfig) -> Result<RequestBuilder, AppError> {
+        let api_key = config.openai_api_key.as_deref().filter(|k| !k.is_empty()).ok_or_else(|| {
+            error!("Missing OpenAI API key. Set OPENAI_API_KEY environment variable.");
+            AppError::LlmApiError("Missing OpenAI API key".to_string())
+        })?;
+
+        let mut req_builder = req_builder.header("Authorization", format!("Bearer {}", api_key));
+
+        // Add optional organization ID if specified
+        if let Some(org_id) = config.openai_org_id.as_deref().filter(|id| !id.is_empty()) {
+            req_builder = req_builder.header("OpenAI-Organization", org_id);
+        }
+
+        // Add optional project ID if specified
+        if let Some(project_id) = config.openai_project_id.as_deref().filter(|id| !id.is_empty()) {
+            req_builder = req_builder.header("OpenAI-Project", project_id);
+        }
+
+        Ok(req_builder)
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiProvider {
+    fn name(&self) -> &'static str {
+        "OpenAI"
+    }
+
+    fn build_request(
+        &self,
+        client: &Client,
+        config: &AppConfig,
+        prompt: &str,
+        stream: bool,
+    ) -> Result<RequestBuilder, AppError> {
+        // OpenAI API format for /v1/responses endpoint
+        let mut input = Vec::new();
+        if let Some(system) = system_prompt(config) {
+            input.push(serde_json::json!({
+                "role": "system",
+                "content": [
+                    {
+                        "type": "input_text",
+                        "text": system
+                    }
+                ]
+            }));
+        }
+        input.push(serde_json::json!({
+            "role": "user",
+            "content": [
+                {
+                    "type": "input_text",
+                    "text": prompt
+                }
+            ]
+        }));
+
+        let mut payload = serde_json::json!({
+            "model": config.model_name,
+            "input": input,
+            "text": {
+                "format": {
+                    "type": "text"
+                }
+            },
+            "reasoning": {},
+            "tools": [],
+            "temperature": 0.7,
+            "max_output_tokens": 2048,
+            "top_p": 0.8,
+            "store": true
+        });
+        merge_llm_params(&mut payload, config);
+        payload["stream"] = Value::Bool(stream);
+
+        self.authorize(json_post(client, &config.llm_url, &payload), config)
+    }
+
+    fn parse_response(&self, response: &Value) -> Option<String> {
+        // The text lives in the first content item of the first output message
+        response
+            .get("output")?
+            .as_array()?
+            .first()?
+            .get("content")?
+            .as_array()?
+            .first()?
+            .get("text")?
+            .as_str()
+            .map(str::to_string)
+    }
+
+    fn parse_usage(&self, response: &Value) -> Option<TokenUsage> {
+        TokenUsage::from_pointers(response, "/usage/input_tokens", "/usage/output_tokens")
+    }
+
+    fn json_output_params(&self) -> Option<Value> {
+        Some(serde_json::json!({ "text": { "format": { "type": "json_object" } } }))
+    }
+
+    fn parse_stream_chunk(&self, chunk: &Value) -> Result<Option<String>, AppError> {
+        // Responses API streams typed events; only text deltas carry output
+        match chunk.get("type").and_then(Value::as_str) {
+            Some("response.output_text.delta") => {
+                Ok(chunk.get("delta").and_then(Value::as_str).map(str::to_string))
+            }
+            Some("error") => Err(stream_error(self.name(), chunk)),
+            Some("response.failed") => Err(stream_error(
+                self.name(),
+                chunk.pointer("/response/error").unwrap_or(chunk),
+            )),
+            _ => Ok(None),
+        }
+    }
+
+    async fn list_models(&self, client: &Client, config: &AppConfig) -> Result<Vec<String>, AppError> {
+        let url = sibling_url(&config.llm_url, "/responses", "/models");
+        let req_builder = self.authorize(client.get(url), config)?;
+        let body = probe_json(self.name(), req_builder).await?;
+
+        Ok(body
+            .get("data")
+            .and_then(Value::as_array)
+            .map(|models| {
+                models
+                    .iter()
+                    .filter_map(|m| m.get("id").and_then(Value::as_str).map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    async fn health_check(&self, client: &Client, config: &AppConfig) -> Result<(), AppError> {
+        if let Some(api_key) = config.openai_api_key.as_deref().filter(|k| !k.is_empty()) {
+            info!("✅ OpenAI API key is configured: {}", mask_key(api_key));
+        }
+        if let Some(org_id) = config.openai_org_id.as_deref().filter(|id| !id.is_empty()) {
+            info!("✅ Using OpenAI Organization ID: {}", org_id);
+        }
+        if let Some(project_id) = config.openai_project_id.as_deref().filter(|id| !id.is_empty()) {
+            info!("✅ Using OpenAI Project ID: {}", project_id);
+        }
+
+        self.list_models(client, config).await.map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn test_config() -> AppConfig {
+        AppConfig {
+            provider: crate::providers::ProviderKind::OpenAi,
+            llm_url: "https://api.openai.com/v1/responses".to_string(),
+            model_name: "gpt-4o".to_string(),
+            llm_params: Some(json!({ "temperature": 0.2 })),
+            openai_api_key: Some("sk-test-key".to_string()),
+            openai_org_id: Some("org-123".to_string()),
+            cache: crate::cache::CacheConfig { enabled: false, ..Default::default() },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_build_request() {
+        let request = OpenAiProvider
+            .build_request(&Client::new(), &test_config(), "Fix this", false)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        assert_eq!(request.url().as_str(), "https://api.openai.com/v1/responses");
+        assert_eq!(request.headers()["Authorization"], "Bearer sk-test-key");
+        assert_eq!(request.headers()["OpenAI-Organization"], "org-123");
+        assert!(request.headers().get("OpenAI-Project").is_none());
+
+        let body: Value = serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!(body["model"], "gpt-4o");
+        assert_eq!(body["input"][1]["content"][0]["text"], "Fix this");
+        assert_eq!(body["temperature"], 0.2);
+        assert_eq!(body["stream"], false);
+    }
+
+    #[test]
+    fn test_build_request_without_api_key() {
+        let mut config = test_config();
+        config.openai_api_key = None;
+
+        match OpenAiProvider.build_request(&Client::new(), &config, "Fix this", false) {
+            Err(AppError::LlmApiError(msg)) => assert!(msg.contains("Missing OpenAI API key")),
+            other => panic!("Expected LlmApiError, got: {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_build_request_system_prompt() {
+        let mut config = test_config();
+        config.system_prompt = Some("You are a translator.".to_string());
+        let request = OpenAiProvider.build_request(&Client::new(), &config, "Hallo", false).unwrap().build().unwrap();
+        let body: Value = serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!(body["input"][0]["role"], "system");
+        assert_eq!(body["input"][0]["content"][0]["text"], "You are a translator.");
+
+        // An empty system prompt leaves only the user message
+        config.system_prompt = Some(String::new());
+        let request = OpenAiProvider.build_request(&Client::new(), &config, "Hallo", false).unwrap().build().unwrap();
+        let body: Value = serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!(body["input"].as_array().unwrap().len(), 1);
+        assert_eq!(body["input"][0]["role"], "user");
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let response = json!({
+            "output": [
+                {
+                    "content": [
+                        { "text": "Test response from OpenAI" }
+                    ]
+                }
+            ]
+        });
+        assert_eq!(
+            OpenAiProvider.parse_response(&response),
+            Some("Test response from OpenAI".to_string())
+        );
+        assert_eq!(OpenAiProvider.parse_response(&json!({ "message": {} })), None);
+
+        let usage = json!({ "usage": { "input_tokens": 12, "output_tokens": 7, "total_tokens": 19 } });
+        assert_eq!(OpenAiProvider.parse_usage(&usage), Some(TokenUsage { input_tokens: 12, output_tokens: 7 }));
+    }
+
+    #[test]
+    fn test_parse_stream_chunk() {
+        let delta = json!({ "type": "response.output_text.delta", "item_id": "msg_1", "delta": "Hel" });
+        assert_eq!(OpenAiProvider.parse_stream_chunk(&delta).unwrap(), Some("Hel".to_string()));
+
+        let created = json!({ "type": "response.created", "response": {} });
+        assert_eq!(OpenAiProvider.parse_stream_chunk(&created).unwrap(), None);
+
+        let failed = json!({ "type": "response.failed", "response": { "error": { "message": "Server overloaded" } } });
+        match OpenAiProvider.parse_stream_chunk(&failed) {
+            Err(AppError::LlmApiError(msg)) => assert!(msg.contains("Server overloaded")),
+            other => panic!("Expected LlmApiError, got: {:?}", other),
+        }
+    }
+}
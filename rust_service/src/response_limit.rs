@@ -0,0 +1,228 @@
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use crate::chunking::{ends_sentence, estimate_tokens};
+use crate::errors::AppError;
+
+/// Unit `max_length` is measured in
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LengthUnit {
+    /// Unicode characters, not bytes
+    #[default]
+    Chars,
+    /// Estimated tokens, at about four characters per token
+    Tokens,
+}
+
+/// What to do with a response over the limit
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// Fail the request
+    Reject,
+    /// Cut the response at the last sentence end that fits
+    #[default]
+    Truncate,
+    /// Return the full response and flag it as over the limit
+    Warn,
+}
+
+/// Length limit for a single LLM response
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ResponseLimitConfig {
+    /// Longest allowed response; 0 disables the limit
+    pub max_length: usize,
+    pub unit: LengthUnit,
+    pub on_overflow: OverflowPolicy,
+}
+
+impl Default for ResponseLimitConfig {
+    fn default() -> Self {
+        Self {
+            max_length: 2000,
+            unit: LengthUnit::Chars,
+            on_overflow: OverflowPolicy::Truncate,
+        }
+    }
+}
+
+/// A response after the limit has been applied
+#[derive(Debug, PartialEq)]
+pub struct LimitedResponse {
+    pub text: String,
+    /// The model's response was longer than the limit
+    pub over_limit: bool,
+}
+
+impl ResponseLimitConfig {
+    fn length(&self, text: &str) -> usize {
+        match self.unit {
+            LengthUnit::Chars => text.chars().count(),
+            LengthUnit::Tokens => estimate_tokens(text),
+        }
+    }
+
+    /// Check a response against the limit and apply the overflow policy
+    pub fn apply(&self, response: String) -> Result<LimitedResponse, AppError> {
+        let length = self.length(&response);
+        if self.max_length == 0 || length <= self.max_length {
+            return Ok(LimitedResponse { text: response, over_limit: false });
+        }
+
+        let unit = match self.unit {
+            LengthUnit::Chars => "characters",
+            LengthUnit::Tokens => "tokens",
+        };
+        match self.on_overflow {
+            OverflowPolicy::Reject => Err(AppError::LlmApiError(format!(
+                "LLM response is too long ({} {}, limit is {})",
+                length, unit, self.max_length
+            ))),
+            OverflowPolicy::Truncate => {
+                let max_chars = match self.unit {
+                    LengthUnit::Chars => self.max_length,
+                    LengthUnit::Tokens => self.max_length * 4,
+                };
+                let text = truncate_at_sentence(&response, max_chars);
+                info!("LLM response was truncated from {} to {} {}", length, self.length(&text), unit);
+                Ok(LimitedResponse { text, over_limit: true })
+            }
+            OverflowPolicy::Warn => {
+                warn!("LLM response is over the limit ({} {}, limit is {})", length, unit, self.max_length);
+                Ok(LimitedResponse { text: response, over_limit: true })
+            }
+        }
+    }
+}
+
+/// Shorten a text to at most `max_chars` characters
+///
+/// Cuts after the last complete sentence that fits. Without one, cuts at the
+/// last word boundary (or mid-word as a last resort) and appends "…". The
+/// text's trailing whitespace, e.g. the newline kept from the request, is put
+/// back after the cut unless it alone fills the limit.
+fn truncate_at_sentence(text: &str, max_chars: usize) -> String {
+    let body = text.trim_end();
+    let trailing = &text[body.len()..];
+    let trailing_chars = trailing.chars().count();
+    if trailing_chars >= max_chars {
+        return cut_at_sentence(body, max_chars);
+    }
+    let mut truncated = cut_at_sentence(body, max_chars - trailing_chars);
+    truncated.push_str(trailing);
+    truncated
+}
+
+fn cut_at_sentence(text: &str, max_chars: usize) -> String {
+    let end = match text.char_indices().nth(max_chars) {
+        Some((end, _)) => end,
+        None => return text.to_string(),
+    };
+
+    // Whitespace positions up to and including the limit are possible cuts
+    let cuts: Vec<usize> = text
+        .char_indices()
+        .take_while(|(index, _)| *index <= end)
+        .filter(|(_, c)| c.is_whitespace())
+        .map(|(index, _)| index)
+        .collect();
+
+    if let Some(&cut) = cuts.iter().rev().find(|&&cut| ends_sentence(&text[..cut])) {
+        return text[..cut].trim_end().to_string();
+    }
+
+    // Leave room for the ellipsis
+    let word_cut = cuts.iter().rev().find(|&&cut| cut < end);
+    let cut = match word_cut {
+        Some(&cut) => cut,
+        None => text[..end].char_indices().last().map_or(0, |(index, _)| index),
+    };
+    let mut truncated = text[..cut].trim_end().to_string();
+    truncated.push('…');
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limit(max_length: usize, unit: LengthUnit, on_overflow: OverflowPolicy) -> ResponseLimitConfig {
+        ResponseLimitConfig { max_length, unit, on_overflow }
+    }
+
+    #[test]
+    fn test_within_limit_is_unchanged() {
+        let config = limit(5, LengthUnit::Chars, OverflowPolicy::Reject);
+        // Five characters, but ten bytes
+        let limited = config.apply("привет".chars().take(5).collect()).unwrap();
+        assert_eq!(limited, LimitedResponse { text: "приве".to_string(), over_limit: false });
+
+        let unlimited = limit(0, LengthUnit::Chars, OverflowPolicy::Reject);
+        assert!(!unlimited.apply("x".repeat(10_000)).unwrap().over_limit);
+    }
+
+    #[test]
+    fn test_truncate_at_sentence_end() {
+        let config = limit(40, LengthUnit::Chars, OverflowPolicy::Truncate);
+        let limited = config.apply("First sentence. Second sentence! Third one is cut.".to_string()).unwrap();
+
+        assert_eq!(limited.text, "First sentence. Second sentence!");
+        assert!(limited.over_limit);
+    }
+
+    #[test]
+    fn test_truncate_multibyte_text() {
+        // Used to panic when slicing at a byte index inside a character
+        let text = "Привет, мир! Это длинный текст. 日本語のテキストです。 😀😀😀😀😀😀";
+        let config = limit(25, LengthUnit::Chars, OverflowPolicy::Truncate);
+        assert_eq!(config.apply(text.to_string()).unwrap().text, "Привет, мир!");
+
+        let config = limit(5, LengthUnit::Chars, OverflowPolicy::Truncate);
+        assert_eq!(config.apply("😀😀😀😀😀😀😀".to_string()).unwrap().text, "😀😀😀😀…");
+    }
+
+    #[test]
+    fn test_truncate_without_sentence_end() {
+        let config = limit(16, LengthUnit::Chars, OverflowPolicy::Truncate);
+        let limited = config.apply("a list of words without any full stop".to_string()).unwrap();
+
+        assert_eq!(limited.text, "a list of words…");
+        assert!(limited.text.chars().count() <= 16);
+    }
+
+    #[test]
+    fn test_truncate_keeps_trailing_whitespace() {
+        let config = limit(20, LengthUnit::Chars, OverflowPolicy::Truncate);
+        let limited = config.apply("  First sentence. Second sentence.\n\n".to_string()).unwrap();
+        assert_eq!(limited.text, "  First sentence.\n\n");
+
+        let limited = config.apply("a list of words without any full stop\n".to_string()).unwrap();
+        assert_eq!(limited.text, "a list of words…\n");
+        assert!(limited.text.chars().count() <= 20);
+    }
+
+    #[test]
+    fn test_token_limit() {
+        let config = limit(5, LengthUnit::Tokens, OverflowPolicy::Truncate);
+        let limited = config.apply("Short one. This second sentence goes past the budget.".to_string()).unwrap();
+
+        assert_eq!(limited.text, "Short one.");
+        assert!(estimate_tokens(&limited.text) <= 5);
+    }
+
+    #[test]
+    fn test_reject_and_warn() {
+        let text = "x".repeat(30);
+
+        let config = limit(20, LengthUnit::Chars, OverflowPolicy::Reject);
+        match config.apply(text.clone()) {
+            Err(AppError::LlmApiError(msg)) => assert!(msg.contains("30 characters, limit is 20")),
+            other => panic!("Expected LlmApiError, got: {:?}", other),
+        }
+
+        let config = limit(20, LengthUnit::Chars, OverflowPolicy::Warn);
+        assert_eq!(config.apply(text.clone()).unwrap(), LimitedResponse { text, over_limit: true });
+    }
+}
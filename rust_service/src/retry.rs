@@ -0,0 +1,186 @@
+use rand::Rng;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::time::{Duration, SystemTime};
+
+/// Retry policy for requests to the LLM backend
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RetryConfig {
+    /// Total number of attempts, including the first one; 1 disables retries
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for every further attempt
+    pub base_delay_ms: u64,
+    /// Upper bound for a single delay, including one requested via `Retry-After`
+    pub max_delay_ms: u64,
+    /// Randomise each delay between half and the full backoff
+    pub jitter: bool,
+    /// HTTP status codes that are worth retrying
+    pub retry_on_status: Vec<u16>,
+    /// Retry when the backend cannot be reached
+    pub retry_on_connect_error: bool,
+    /// Retry when the request times out
+    pub retry_on_timeout: bool,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay_ms: 500,
+            max_delay_ms: 10_000,
+            jitter: true,
+            retry_on_status: vec![429, 500, 502, 503, 504],
+            retry_on_connect_error: true,
+            retry_on_timeout: true,
+        }
+    }
+}
+
+impl RetryConfig {
+    /// Delay before retrying a request that failed to send, or `None` to give up
+    pub fn delay_after_error(&self, error: &reqwest::Error, attempt: u32) -> Option<Duration> {
+        let retryable = (error.is_connect() && self.retry_on_connect_error)
+            || (error.is_timeout() && self.retry_on_timeout);
+
+        if retryable && attempt < self.max_attempts {
+            Some(self.backoff(attempt))
+        } else {
+            None
+        }
+    }
+
+    /// Delay before retrying a request that got an error status, or `None` to give up
+    ///
+    /// A `Retry-After` header replaces the computed backoff. If the backend asks
+    /// for a longer wait than `max_delay_ms` we fail immediately instead.
+    pub fn delay_after_status(&self, status: StatusCode, headers: &HeaderMap, attempt: u32) -> Option<Duration> {
+        if !self.retry_on_status.contains(&status.as_u16()) || attempt >= self.max_attempts {
+            return None;
+        }
+
+        match retry_after(headers) {
+            Some(delay) if delay > Duration::from_millis(self.max_delay_ms) => None,
+            Some(delay) => Some(delay),
+            None => Some(self.backoff(attempt)),
+        }
+    }
+
+    /// Exponential backoff for the given (1-based) attempt that just failed
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(31);
+        let delay_ms = self
+            .base_delay_ms
+            .saturating_mul(1 << exponent)
+            .min(self.max_delay_ms);
+
+        if self.jitter && delay_ms > 1 {
+            Duration::from_millis(rand::thread_rng().gen_range(delay_ms / 2..=delay_ms))
+        } else {
+            Duration::from_millis(delay_ms)
+        }
+    }
+}
+
+/// Parse a `Retry-After` header given either in seconds or as an HTTP date
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = httpdate::parse_http_date(value).ok()?;
+    // A date in the past means "retry now"
+    Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn no_jitter() -> RetryConfig {
+        RetryConfig {
+            jitter: false,
+            ..RetryConfig::default()
+        }
+    }
+
+    fn retry_after_headers(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_max_delay() {
+        let config = RetryConfig {
+            max_attempts: 10,
+            base_delay_ms: 500,
+            max_delay_ms: 3_000,
+            ..no_jitter()
+        };
+
+        assert_eq!(config.backoff(1), Duration::from_millis(500));
+        assert_eq!(config.backoff(2), Duration::from_millis(1_000));
+        assert_eq!(config.backoff(3), Duration::from_millis(2_000));
+        assert_eq!(config.backoff(4), Duration::from_millis(3_000));
+        assert_eq!(config.backoff(40), Duration::from_millis(3_000));
+    }
+
+    #[test]
+    fn test_backoff_jitter_stays_in_range() {
+        let config = RetryConfig::default();
+        for _ in 0..100 {
+            let delay = config.backoff(2);
+            assert!(delay >= Duration::from_millis(500) && delay <= Duration::from_millis(1_000));
+        }
+    }
+
+    #[test]
+    fn test_delay_after_status() {
+        let config = no_jitter();
+        let headers = HeaderMap::new();
+
+        assert_eq!(
+            config.delay_after_status(StatusCode::SERVICE_UNAVAILABLE, &headers, 1),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(
+            config.delay_after_status(StatusCode::TOO_MANY_REQUESTS, &headers, 2),
+            Some(Duration::from_millis(1_000))
+        );
+        // Client errors are never retried
+        assert_eq!(config.delay_after_status(StatusCode::BAD_REQUEST, &headers, 1), None);
+        assert_eq!(config.delay_after_status(StatusCode::UNAUTHORIZED, &headers, 1), None);
+        // No attempts left
+        assert_eq!(config.delay_after_status(StatusCode::SERVICE_UNAVAILABLE, &headers, 3), None);
+    }
+
+    #[test]
+    fn test_retry_after_header() {
+        let config = no_jitter();
+
+        assert_eq!(
+            config.delay_after_status(StatusCode::TOO_MANY_REQUESTS, &retry_after_headers("2"), 1),
+            Some(Duration::from_secs(2))
+        );
+        // Longer than max_delay_ms: give up rather than hold the request
+        assert_eq!(
+            config.delay_after_status(StatusCode::TOO_MANY_REQUESTS, &retry_after_headers("120"), 1),
+            None
+        );
+
+        let soon = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(5));
+        let delay = retry_after(&retry_after_headers(&soon)).unwrap();
+        assert!(delay > Duration::from_secs(3) && delay <= Duration::from_secs(5));
+
+        assert_eq!(
+            retry_after(&retry_after_headers("Wed, 21 Oct 2015 07:28:00 GMT")),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(retry_after(&retry_after_headers("soon")), None);
+    }
+}
@@ -0,0 +1,150 @@
+use futures_util::{stream, Stream, StreamExt};
+use serde_json::Value;
+use std::collections::VecDeque;
+
+use crate::errors::AppError;
+
+/// Wire format of a streamed LLM reply
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamFormat {
+    /// Server-Sent Events with one JSON object per `data:` line (OpenAI, Anthropic)
+    Sse,
+    /// Newline-delimited JSON (Ollama)
+    Ndjson,
+}
+
+/// Splits incoming bytes into complete lines
+#[derive(Default)]
+struct LineDecoder {
+    buffer: Vec<u8>,
+}
+
+impl LineDecoder {
+    /// Add a chunk and return every line it completed
+    fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut lines = Vec::new();
+        while let Some(pos) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+            lines.push(String::from_utf8_lossy(&line).trim_end_matches(['\r', '\n']).to_string());
+        }
+        lines
+    }
+
+    /// Return whatever is left once the stream has ended
+    fn finish(&mut self) -> Option<String> {
+        let rest = String::from_utf8_lossy(&self.buffer).trim().to_string();
+        self.buffer.clear();
+        if rest.is_empty() {
+            None
+        } else {
+            Some(rest)
+        }
+    }
+}
+
+/// Parse one line into a JSON message, skipping blank lines and SSE metadata
+fn parse_line(line: &str, format: StreamFormat) -> Option<Result<Value, AppError>> {
+    let data = match format {
+        StreamFormat::Ndjson => line.trim(),
+        // `event:`, `id:` and `:` comment lines carry nothing we need
+        StreamFormat::Sse => line.strip_prefix("data:")?.trim(),
+    };
+
+    if data.is_empty() || data == "[DONE]" {
+        return None;
+    }
+    Some(serde_json::from_str(data).map_err(AppError::from))
+}
+
+/// Turn a raw byte stream into a stream of JSON messages
+pub fn json_messages<S, B, E>(bytes: S, format: StreamFormat) -> impl Stream<Item = Result<Value, AppError>>
+where
+    S: Stream<Item = Result<B, E>> + Unpin,
+    B: AsRef<[u8]>,
+    E: Into<AppError>,
+{
+    let state = (bytes, LineDecoder::default(), VecDeque::new(), false);
+
+    stream::unfold(state, move |(mut bytes, mut decoder, mut pending, mut finished)| async move {
+        loop {
+            if let Some(item) = pending.pop_front() {
+                return Some((item, (bytes, decoder, pending, finished)));
+            }
+            if finished {
+                return None;
+            }
+
+            match bytes.next().await {
+                Some(Ok(chunk)) => {
+                    pending.extend(decoder.push(chunk.as_ref()).iter().filter_map(|line| parse_line(line, format)));
+                }
+                Some(Err(e)) => {
+                    finished = true;
+                    pending.push_back(Err(e.into()));
+                }
+                None => {
+                    finished = true;
+                    pending.extend(decoder.finish().and_then(|line| parse_line(&line, format)));
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    async fn collect(chunks: Vec<&str>, format: StreamFormat) -> Vec<Result<Value, AppError>> {
+        let bytes = stream::iter(chunks.into_iter().map(|c| Ok::<_, AppError>(c.as_bytes().to_vec())));
+        json_messages(bytes, format).collect().await
+    }
+
+    #[tokio::test]
+    async fn test_ndjson_split_across_chunks() {
+        let messages = collect(
+            vec![
+                "{\"message\":{\"content\":\"Hel\"},\"done\":false}\n{\"message\":",
+                "{\"content\":\"lo\"},\"done\":false}\n",
+                "{\"done\":true}",
+            ],
+            StreamFormat::Ndjson,
+        )
+        .await;
+
+        let messages: Vec<Value> = messages.into_iter().map(Result::unwrap).collect();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0]["message"]["content"], "Hel");
+        assert_eq!(messages[1]["message"]["content"], "lo");
+        assert_eq!(messages[2], json!({ "done": true }));
+    }
+
+    #[tokio::test]
+    async fn test_sse_skips_metadata_and_done() {
+        let messages = collect(
+            vec![
+                ": keep-alive\r\n",
+                "event: response.output_text.delta\r\ndata: {\"delta\":\"Hel\"}\r\n\r\n",
+                "event: response.output_text.delta\ndata: {\"del",
+                "ta\":\"lo\"}\n\n",
+                "data: [DONE]\n\n",
+            ],
+            StreamFormat::Sse,
+        )
+        .await;
+
+        let messages: Vec<Value> = messages.into_iter().map(Result::unwrap).collect();
+        assert_eq!(messages, vec![json!({ "delta": "Hel" }), json!({ "delta": "lo" })]);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_json_is_reported() {
+        let messages = collect(vec!["not json\n"], StreamFormat::Ndjson).await;
+
+        assert_eq!(messages.len(), 1);
+        assert!(matches!(messages[0], Err(AppError::SerdeJson(_))));
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::BTreeMap;
+use tracing::{debug, warn};
+
+use crate::errors::AppError;
+
+/// Values substituted into a prompt template, keyed by variable name
+pub type TemplateVars = BTreeMap<String, String>;
+
+/// Variable that receives the user's text; every template must use it
+pub const INPUT_VAR: &str = "input";
+
+/// Placeholder syntax from before `{{input}}`, still accepted for the input only
+const LEGACY_INPUT: &str = "{input}";
+
+/// A piece of a parsed template
+#[derive(Debug, PartialEq)]
+enum Segment<'a> {
+    Text(&'a str),
+    Var(&'a str),
+}
+
+fn template_error(message: String) -> AppError {
+    AppError::Config(config::ConfigError::Message(message))
+}
+
+/// Split a template into literal text and `{{name}}` placeholders
+fn parse(template: &str) -> Result<Vec<Segment<'_>>, AppError> {
+    let mut segments = Vec::new();
+    let mut rest = template;
+
+    while let Some(pos) = rest.find('{') {
+        let (text, tail) = rest.split_at(pos);
+
+        if let Some(inner) = tail.strip_prefix("{{") {
+            let end = inner.find("}}").ok_or_else(|| {
+                template_error(format!(
+                    "Unclosed '{{{{' in prompt template near: {}",
+                    tail.chars().take(30).collect::<String>()
+                ))
+            })?;
+            let name = inner[..end].trim();
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(template_error(format!(
+                    "Invalid variable name '{}' in prompt template; use letters, digits and '_'",
+                    name
+                )));
+            }
+            segments.push(Segment::Text(text));
+            segments.push(Segment::Var(name));
+            rest = &inner[end + 2..];
+        } else if let Some(after) = tail.strip_prefix(LEGACY_INPUT) {
+            segments.push(Segment::Text(text));
+            segments.push(Segment::Var(INPUT_VAR));
+            rest = after;
+        } else {
+            // A lone brace is literal text, e.g. in JSON examples
+            segments.push(Segment::Text(&rest[..pos + 1]));
+            rest = &tail[1..];
+        }
+    }
+    segments.push(Segment::Text(rest));
+    segments.retain(|segment| *segment != Segment::Text(""));
+
+    Ok(segments)
+}
+
+/// Check that a template parses and contains the input placeholder
+pub fn validate(template: &str) -> Result<(), AppError> {
+    let segments = parse(template)?;
+
+    if !segments.contains(&Segment::Var(INPUT_VAR)) {
+        return Err(template_error(
+            "Prompt template has no {{input}} placeholder, so the user's text would be dropped".to_string(),
+        ));
+    }
+    if template.contains(LEGACY_INPUT) && !template.contains("{{input}}") {
+        warn!("Prompt template uses the old {{input}} placeholder syntax; prefer {{{{input}}}}");
+    }
+    Ok(())
+}
+
+/// Names of the variables a template uses, without duplicates
+pub fn variables(template: &str) -> Result<Vec<&str>, AppError> {
+    let mut names: Vec<&str> = parse(template)?
+        .into_iter()
+        .filter_map(|segment| match segment {
+            Segment::Var(name) => Some(name),
+            Segment::Text(_) => None,
+        })
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+    Ok(names)
+}
+
+/// Substitute the variables into a template
+///
+/// Variables without a value are replaced with an empty string.
+pub fn render(template: &str, vars: &TemplateVars) -> Result<String, AppError> {
+    let mut output = String::with_capacity(template.len());
+
+    for segment in parse(template)? {
+        match segment {
+            Segment::Text(text) => output.push_str(text),
+            Segment::Var(name) => match vars.get(name) {
+                Some(value) => output.push_str(value),
+                None => debug!("No value for template variable '{}', leaving it empty", name),
+            },
+        }
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> TemplateVars {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_render() {
+        let template = "Rewrite for {{ app }} in {{language}} ({{date}}):\n\n{{input}}";
+        let rendered = render(
+            template,
+            &vars(&[("input", "hello"), ("app", "Slack"), ("language", "English"), ("date", "2025-01-31")]),
+        )
+        .unwrap();
+
+        assert_eq!(rendered, "Rewrite for Slack in English (2025-01-31):\n\nhello");
+    }
+
+    #[test]
+    fn test_render_keeps_literal_braces_and_values() {
+        // Braces in the template and in the user's text are left alone
+        let template = "Reply as JSON like {\"text\": \"...\"}.\n{{input}}";
+        let rendered = render(template, &vars(&[("input", "use {{input}} and {x}")])).unwrap();
+
+        assert_eq!(rendered, "Reply as JSON like {\"text\": \"...\"}.\nuse {{input}} and {x}");
+    }
+
+    #[test]
+    fn test_render_missing_variable_is_empty() {
+        let rendered = render("[{{app}}] {{input}}", &vars(&[("input", "hi")])).unwrap();
+        assert_eq!(rendered, "[] hi");
+    }
+
+    #[test]
+    fn test_legacy_input_placeholder() {
+        assert!(validate("Improve this text: {input}").is_ok());
+        let rendered = render("Improve this text: {input}", &vars(&[("input", "Test input")])).unwrap();
+        assert_eq!(rendered, "Improve this text: Test input");
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate("Fix this:\n{{input}}").is_ok());
+
+        match validate("Fix the grammar of the following text.") {
+            Err(AppError::Config(e)) => assert!(e.to_string().contains("no {{input}} placeholder")),
+            other => panic!("Expected Config error, got: {:?}", other),
+        }
+        match validate("Fix {{input}} for {{app") {
+            Err(AppError::Config(e)) => assert!(e.to_string().contains("Unclosed '{{'")),
+            other => panic!("Expected Config error, got: {:?}", other),
+        }
+        match validate("Fix {{in put}}") {
+            Err(AppError::Config(e)) => assert!(e.to_string().contains("Invalid variable name 'in put'")),
+            other => panic!("Expected Config error, got: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_variables() {
+        let names = variables("{{input}} {{ language }} {{app}} {{input}}").unwrap();
+        assert_eq!(names, vec!["app", "input", "language"]);
+    }
+}
@@ -0,0 +1,214 @@
+mod common;
+
+use tempfile::TempDir;
+use axum::extract::State;
+use axum::Json;
+use wiremock::{MockServer, Mock, ResponseTemplate};
+use wiremock::matchers::{body_partial_json, method, path};
+use serde_json::json;
+
+use writer_ai_rust_service::errors::AppError;
+use writer_ai_rust_service::http::{cache_fingerprint, process_text_handler, ProcessRequest};
+use writer_ai_rust_service::providers::ProviderKind;
+use writer_ai_rust_service::template::TemplateVars;
+
+/// Ollama gets one request per alternative at rising temperatures
+#[tokio::test]
+async fn test_ollama_alternatives_are_deduplicated_and_cached() {
+    let mock_server = MockServer::start().await;
+    let temp_dir = TempDir::new().unwrap();
+
+    Mock::given(method("POST"))
+        .and(path("/api/chat"))
+        .and(body_partial_json(json!({ "options": { "temperature": 0.9 } })))
+        .respond_with(common::ollama_reply("I am not used to it yet."))
+        .with_priority(1)
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/api/chat"))
+        .and(body_partial_json(json!({ "options": { "temperature": 1.1 } })))
+        // Only differs in spacing and case from the 0.9 candidate
+        .respond_with(common::ollama_reply("I am not  used to it YET."))
+        .with_priority(1)
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/api/chat"))
+        .respond_with(common::ollama_reply("I'm not used to it."))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let config = common::ollama_config(&mock_server);
+    let state = common::state(config, &temp_dir);
+    let request = ProcessRequest { text: "I not used it.".to_string(), n: Some(3), ..Default::default() };
+
+    let response = process_text_handler(State(state.clone()), Json(request.clone())).await.unwrap();
+    assert_eq!(response.response, "I'm not used to it.");
+    assert_eq!(
+        response.alternatives.as_deref().unwrap(),
+        ["I'm not used to it.", "I am not used to it yet."]
+    );
+
+    // The whole candidate set comes from the cache the second time
+    let cached = process_text_handler(State(state.clone()), Json(request)).await.unwrap();
+    assert_eq!(cached.alternatives, response.alternatives);
+
+    // The single-response entry for the same text is a separate one
+    let fingerprint = cache_fingerprint(&state.0, None, &TemplateVars::new(), 1);
+    assert!(state.2.lookup("I not used it.", "llama3", &fingerprint).unwrap().is_none());
+}
+
+/// Chat Completions servers return all alternatives from one request with `n`
+#[tokio::test]
+async fn test_chat_completions_alternatives_use_n() {
+    let mock_server = MockServer::start().await;
+    let temp_dir = TempDir::new().unwrap();
+
+    Mock::given(method("POST"))
+        .and(path("/v1/chat/completions"))
+        .and(body_partial_json(json!({ "n": 2 })))
+        .respond_with(common::chat_reply(&["How are you?", "How are you doing?"]))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let config = common::test_config(ProviderKind::OpenAiCompatible, format!("{}/v1/chat/completions", mock_server.uri()), "llama3");
+    let state = common::state(config, &temp_dir);
+    let request = ProcessRequest { text: "Howe ar you?".to_string(), n: Some(2), ..Default::default() };
+
+    let response = process_text_handler(State(state), Json(request)).await.unwrap();
+    assert_eq!(response.response, "How are you?");
+    assert_eq!(response.alternatives.as_deref().unwrap(), ["How are you?", "How are you doing?"]);
+}
+
+/// With `n`, the request is sent again when every candidate lost a protected
+/// span, and candidates that lost one are left out and counted
+#[tokio::test]
+async fn test_chat_completions_alternatives_keep_protected_spans() {
+    let mock_server = MockServer::start().await;
+    let temp_dir = TempDir::new().unwrap();
+
+    Mock::given(method("POST"))
+        .and(path("/v1/chat/completions"))
+        .respond_with(common::chat_reply(&["Run make now.", "Please run make now."]))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/v1/chat/completions"))
+        .respond_with(common::chat_reply(&["Run [[KEEP_0]] now.", "Please run make now."]))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let config = common::test_config(ProviderKind::OpenAiCompatible, format!("{}/v1/chat/completions", mock_server.uri()), "llama3");
+    let state = common::state(config, &temp_dir);
+    let request = ProcessRequest { text: "run `make` now".to_string(), n: Some(2), ..Default::default() };
+
+    let response = process_text_handler(State(state), Json(request)).await.unwrap();
+    assert_eq!(response.response, "Run `make` now.");
+    assert_eq!(response.alternatives.as_deref().unwrap(), ["Run `make` now."]);
+    assert_eq!(response.dropped_alternatives, Some(1));
+}
+
+/// With `n`, candidates in another language are left out, and the request is
+/// sent again with the language instruction when all of them switched
+#[tokio::test]
+async fn test_chat_completions_alternatives_keep_language() {
+    let mock_server = MockServer::start().await;
+    let temp_dir = TempDir::new().unwrap();
+
+    let ukrainian = "Будь ласка, перевірте цей документ до завтрашньої зустрічі.";
+    let english = "Please check this document before tomorrow's meeting.";
+    Mock::given(method("POST"))
+        .and(path("/v1/chat/completions"))
+        .respond_with(common::chat_reply(&[english, english]))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/v1/chat/completions"))
+        .respond_with(common::chat_reply(&[english, ukrainian]))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let config = common::test_config(ProviderKind::OpenAiCompatible, format!("{}/v1/chat/completions", mock_server.uri()), "llama3");
+    let state = common::state(config, &temp_dir);
+    let text = "Будь ласка перевірте цей документ до завтрашньої зустрічі";
+    let request = ProcessRequest { text: text.to_string(), n: Some(2), ..Default::default() };
+
+    let response = process_text_handler(State(state), Json(request)).await.unwrap();
+    assert_eq!(response.response, ukrainian);
+    assert_eq!(response.alternatives.as_deref().unwrap(), [ukrainian]);
+    assert_eq!(response.dropped_alternatives, Some(1));
+    assert!(response.language_mismatch.is_none());
+
+    let requests = mock_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&requests[1].body).unwrap();
+    let prompt = body["messages"].as_array().unwrap().last().unwrap()["content"].as_str().unwrap();
+    assert!(prompt.contains("the text is written in Ukrainian"));
+}
+
+/// The OpenAI Responses API has no `n`, so each alternative is a request of its own
+#[tokio::test]
+async fn test_openai_alternatives_use_parallel_requests() {
+    let mock_server = MockServer::start().await;
+    let temp_dir = TempDir::new().unwrap();
+    let reply = |text: &str| {
+        ResponseTemplate::new(200).set_body_json(json!({
+            "output": [{ "content": [{ "type": "output_text", "text": text }] }]
+        }))
+    };
+
+    Mock::given(method("POST"))
+        .and(path("/v1/responses"))
+        .and(body_partial_json(json!({ "temperature": 0.9 })))
+        .respond_with(reply("How are you doing?"))
+        .with_priority(1)
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/v1/responses"))
+        .respond_with(reply("How are you?"))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let config = common::test_config(ProviderKind::OpenAi, format!("{}/v1/responses", mock_server.uri()), "gpt-4o");
+    let state = common::state(config, &temp_dir);
+    let request = ProcessRequest { text: "Howe ar you?".to_string(), n: Some(2), ..Default::default() };
+
+    let response = process_text_handler(State(state), Json(request)).await.unwrap();
+    assert_eq!(response.response, "How are you?");
+    assert_eq!(response.alternatives.as_deref().unwrap(), ["How are you?", "How are you doing?"]);
+
+    for request in mock_server.received_requests().await.unwrap() {
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert!(body.get("n").is_none());
+    }
+}
+
+#[tokio::test]
+async fn test_alternatives_out_of_range() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = common::test_config(ProviderKind::Ollama, "http://127.0.0.1:9/api/chat".to_string(), "llama3");
+    let state = common::state(config, &temp_dir);
+
+    for n in [0, 6] {
+        let request = ProcessRequest { text: "Hello".to_string(), n: Some(n), ..Default::default() };
+        match process_text_handler(State(state.clone()), Json(request)).await {
+            Err(AppError::InvalidRequest(msg)) => assert!(msg.contains("between 1 and 5")),
+            other => panic!("Expected InvalidRequest error, got: {:?}", other.map(|r| r.0)),
+        }
+    }
+}
@@ -1,15 +1,14 @@
-use std::sync::Arc;
+mod common;
+
 use tempfile::TempDir;
 use axum::extract::State;
 use axum::Json;
-use reqwest::Client;
 use wiremock::{MockServer, Mock, ResponseTemplate};
 use wiremock::matchers::{method, path};
 use serde_json::json;
 
-use writer_ai_rust_service::config::AppConfig;
-use writer_ai_rust_service::cache::CacheManager;
 use writer_ai_rust_service::http::{process_text_handler, ProcessRequest};
+use writer_ai_rust_service::providers::ProviderKind;
 
 /// Test that the caching functionality works end-to-end
 #[tokio::test]
@@ -19,33 +18,15 @@ async fn test_cache_integration() {
     
     // Create a temporary directory for the cache
     let temp_dir = TempDir::new().unwrap();
-    let cache_path = temp_dir.path().join("test_cache.sled");
-    
-    // Create configuration for this test
-    let app_config = AppConfig {
-        port: 8989,
-        llm_url: format!("{}/v1/responses", mock_server.uri()),
-        model_name: "test-model".to_string(),
-        llm_params: None,
-        prompt_template: None,
-        openai_api_key: Some("fake-api-key".to_string()),
-        openai_org_id: None,
-        openai_project_id: None,
-        cache: writer_ai_rust_service::cache::CacheConfig {
-            enabled: true,
-            ttl_days: 30,
-            max_size_mb: 100,
-        },
-    };
     
-    // Set up the shared state
-    let client = Arc::new(Client::new());
-    let cache_manager = Arc::new(CacheManager::new(cache_path, app_config.cache.clone()).unwrap());
-    let app_state = (Arc::new(app_config.clone()), client.clone(), cache_manager.clone());
+    // Create configuration for this test and set up the shared state
+    let app_config = common::test_config(ProviderKind::OpenAi, format!("{}/v1/responses", mock_server.uri()), "test-model");
+    let app_state = common::state(app_config, &temp_dir);
     
     // Create test request
     let request = ProcessRequest {
         text: "Test input for caching".to_string(),
+        ..Default::default()
     };
     
     // Configure first mock response - use a more specific matcher for the first request
@@ -78,6 +59,7 @@ async fn test_cache_integration() {
     // Create a request with different text (should miss cache)
     let different_request = ProcessRequest {
         text: "Different test input".to_string(),
+        ..Default::default()
     };
     
     // Set up another expectation for the different request
@@ -106,6 +88,7 @@ async fn test_cache_integration() {
     // Original request should still be in cache
     let original_request = ProcessRequest {
         text: "Test input for caching".to_string(),
+        ..Default::default()
     };
     
     // Should still be in cache
@@ -139,33 +122,16 @@ async fn test_disabled_cache() {
     
     // Create a temporary directory for the cache
     let temp_dir = TempDir::new().unwrap();
-    let cache_path = temp_dir.path().join("test_cache.sled");
-    
-    // Create configuration with cache disabled
-    let app_config = AppConfig {
-        port: 8989,
-        llm_url: format!("{}/v1/responses", mock_server.uri()),
-        model_name: "test-model".to_string(),
-        llm_params: None,
-        prompt_template: None,
-        openai_api_key: Some("fake-api-key".to_string()),
-        openai_org_id: None,
-        openai_project_id: None,
-        cache: writer_ai_rust_service::cache::CacheConfig {
-            enabled: false, // Cache is disabled
-            ttl_days: 30,
-            max_size_mb: 100,
-        },
-    };
     
-    // Set up the shared state
-    let client = Arc::new(Client::new());
-    let cache_manager = Arc::new(CacheManager::new(cache_path, app_config.cache.clone()).unwrap());
-    let app_state = (Arc::new(app_config.clone()), client.clone(), cache_manager.clone());
+    // Create configuration with cache disabled and set up the shared state
+    let mut app_config = common::test_config(ProviderKind::OpenAi, format!("{}/v1/responses", mock_server.uri()), "test-model");
+    app_config.cache.enabled = false;
+    let app_state = common::state(app_config, &temp_dir);
     
     // Create test request
     let request = ProcessRequest {
         text: "Test input for disabled cache".to_string(),
+        ..Default::default()
     };
     
     // First request should call the LLM API
@@ -0,0 +1,139 @@
+mod common;
+
+use tempfile::TempDir;
+use axum::extract::State;
+use axum::Json;
+use wiremock::{MockServer, Mock, Request, Respond, ResponseTemplate};
+use wiremock::matchers::{method, path};
+
+use writer_ai_rust_service::chunking::ChunkingConfig;
+use writer_ai_rust_service::http::{process_text_handler, ProcessRequest};
+
+/// Ollama stand-in that answers with the user's message in upper case,
+/// leaving out the instructions about placeholders
+struct Uppercase;
+
+impl Respond for Uppercase {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let prompt = common::prompts(std::slice::from_ref(request)).remove(0);
+        let text = prompt.split("\n\nThe text contains placeholders").next().unwrap();
+        common::ollama_reply(&text.to_uppercase())
+    }
+}
+
+async fn setup(chunking: ChunkingConfig) -> (MockServer, TempDir, common::AppState) {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/chat"))
+        .respond_with(Uppercase)
+        .mount(&mock_server)
+        .await;
+
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = common::ollama_config(&mock_server);
+    config.chunking = chunking;
+    let state = common::state(config, &temp_dir);
+    (mock_server, temp_dir, state)
+}
+
+fn small_chunks() -> ChunkingConfig {
+    ChunkingConfig {
+        max_chunk_tokens: 10,
+        ..Default::default()
+    }
+}
+
+const DOCUMENT: &str = "Hi team,\n\nThe release is ready for review.\n\n\
+                        - fix the login bug\n  - add a regression test\n- update the docs\n\nThanks!\n";
+
+/// Chunks are rewritten separately and reassembled with the original layout
+#[tokio::test]
+async fn test_long_document_is_chunked_and_reassembled() {
+    let (mock_server, _temp_dir, state) = setup(small_chunks()).await;
+
+    let request = ProcessRequest { text: DOCUMENT.to_string(), ..Default::default() };
+    let response = process_text_handler(State(state), Json(request)).await.unwrap();
+
+    assert_eq!(
+        response.response,
+        "HI TEAM,\n\nTHE RELEASE IS READY FOR REVIEW.\n\n\
+         - FIX THE LOGIN BUG\n  - ADD A REGRESSION TEST\n- UPDATE THE DOCS\n\nTHANKS!\n"
+    );
+    assert_eq!(response.model, "llama3");
+
+    // No chunk carries the blank lines between paragraphs
+    let requests = mock_server.received_requests().await.unwrap();
+    assert!(requests.len() > 3);
+    for chunk in common::prompts(&requests) {
+        assert!(!chunk.contains("\n\n"), "chunk spans paragraphs: {:?}", chunk);
+    }
+}
+
+/// Editing one paragraph only re-queries that paragraph's chunk
+#[tokio::test]
+async fn test_chunks_are_cached_separately() {
+    let (mock_server, _temp_dir, state) = setup(small_chunks()).await;
+
+    let request = ProcessRequest { text: DOCUMENT.to_string(), ..Default::default() };
+    let response = process_text_handler(State(state.clone()), Json(request)).await.unwrap();
+    assert!(response.response.ends_with("\n\nTHANKS!\n"));
+    let first_run = mock_server.received_requests().await.unwrap().len();
+
+    let edited = DOCUMENT.replace("Thanks!", "Thanks a lot!");
+    let request = ProcessRequest { text: edited, ..Default::default() };
+    let response = process_text_handler(State(state), Json(request)).await.unwrap();
+
+    assert!(response.response.ends_with("\n\nTHANKS A LOT!\n"));
+    assert_eq!(mock_server.received_requests().await.unwrap().len(), first_run + 1);
+}
+
+/// Concurrent processing still returns the chunks in order
+#[tokio::test]
+async fn test_concurrent_chunks_keep_order() {
+    let chunking = ChunkingConfig {
+        max_concurrency: 4,
+        ..small_chunks()
+    };
+    let (_mock_server, _temp_dir, state) = setup(chunking).await;
+
+    let request = ProcessRequest { text: DOCUMENT.to_string(), ..Default::default() };
+    let response = process_text_handler(State(state), Json(request)).await.unwrap();
+
+    assert_eq!(response.response, DOCUMENT.to_uppercase());
+}
+
+/// With chunking disabled the whole text goes out in one request
+#[tokio::test]
+async fn test_chunking_disabled() {
+    let chunking = ChunkingConfig {
+        enabled: false,
+        ..small_chunks()
+    };
+    let (mock_server, _temp_dir, state) = setup(chunking).await;
+
+    let request = ProcessRequest { text: DOCUMENT.to_string(), ..Default::default() };
+    let response = process_text_handler(State(state), Json(request)).await.unwrap();
+
+    // The reply keeps the trailing newline of the text
+    assert_eq!(response.response, DOCUMENT.to_uppercase());
+    assert_eq!(mock_server.received_requests().await.unwrap().len(), 1);
+}
+
+/// A code block with a blank line stays in one chunk, so it is still masked
+#[tokio::test]
+async fn test_code_block_larger_than_a_chunk_is_masked() {
+    let (mock_server, _temp_dir, state) = setup(small_chunks()).await;
+
+    let code = "```\nlet first_value = 1;\n\nlet second_value = 2;\n```";
+    let text = format!("See the code:\n\n{}\n\nThanks!", code);
+    let request = ProcessRequest { text, ..Default::default() };
+    let response = process_text_handler(State(state), Json(request)).await.unwrap();
+
+    assert_eq!(response.response, format!("SEE THE CODE:\n\n{}\n\nTHANKS!", code));
+
+    let requests = mock_server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 3);
+    for chunk in common::prompts(&requests) {
+        assert!(!chunk.contains("```") && !chunk.contains("value"), "code sent unmasked: {:?}", chunk);
+    }
+}
@@ -0,0 +1,84 @@
+//! Fixtures shared by the integration tests
+//!
+//! Each test file is its own crate and uses only some of these.
+#![allow(dead_code)]
+
+use std::sync::Arc;
+use tempfile::TempDir;
+use reqwest::Client;
+use wiremock::{MockServer, Mock, ResponseTemplate};
+use wiremock::matchers::{method, path};
+use serde_json::{json, Value};
+
+use writer_ai_rust_service::config::AppConfig;
+use writer_ai_rust_service::cache::CacheManager;
+use writer_ai_rust_service::providers::ProviderKind;
+
+/// The state the handlers are called with
+pub type AppState = (Arc<AppConfig>, Arc<Client>, Arc<CacheManager>);
+
+/// Config for a mock backend; everything else keeps its default
+pub fn test_config(provider: ProviderKind, llm_url: String, model_name: &str) -> AppConfig {
+    AppConfig {
+        provider,
+        llm_url,
+        model_name: model_name.to_string(),
+        openai_api_key: Some("fake-api-key".to_string()),
+        ..Default::default()
+    }
+}
+
+/// `test_config` for an Ollama model served by `mock_server`
+pub fn ollama_config(mock_server: &MockServer) -> AppConfig {
+    test_config(ProviderKind::Ollama, format!("{}/api/chat", mock_server.uri()), "llama3")
+}
+
+/// Handler state for `config`, with its cache in `temp_dir`
+pub fn state(config: AppConfig, temp_dir: &TempDir) -> AppState {
+    state_with_client(config, Client::new(), temp_dir)
+}
+
+/// `state` with a client of its own, e.g. one that resolves made-up host names
+pub fn state_with_client(config: AppConfig, client: Client, temp_dir: &TempDir) -> AppState {
+    let cache_manager = CacheManager::new(temp_dir.path().join("cache.sled"), config.cache.clone()).unwrap();
+    (Arc::new(config), Arc::new(client), Arc::new(cache_manager))
+}
+
+/// A non-streaming Ollama `/api/chat` reply
+pub fn ollama_reply(content: &str) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({
+        "message": { "role": "assistant", "content": content },
+        "done": true
+    }))
+}
+
+/// A Chat Completions reply with one choice per content
+pub fn chat_reply(contents: &[&str]) -> ResponseTemplate {
+    let choices: Vec<Value> = contents
+        .iter()
+        .enumerate()
+        .map(|(index, content)| json!({ "index": index, "message": { "role": "assistant", "content": content } }))
+        .collect();
+    ResponseTemplate::new(200).set_body_json(json!({ "choices": choices }))
+}
+
+/// Answer exactly one Ollama request with `content`
+pub async fn mount_ollama(mock_server: &MockServer, content: &str) {
+    Mock::given(method("POST"))
+        .and(path("/api/chat"))
+        .respond_with(ollama_reply(content))
+        .expect(1)
+        .mount(mock_server)
+        .await;
+}
+
+/// The last message of each request's `messages`, i.e. the prompt
+pub fn prompts(requests: &[wiremock::Request]) -> Vec<String> {
+    requests
+        .iter()
+        .map(|request| {
+            let body: Value = serde_json::from_slice(&request.body).unwrap();
+            body["messages"].as_array().unwrap().last().unwrap()["content"].as_str().unwrap().to_string()
+        })
+        .collect()
+}
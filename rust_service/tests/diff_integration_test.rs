@@ -0,0 +1,124 @@
+mod common;
+
+use tempfile::TempDir;
+use axum::extract::State;
+use axum::Json;
+use wiremock::{MockServer, Mock};
+use wiremock::matchers::{method, path};
+
+use writer_ai_rust_service::diff::EditKind;
+use writer_ai_rust_service::errors::AppError;
+use writer_ai_rust_service::http::{apply_handler, process_text_handler, ApplyRequest, ProcessRequest};
+
+async fn setup() -> (MockServer, TempDir, common::AppState) {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/chat"))
+        .respond_with(common::ollama_reply("The weather here changes a lot. I'm not used to it."))
+        .mount(&mock_server)
+        .await;
+
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = common::ollama_config(&mock_server);
+    config.cache.enabled = false;
+    let state = common::state(config, &temp_dir);
+    (mock_server, temp_dir, state)
+}
+
+/// `include_diff` adds word-level spans with offsets into the original text
+#[tokio::test]
+async fn test_include_diff() {
+    let (_mock_server, _temp_dir, state) = setup().await;
+    let original = "Weather here change alot. I not used it.";
+
+    let request = ProcessRequest { text: original.to_string(), include_diff: true, ..Default::default() };
+    let response = process_text_handler(State(state), Json(request)).await.unwrap();
+
+    let diff = response.diff.as_ref().unwrap();
+    let spans: Vec<_> = diff.iter().map(|s| (s.kind, s.original.as_str(), s.revised.as_str())).collect();
+    assert_eq!(
+        spans,
+        vec![
+            (EditKind::Replace, "Weather", "The weather"),
+            (EditKind::Replace, "change alot", "changes a lot"),
+            (EditKind::Insert, "", "'m"),
+            (EditKind::Insert, "", " to"),
+        ]
+    );
+
+    // Offsets index characters of the request text
+    let chars: Vec<char> = original.chars().collect();
+    for span in diff {
+        let text: String = chars[span.original_start..span.original_end].iter().collect();
+        assert_eq!(text, span.original);
+    }
+}
+
+/// Without the flag the response has no `diff` field at all
+#[tokio::test]
+async fn test_diff_is_opt_in() {
+    let (_mock_server, _temp_dir, state) = setup().await;
+
+    let request = ProcessRequest { text: "Weather here change alot.".to_string(), ..Default::default() };
+    let response = process_text_handler(State(state), Json(request)).await.unwrap();
+
+    assert!(response.diff.is_none());
+    let body = serde_json::to_value(&response.0).unwrap();
+    assert!(body.get("diff").is_none());
+}
+
+/// Accepting some changes of a `/process` diff merges only those
+#[tokio::test]
+async fn test_apply_accepted_changes() {
+    let (_mock_server, _temp_dir, state) = setup().await;
+    let original = "Weather here change alot. I not used it.";
+
+    let request = ProcessRequest { text: original.to_string(), include_diff: true, ..Default::default() };
+    let response = process_text_handler(State(state), Json(request)).await.unwrap();
+
+    let apply = ApplyRequest {
+        original: original.to_string(),
+        revised: response.response.clone(),
+        original_hash: response.original_hash.clone().unwrap(),
+        accepted: vec![1, 3],
+    };
+    let merged = apply_handler(Json(apply.clone())).await.unwrap();
+    assert_eq!(merged.text, "Weather here changes a lot. I not used to it.");
+
+    // Deterministic: the same change set always gives the same text
+    let again = apply_handler(Json(apply)).await.unwrap();
+    assert_eq!(again.text, merged.text);
+}
+
+/// A change set computed for a different original is rejected
+#[tokio::test]
+async fn test_apply_rejects_stale_changes() {
+    let original = "Weather here change alot.";
+    let apply = ApplyRequest {
+        original: original.to_string(),
+        revised: "The weather here changes a lot.".to_string(),
+        original_hash: writer_ai_rust_service::diff::text_hash("Weather here changed alot."),
+        accepted: vec![0],
+    };
+
+    match apply_handler(Json(apply)).await {
+        Err(AppError::Conflict(msg)) => assert!(msg.contains("has changed")),
+        other => panic!("Expected Conflict error, got: {:?}", other.map(|r| r.0)),
+    }
+}
+
+#[tokio::test]
+async fn test_apply_rejects_unknown_change() {
+    let original = "Weather here change alot.";
+    let apply = ApplyRequest {
+        original: original.to_string(),
+        revised: "The weather here changes a lot.".to_string(),
+        original_hash: writer_ai_rust_service::diff::text_hash(original),
+        accepted: vec![0, 5],
+    };
+
+    match apply_handler(Json(apply)).await {
+        Err(AppError::InvalidRequest(msg)) => assert!(msg.contains("Change 5 does not exist")),
+        other => panic!("Expected InvalidRequest error, got: {:?}", other.map(|r| r.0)),
+    }
+}
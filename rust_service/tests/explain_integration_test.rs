@@ -0,0 +1,130 @@
+mod common;
+
+use tempfile::TempDir;
+use axum::extract::State;
+use axum::Json;
+use wiremock::{MockServer, Mock};
+use wiremock::matchers::{body_partial_json, method, path};
+use serde_json::json;
+
+use writer_ai_rust_service::errors::AppError;
+use writer_ai_rust_service::explain::ChangeCategory;
+use writer_ai_rust_service::http::{process_stream_handler, process_text_handler, ProcessRequest};
+
+/// The model is asked for JSON and its list of changes is returned as is
+#[tokio::test]
+async fn test_explain_returns_changes() {
+    let mock_server = MockServer::start().await;
+    let temp_dir = TempDir::new().unwrap();
+
+    let reply = json!({
+        "corrected": "How are you?",
+        "changes": [
+            { "original": "Howe ar", "corrected": "How are", "category": "spelling", "explanation": "Both words were misspelled." }
+        ]
+    });
+    Mock::given(method("POST"))
+        .and(path("/api/chat"))
+        .and(body_partial_json(json!({ "format": "json" })))
+        .respond_with(common::ollama_reply(&reply.to_string()))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let state = common::state(common::ollama_config(&mock_server), &temp_dir);
+    let request = ProcessRequest { text: "Howe ar you?".to_string(), explain: true, ..Default::default() };
+
+    let response = process_text_handler(State(state.clone()), Json(request.clone())).await.unwrap();
+    assert_eq!(response.response, "How are you?");
+    let changes = response.changes.as_deref().unwrap();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].original, "Howe ar");
+    assert_eq!(changes[0].category, ChangeCategory::Spelling);
+
+    // The explained answer is cached and parsed again on a hit
+    let cached = process_text_handler(State(state), Json(request)).await.unwrap();
+    assert_eq!(cached.changes, response.changes);
+}
+
+/// Outside explain mode a JSON reply is restored as text, keeping its layout and key order
+#[tokio::test]
+async fn test_json_reply_kept_without_explain() {
+    let mock_server = MockServer::start().await;
+    let temp_dir = TempDir::new().unwrap();
+
+    Mock::given(method("POST"))
+        .and(path("/api/chat"))
+        .respond_with(common::ollama_reply("{\n  \"title\": \"Fixes [[KEEP_0]]\",\n  \"id\": 3\n}"))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let state = common::state(common::ollama_config(&mock_server), &temp_dir);
+    let request = ProcessRequest { text: "{\n  \"title\": \"fix PROJ-12\",\n  \"id\": 3\n}".to_string(), ..Default::default() };
+
+    let response = process_text_handler(State(state), Json(request)).await.unwrap();
+    assert_eq!(response.response, "{\n  \"title\": \"Fixes PROJ-12\",\n  \"id\": 3\n}");
+}
+
+/// A plain-text answer still gives the corrected text, just without changes
+#[tokio::test]
+async fn test_explain_falls_back_to_plain_text() {
+    let mock_server = MockServer::start().await;
+    let temp_dir = TempDir::new().unwrap();
+
+    Mock::given(method("POST"))
+        .and(path("/api/chat"))
+        .respond_with(common::ollama_reply("How are you?"))
+        .expect(2)
+        .mount(&mock_server)
+        .await;
+
+    let state = common::state(common::ollama_config(&mock_server), &temp_dir);
+
+    let request = ProcessRequest { text: "Howe ar you?".to_string(), explain: true, ..Default::default() };
+    let response = process_text_handler(State(state.clone()), Json(request)).await.unwrap();
+    assert_eq!(response.response, "How are you?");
+    assert!(response.changes.is_none());
+    let body = serde_json::to_value(&response.0).unwrap();
+    assert!(body.get("changes").is_none());
+
+    // A plain request for the same text does not reuse the explained entry
+    let request = ProcessRequest { text: "Howe ar you?".to_string(), ..Default::default() };
+    let response = process_text_handler(State(state), Json(request)).await.unwrap();
+    assert!(response.changes.is_none());
+}
+
+/// The corrected text keeps the outer whitespace of the selection
+#[tokio::test]
+async fn test_explain_preserves_whitespace() {
+    let mock_server = MockServer::start().await;
+    let temp_dir = TempDir::new().unwrap();
+
+    let reply = json!({ "corrected": "How are you?", "changes": [] });
+    Mock::given(method("POST"))
+        .and(path("/api/chat"))
+        .respond_with(common::ollama_reply(&reply.to_string()))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let state = common::state(common::ollama_config(&mock_server), &temp_dir);
+    let request = ProcessRequest { text: "  Howe ar you?\n".to_string(), explain: true, ..Default::default() };
+
+    let response = process_text_handler(State(state), Json(request)).await.unwrap();
+    assert_eq!(response.response, "  How are you?\n");
+}
+
+#[tokio::test]
+async fn test_explain_not_supported_for_streaming() {
+    let mock_server = MockServer::start().await;
+    let temp_dir = TempDir::new().unwrap();
+    let state = common::state(common::ollama_config(&mock_server), &temp_dir);
+
+    let request = ProcessRequest { text: "Howe ar you?".to_string(), explain: true, ..Default::default() };
+    match process_stream_handler(State(state), Json(request)).await {
+        Err(AppError::InvalidRequest(msg)) => assert!(msg.contains("explain")),
+        Err(e) => panic!("Expected InvalidRequest error, got: {:?}", e),
+        Ok(_) => panic!("Expected InvalidRequest error, got a stream"),
+    }
+}
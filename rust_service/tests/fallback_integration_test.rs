@@ -0,0 +1,132 @@
+mod common;
+
+use tempfile::TempDir;
+use axum::extract::State;
+use axum::Json;
+use wiremock::{MockServer, Mock, ResponseTemplate};
+use wiremock::matchers::{method, path};
+
+use writer_ai_rust_service::config::{AppConfig, FallbackConfig};
+use writer_ai_rust_service::errors::AppError;
+use writer_ai_rust_service::http::{cache_fingerprint, process_text_handler, ProcessRequest};
+use writer_ai_rust_service::providers::ProviderKind;
+use writer_ai_rust_service::template::TemplateVars;
+
+/// OpenAI as the primary backend with a local Ollama as fallback
+fn test_config(primary_url: String, fallback_url: String) -> AppConfig {
+    let mut config = common::test_config(ProviderKind::OpenAi, primary_url, "gpt-4o");
+    config.fallbacks = vec![FallbackConfig {
+        provider: ProviderKind::Ollama,
+        llm_url: fallback_url,
+        model_name: "mistral:latest".to_string(),
+        llm_params: None,
+        llm_api_key: None,
+    }];
+    config.retry.max_attempts = 1;
+    config
+}
+
+/// A 5xx from the primary backend falls through to the next one
+#[tokio::test]
+async fn test_fallback_on_server_error() {
+    let mock_server = MockServer::start().await;
+    let temp_dir = TempDir::new().unwrap();
+
+    Mock::given(method("POST"))
+        .and(path("/v1/responses"))
+        .respond_with(ResponseTemplate::new(503).set_body_string("Service Unavailable"))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    common::mount_ollama(&mock_server, "Answer from Ollama").await;
+
+    let config = test_config(
+        format!("{}/v1/responses", mock_server.uri()),
+        format!("{}/api/chat", mock_server.uri()),
+    );
+    let state = common::state(config, &temp_dir);
+    let request = ProcessRequest { text: "Fallback test".to_string(), ..Default::default() };
+
+    let response = process_text_handler(State(state.clone()), Json(request.clone())).await.unwrap();
+    assert_eq!(response.response, "Answer from Ollama");
+    assert_eq!(response.provider, ProviderKind::Ollama);
+    assert_eq!(response.model, "mistral:latest");
+
+    // Cached under the backend that actually answered
+    let backends = state.0.backends();
+    let vars = TemplateVars::new();
+    assert_eq!(
+        state.2.lookup("Fallback test", "mistral:latest", &cache_fingerprint(&backends[1], None, &vars, 1)).unwrap().as_deref(),
+        Some("Answer from Ollama")
+    );
+    assert!(state.2.lookup("Fallback test", "gpt-4o", &cache_fingerprint(&backends[0], None, &vars, 1)).unwrap().is_none());
+}
+
+/// An unreachable primary backend (e.g. when offline) falls through as well
+#[tokio::test]
+async fn test_fallback_on_connection_error() {
+    let mock_server = MockServer::start().await;
+    let temp_dir = TempDir::new().unwrap();
+    common::mount_ollama(&mock_server, "Offline answer").await;
+
+    // Nothing listens on this port
+    let config = test_config(
+        "http://127.0.0.1:9/v1/responses".to_string(),
+        format!("{}/api/chat", mock_server.uri()),
+    );
+    let state = common::state(config, &temp_dir);
+
+    let response = process_text_handler(State(state), Json(ProcessRequest { text: "Offline".to_string(), ..Default::default() }))
+        .await
+        .unwrap();
+    assert_eq!(response.response, "Offline answer");
+    assert_eq!(response.provider, ProviderKind::Ollama);
+}
+
+/// Client errors such as a rejected API key are not hidden by the fallback
+#[tokio::test]
+async fn test_no_fallback_on_client_error() {
+    let mock_server = MockServer::start().await;
+    let temp_dir = TempDir::new().unwrap();
+
+    Mock::given(method("POST"))
+        .and(path("/v1/responses"))
+        .respond_with(ResponseTemplate::new(401).set_body_string("Invalid API key"))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/api/chat"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&mock_server)
+        .await;
+
+    let config = test_config(
+        format!("{}/v1/responses", mock_server.uri()),
+        format!("{}/api/chat", mock_server.uri()),
+    );
+    let state = common::state(config, &temp_dir);
+
+    match process_text_handler(State(state), Json(ProcessRequest { text: "Bad key".to_string(), ..Default::default() })).await {
+        Err(AppError::LlmApiError(msg)) => assert!(msg.contains("Status 401")),
+        other => panic!("Expected LlmApiError, got: {:?}", other.map(|r| r.0)),
+    }
+}
+
+/// When the last backend is unavailable too, its error is returned
+#[tokio::test]
+async fn test_all_backends_unavailable() {
+    let temp_dir = TempDir::new().unwrap();
+
+    let config = test_config(
+        "http://127.0.0.1:9/v1/responses".to_string(),
+        "http://127.0.0.1:9/api/chat".to_string(),
+    );
+    let state = common::state(config, &temp_dir);
+
+    match process_text_handler(State(state), Json(ProcessRequest { text: "Nobody home".to_string(), ..Default::default() })).await {
+        Err(AppError::LlmUnavailable(msg)) => assert!(msg.contains("Ollama API request failed")),
+        other => panic!("Expected LlmUnavailable, got: {:?}", other.map(|r| r.0)),
+    }
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use tempfile::TempDir;
+use axum::extract::State;
+use axum::Json;
+use wiremock::MockServer;
+
+use writer_ai_rust_service::config::AppConfig;
+use writer_ai_rust_service::glossary::{GlossaryConfig, GlossaryTerm, ViolationKind};
+use writer_ai_rust_service::http::{process_text_handler, ProcessRequest, ProcessResponse};
+
+/// An Ollama backend answering `content`, with "Prod" in the glossary
+async fn setup(content: &str, auto_correct: bool) -> (MockServer, AppConfig) {
+    let mock_server = MockServer::start().await;
+    common::mount_ollama(&mock_server, content).await;
+
+    let mut config = common::ollama_config(&mock_server);
+    config.glossary = GlossaryConfig {
+        path: None,
+        auto_correct,
+        terms: vec![GlossaryTerm {
+            term: "Prod".to_string(),
+            variants: vec!["prod".to_string()],
+            forbidden: vec!["production".to_string()],
+        }],
+    };
+    config.cache.enabled = false;
+    (mock_server, config)
+}
+
+async fn process(config: AppConfig, text: &str) -> ProcessResponse {
+    let temp_dir = TempDir::new().unwrap();
+    let request = ProcessRequest { text: text.to_string(), ..Default::default() };
+    process_text_handler(State(common::state(config, &temp_dir)), Json(request)).await.unwrap().0
+}
+
+/// The relevant terms are sent with the prompt and violations are reported
+#[tokio::test]
+async fn test_glossary_violations_are_reported() {
+    let (mock_server, config) = setup("Deploy the fix to production tomorrow.", false).await;
+
+    let response = process(config, "deploy fix to Prod tomorow").await;
+    assert_eq!(response.response, "Deploy the fix to production tomorrow.");
+    let violations = response.glossary_violations.unwrap();
+    assert_eq!(violations.len(), 1);
+    assert_eq!(violations[0].kind, ViolationKind::Forbidden);
+    assert_eq!(violations[0].found.as_deref(), Some("production"));
+    assert!(!violations[0].corrected);
+
+    let requests = mock_server.received_requests().await.unwrap();
+    assert!(common::prompts(&requests)[0].ends_with("Follow this glossary:\n- \"Prod\": keep exactly as written, never \"prod\" or \"production\""));
+}
+
+#[tokio::test]
+async fn test_glossary_auto_correct() {
+    let (_mock_server, config) = setup("Deploy the fix to production tomorrow.", true).await;
+
+    let response = process(config, "deploy fix to Prod tomorow").await;
+    assert_eq!(response.response, "Deploy the fix to Prod tomorrow.");
+    assert!(response.glossary_violations.unwrap()[0].corrected);
+}
+
+/// A response that follows the glossary has no `glossary_violations` field
+#[tokio::test]
+async fn test_glossary_no_violations() {
+    let (_mock_server, config) = setup("Deploy the fix to Prod tomorrow.", false).await;
+
+    let response = process(config, "deploy fix to Prod tomorow").await;
+    let body = serde_json::to_value(&response).unwrap();
+    assert!(body.get("glossary_violations").is_none());
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use tempfile::TempDir;
+use axum::extract::State;
+use axum::Json;
+use wiremock::{MockServer, Mock};
+use wiremock::matchers::method;
+use serde_json::json;
+
+use writer_ai_rust_service::http::{process_text_handler, ProcessRequest};
+use writer_ai_rust_service::language::LanguageMismatch;
+use writer_ai_rust_service::providers::ProviderKind;
+
+const UKRAINIAN: &str = "ми випустимо реліз у пʼятницю після остаточної перевірки документації";
+const UKRAINIAN_FIXED: &str = "Ми випустимо реліз у пʼятницю після остаточної перевірки документації.";
+const ENGLISH: &str = "We will ship the release on Friday after the final review of the documentation.";
+
+fn state(mock_server: &MockServer, temp_dir: &TempDir) -> common::AppState {
+    let llm_url = format!("{}/v1/chat/completions", mock_server.uri());
+    let mut config = common::test_config(ProviderKind::OpenAiCompatible, llm_url, "gpt-4o-mini");
+    config.prompt_template = Some("Fix the grammar of this {{input_language}} text:\n\n{{input}}".to_string());
+    config.cache.enabled = false;
+    common::state(config, temp_dir)
+}
+
+/// A translated response is requested again with a stricter instruction
+#[tokio::test]
+async fn test_translated_response_is_retried() {
+    let mock_server = MockServer::start().await;
+    let temp_dir = TempDir::new().unwrap();
+
+    Mock::given(method("POST"))
+        .respond_with(common::chat_reply(&[ENGLISH]))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("POST"))
+        .respond_with(common::chat_reply(&[UKRAINIAN_FIXED]))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let request = ProcessRequest { text: UKRAINIAN.to_string(), ..Default::default() };
+    let response = process_text_handler(State(state(&mock_server, &temp_dir)), Json(request)).await.unwrap();
+    assert_eq!(response.response, UKRAINIAN_FIXED);
+    assert!(response.language_mismatch.is_none());
+
+    let prompts = common::prompts(&mock_server.received_requests().await.unwrap());
+    assert!(prompts[0].starts_with("Fix the grammar of this Ukrainian text:"));
+    assert!(!prompts[0].contains("Answer in Ukrainian only"));
+    assert!(prompts[1].contains("Answer in Ukrainian only"));
+}
+
+/// A model that keeps translating is reported, not failed
+#[tokio::test]
+async fn test_persistent_mismatch_is_flagged() {
+    let mock_server = MockServer::start().await;
+    let temp_dir = TempDir::new().unwrap();
+
+    Mock::given(method("POST"))
+        .respond_with(common::chat_reply(&[ENGLISH]))
+        .expect(2)
+        .mount(&mock_server)
+        .await;
+
+    let request = ProcessRequest { text: UKRAINIAN.to_string(), ..Default::default() };
+    let response = process_text_handler(State(state(&mock_server, &temp_dir)), Json(request)).await.unwrap();
+    assert_eq!(response.response, ENGLISH);
+    assert_eq!(
+        response.language_mismatch,
+        Some(LanguageMismatch { expected: "Ukrainian".to_string(), detected: "English".to_string() })
+    );
+}
+
+/// In explain mode only the corrected text is checked, not the English explanations
+#[tokio::test]
+async fn test_explain_mode_checks_corrected_text_only() {
+    let mock_server = MockServer::start().await;
+    let temp_dir = TempDir::new().unwrap();
+
+    let reply = json!({
+        "corrected": UKRAINIAN_FIXED,
+        "changes": [
+            {
+                "original": "ми",
+                "corrected": "Ми",
+                "category": "grammar",
+                "explanation": "A sentence always starts with a capital letter, so the first word needs one too."
+            },
+            {
+                "original": "документації",
+                "corrected": "документації.",
+                "category": "grammar",
+                "explanation": "A full stop is added at the end because the sentence is complete."
+            }
+        ]
+    });
+    Mock::given(method("POST"))
+        .respond_with(common::chat_reply(&[&reply.to_string()]))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let request = ProcessRequest { text: UKRAINIAN.to_string(), explain: true, ..Default::default() };
+    let response = process_text_handler(State(state(&mock_server, &temp_dir)), Json(request)).await.unwrap();
+    assert_eq!(response.response, UKRAINIAN_FIXED);
+    assert_eq!(response.changes.as_ref().map(Vec::len), Some(2));
+    assert!(response.language_mismatch.is_none());
+
+    let prompts = common::prompts(&mock_server.received_requests().await.unwrap());
+    assert!(!prompts[0].contains("Ukrainian only"));
+}
@@ -89,12 +89,12 @@ mod llm_test_data {
         let mut dp = vec![vec![0; n+1]; m+1];
         
         // Initialize first row and column
-        for i in 0..=m {
-            dp[i][0] = i;
+        for (i, row) in dp.iter_mut().enumerate() {
+            row[0] = i;
         }
         
-        for j in 0..=n {
-            dp[0][j] = j;
+        for (j, cell) in dp[0].iter_mut().enumerate() {
+            *cell = j;
         }
         
         // Fill the matrix
@@ -875,12 +875,15 @@ async fn test_llm_responses() {
                 enabled: false, // Disable cache for integration tests
                 ttl_days: 30,
                 max_size_mb: 100,
+                invalidate_changed_params: false,
+                normalize: Vec::new(),
             };
             let cache_manager = Arc::new(CacheManager::new(cache_path, cache_config).unwrap());
             
             let app_state = (config.clone(), client, cache_manager);
             let request = ProcessRequest {
                 text: test_sentence.text.clone(),
+                ..Default::default()
             };
 
             // Process the request
@@ -1012,12 +1015,15 @@ async fn test_llm_responses() {
                 enabled: false, // Disable cache for integration tests
                 ttl_days: 30,
                 max_size_mb: 100,
+                invalidate_changed_params: false,
+                normalize: Vec::new(),
             };
             let cache_manager = Arc::new(CacheManager::new(cache_path, cache_config).unwrap());
             
             let app_state = (config.clone(), client, cache_manager);
             let request = ProcessRequest {
                 text: test_sentence.text.clone(),
+                ..Default::default()
             };
 
             // Process the request
@@ -1132,7 +1138,7 @@ async fn test_llm_responses() {
             
             // Stop the Ollama model after testing to free resources
             if model_name.contains("ollama") {
-                let model_short_name = model_name.split('/').last().unwrap_or(model_name);
+                let model_short_name = model_name.split('/').next_back().unwrap_or(model_name);
                 println!("  Stopping Ollama model: {}", model_short_name);
                 
                 // Run the command to stop the model
@@ -0,0 +1,131 @@
+mod common;
+
+use std::collections::BTreeMap;
+use tempfile::TempDir;
+use axum::extract::State;
+use axum::Json;
+use wiremock::{MockServer, Mock};
+use wiremock::matchers::{body_string_contains, method, path};
+use serde_json::json;
+
+use writer_ai_rust_service::config::{AppConfig, ModeConfig};
+use writer_ai_rust_service::errors::AppError;
+use writer_ai_rust_service::http::{list_modes_handler, process_text_handler, ProcessRequest};
+use writer_ai_rust_service::providers::ProviderKind;
+
+fn test_config(llm_url: String) -> AppConfig {
+    let mut modes = BTreeMap::new();
+    modes.insert(
+        "formal".to_string(),
+        ModeConfig {
+            description: Some("Make the text formal".to_string()),
+            prompt_template: Some("Rewrite formally: {{input}}".to_string()),
+            system_prompt: Some("You are a formal editor.".to_string()),
+            model_name: Some("mistral-large:latest".to_string()),
+            llm_params: None,
+            preserve_language: None,
+        },
+    );
+    modes.insert(
+        "shorter".to_string(),
+        ModeConfig {
+            prompt_template: Some("Shorten: {{input}}".to_string()),
+            ..Default::default()
+        },
+    );
+
+    AppConfig {
+        prompt_template: Some("Fix grammar: {{input}}".to_string()),
+        modes,
+        ..common::test_config(ProviderKind::Ollama, llm_url, "mistral:latest")
+    }
+}
+
+async fn mount_reply(mock_server: &MockServer, prompt: &str, reply: &str) {
+    Mock::given(method("POST"))
+        .and(path("/api/chat"))
+        .and(body_string_contains(prompt))
+        .respond_with(common::ollama_reply(reply))
+        .expect(1)
+        .mount(mock_server)
+        .await;
+}
+
+/// Each mode uses its own template and model, and is cached separately
+#[tokio::test]
+async fn test_modes_select_template_and_cache_separately() {
+    let mock_server = MockServer::start().await;
+    let temp_dir = TempDir::new().unwrap();
+
+    mount_reply(&mock_server, "Fix grammar: hey whats up", "Hey, what's up?").await;
+    mount_reply(&mock_server, "Shorten: hey whats up", "Hi.").await;
+    Mock::given(method("POST"))
+        .and(path("/api/chat"))
+        .and(body_string_contains("Rewrite formally: hey whats up"))
+        .and(body_string_contains("You are a formal editor."))
+        .and(body_string_contains("mistral-large:latest"))
+        .respond_with(common::ollama_reply("Good day. How are you?"))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let config = test_config(format!("{}/api/chat", mock_server.uri()));
+    let state = common::state(config, &temp_dir);
+
+    let request = |mode: Option<&str>| ProcessRequest {
+        text: "hey whats up".to_string(),
+        mode: mode.map(str::to_string),
+        ..Default::default()
+    };
+
+    // Every request is made twice; the mocks only allow one call each, so the
+    // second one must come from a cache entry specific to the mode
+    for _ in 0..2 {
+        let default = process_text_handler(State(state.clone()), Json(request(None))).await.unwrap();
+        assert_eq!(default.response, "Hey, what's up?");
+
+        let shorter = process_text_handler(State(state.clone()), Json(request(Some("shorter")))).await.unwrap();
+        assert_eq!(shorter.response, "Hi.");
+        assert_eq!(shorter.model, "mistral:latest");
+
+        let formal = process_text_handler(State(state.clone()), Json(request(Some("formal")))).await.unwrap();
+        assert_eq!(formal.response, "Good day. How are you?");
+        assert_eq!(formal.model, "mistral-large:latest");
+    }
+}
+
+#[tokio::test]
+async fn test_unknown_mode_is_rejected() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = test_config("http://127.0.0.1:9/api/chat".to_string());
+    let state = common::state(config, &temp_dir);
+
+    let request = ProcessRequest {
+        text: "hello".to_string(),
+        mode: Some("poetic".to_string()),
+        ..Default::default()
+    };
+    match process_text_handler(State(state), Json(request)).await {
+        Err(AppError::InvalidRequest(msg)) => assert!(msg.contains("Unknown mode 'poetic'")),
+        other => panic!("Expected InvalidRequest, got: {:?}", other.map(|r| r.0)),
+    }
+}
+
+#[tokio::test]
+async fn test_list_modes() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = test_config("http://127.0.0.1:9/api/chat".to_string());
+    let state = common::state(config, &temp_dir);
+
+    let Json(listing) = list_modes_handler(State(state)).await;
+
+    assert_eq!(
+        serde_json::to_value(listing).unwrap(),
+        json!({
+            "modes": [
+                { "name": "formal", "description": "Make the text formal", "model": "mistral-large:latest" },
+                { "name": "shorter", "description": null, "model": "mistral:latest" }
+            ]
+        })
+    );
+}